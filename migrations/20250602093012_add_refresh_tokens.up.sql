@@ -0,0 +1,14 @@
+CREATE TABLE refresh_tokens (
+	id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+	family_id UUID NOT NULL,
+	admin_email VARCHAR(255) NOT NULL REFERENCES admins(email) ON DELETE CASCADE,
+	token_hash VARCHAR(64) NOT NULL UNIQUE,
+	expires_at TIMESTAMPTZ NOT NULL,
+	rotated_at TIMESTAMPTZ,
+	revoked_at TIMESTAMPTZ,
+
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX refresh_tokens_family_id_idx ON refresh_tokens (family_id);
+CREATE INDEX refresh_tokens_admin_email_idx ON refresh_tokens (admin_email);
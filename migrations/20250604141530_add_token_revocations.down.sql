@@ -0,0 +1,2 @@
+DROP TABLE admin_token_revocations;
+DROP TABLE revoked_tokens;
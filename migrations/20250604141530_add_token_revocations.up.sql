@@ -0,0 +1,15 @@
+CREATE TABLE revoked_tokens (
+	jti VARCHAR(64) PRIMARY KEY,
+	admin_email VARCHAR(255) NOT NULL,
+	expires_at TIMESTAMPTZ NOT NULL,
+
+	revoked_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX revoked_tokens_expires_at_idx ON revoked_tokens (expires_at);
+
+-- tokens for the admin issued before `revoked_before` are rejected
+CREATE TABLE admin_token_revocations (
+	admin_email VARCHAR(255) PRIMARY KEY,
+	revoked_before TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,51 @@
+CREATE TABLE roles (
+	name VARCHAR(32) PRIMARY KEY
+);
+
+CREATE TABLE permissions (
+	name VARCHAR(64) PRIMARY KEY
+);
+
+CREATE TABLE role_permissions (
+	role VARCHAR(32) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+	permission VARCHAR(64) NOT NULL REFERENCES permissions(name) ON DELETE CASCADE,
+
+	PRIMARY KEY (role, permission)
+);
+
+CREATE TABLE admin_roles (
+	admin_email VARCHAR(255) NOT NULL REFERENCES admins(email) ON DELETE CASCADE,
+	role VARCHAR(32) NOT NULL REFERENCES roles(name) ON DELETE CASCADE,
+
+	PRIMARY KEY (admin_email, role)
+);
+
+INSERT INTO roles (name) VALUES
+	('owner'),
+	('manager'),
+	('cashier'),
+	('waiter');
+
+INSERT INTO permissions (name) VALUES
+	('admin:create'),
+	('admin:delete'),
+	('admin:roles'),
+	('keys:rotate'),
+	('table_session:manage'),
+	('checkout:manage');
+
+INSERT INTO role_permissions (role, permission) VALUES
+	('owner', 'admin:create'),
+	('owner', 'admin:delete'),
+	('owner', 'admin:roles'),
+	('owner', 'keys:rotate'),
+	('owner', 'table_session:manage'),
+	('owner', 'checkout:manage'),
+	('manager', 'table_session:manage'),
+	('manager', 'checkout:manage'),
+	('cashier', 'checkout:manage'),
+	('waiter', 'table_session:manage');
+
+-- every existing admin could do everything before roles existed
+INSERT INTO admin_roles (admin_email, role)
+SELECT email, 'owner' FROM admins;
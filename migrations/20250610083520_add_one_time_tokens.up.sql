@@ -0,0 +1,13 @@
+-- single-use tokens sent by email, e.g. for password resets
+CREATE TABLE one_time_tokens (
+	id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+	admin_email VARCHAR(255) NOT NULL REFERENCES admins(email) ON DELETE CASCADE,
+	purpose VARCHAR(32) NOT NULL,
+	token_hash VARCHAR(64) NOT NULL UNIQUE,
+	expires_at TIMESTAMPTZ NOT NULL,
+	used_at TIMESTAMPTZ,
+
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX one_time_tokens_admin_email_idx ON one_time_tokens (admin_email, purpose);
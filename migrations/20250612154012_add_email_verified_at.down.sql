@@ -0,0 +1 @@
+ALTER TABLE admins DROP COLUMN email_verified_at;
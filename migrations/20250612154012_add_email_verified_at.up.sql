@@ -0,0 +1,4 @@
+ALTER TABLE admins ADD COLUMN email_verified_at TIMESTAMPTZ;
+
+-- existing admins keep working when verification is required
+UPDATE admins SET email_verified_at = NOW();
@@ -0,0 +1,21 @@
+-- TOTP secret of an admin, only enforced at login once confirmed
+CREATE TABLE admin_mfa (
+	admin_email VARCHAR(255) PRIMARY KEY REFERENCES admins(email) ON DELETE CASCADE,
+	secret VARCHAR(64) NOT NULL,
+	confirmed_at TIMESTAMPTZ,
+	-- time step of the last accepted code, so a code cannot be replayed
+	last_used_step BIGINT,
+
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TABLE mfa_recovery_codes (
+	id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+	admin_email VARCHAR(255) NOT NULL REFERENCES admins(email) ON DELETE CASCADE,
+	code_hash VARCHAR(64) NOT NULL,
+	used_at TIMESTAMPTZ,
+
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX mfa_recovery_codes_admin_email_idx ON mfa_recovery_codes (admin_email);
@@ -0,0 +1,10 @@
+-- failed logins per email and per client IP, shared by all replicas
+CREATE TABLE login_failures (
+	kind VARCHAR(16) NOT NULL,
+	subject VARCHAR(255) NOT NULL,
+	failures INT NOT NULL,
+	last_failure_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	locked_until TIMESTAMPTZ,
+
+	PRIMARY KEY (kind, subject)
+);
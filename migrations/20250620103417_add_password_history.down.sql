@@ -0,0 +1,2 @@
+DROP TABLE password_history;
+ALTER TABLE admins DROP COLUMN password_changed_at;
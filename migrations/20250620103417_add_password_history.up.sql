@@ -0,0 +1,13 @@
+-- existing passwords count as set now, so enabling expiry does not lock everyone out at once
+ALTER TABLE admins ADD COLUMN password_changed_at TIMESTAMPTZ NOT NULL DEFAULT NOW();
+
+-- previous password hashes, so they cannot be chosen again
+CREATE TABLE password_history (
+	id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+	admin_email VARCHAR(255) NOT NULL REFERENCES admins(email) ON DELETE CASCADE,
+	password VARCHAR(255) NOT NULL,
+
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX password_history_admin_email_idx ON password_history (admin_email, created_at);
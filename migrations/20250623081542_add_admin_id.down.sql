@@ -0,0 +1,63 @@
+-- back to the email as key, every reference is rebuilt from the id
+ALTER TABLE revoked_tokens RENAME COLUMN subject TO admin_email;
+UPDATE revoked_tokens t SET admin_email = a.email FROM admins a WHERE a.id::TEXT = t.admin_email;
+
+ALTER TABLE admin_token_revocations ADD COLUMN admin_email VARCHAR(255);
+UPDATE admin_token_revocations r SET admin_email = a.email FROM admins a WHERE a.id = r.admin_id;
+DELETE FROM admin_token_revocations WHERE admin_email IS NULL;
+ALTER TABLE admin_token_revocations DROP COLUMN admin_id;
+ALTER TABLE admin_token_revocations ADD PRIMARY KEY (admin_email);
+
+ALTER TABLE refresh_tokens ADD COLUMN admin_email VARCHAR(255);
+UPDATE refresh_tokens t SET admin_email = a.email FROM admins a WHERE a.id = t.admin_id;
+ALTER TABLE refresh_tokens DROP COLUMN admin_id;
+
+ALTER TABLE admin_roles ADD COLUMN admin_email VARCHAR(255);
+UPDATE admin_roles r SET admin_email = a.email FROM admins a WHERE a.id = r.admin_id;
+ALTER TABLE admin_roles DROP COLUMN admin_id;
+
+ALTER TABLE one_time_tokens RENAME COLUMN email TO admin_email;
+UPDATE one_time_tokens t SET admin_email = a.email FROM admins a WHERE a.id = t.admin_id;
+ALTER TABLE one_time_tokens DROP COLUMN admin_id;
+
+ALTER TABLE admin_mfa ADD COLUMN admin_email VARCHAR(255);
+UPDATE admin_mfa m SET admin_email = a.email FROM admins a WHERE a.id = m.admin_id;
+ALTER TABLE admin_mfa DROP COLUMN admin_id;
+
+ALTER TABLE mfa_recovery_codes ADD COLUMN admin_email VARCHAR(255);
+UPDATE mfa_recovery_codes c SET admin_email = a.email FROM admins a WHERE a.id = c.admin_id;
+ALTER TABLE mfa_recovery_codes DROP COLUMN admin_id;
+
+ALTER TABLE password_history ADD COLUMN admin_email VARCHAR(255);
+UPDATE password_history h SET admin_email = a.email FROM admins a WHERE a.id = h.admin_id;
+ALTER TABLE password_history DROP COLUMN admin_id;
+
+ALTER TABLE admins DROP CONSTRAINT admins_pkey;
+ALTER TABLE admins DROP CONSTRAINT admins_email_key;
+ALTER TABLE admins ADD CONSTRAINT users_pkey PRIMARY KEY (email);
+ALTER TABLE admins DROP COLUMN id;
+
+ALTER TABLE refresh_tokens ALTER COLUMN admin_email SET NOT NULL;
+ALTER TABLE refresh_tokens ADD FOREIGN KEY (admin_email) REFERENCES admins(email) ON DELETE CASCADE;
+CREATE INDEX refresh_tokens_admin_email_idx ON refresh_tokens (admin_email);
+
+ALTER TABLE admin_roles ALTER COLUMN admin_email SET NOT NULL;
+ALTER TABLE admin_roles ADD FOREIGN KEY (admin_email) REFERENCES admins(email) ON DELETE CASCADE;
+ALTER TABLE admin_roles ADD PRIMARY KEY (admin_email, role);
+
+-- tokens sent to an address other than the admin's current one cannot be kept
+DELETE FROM one_time_tokens WHERE admin_email NOT IN (SELECT email FROM admins);
+ALTER TABLE one_time_tokens ADD FOREIGN KEY (admin_email) REFERENCES admins(email) ON DELETE CASCADE;
+CREATE INDEX one_time_tokens_admin_email_idx ON one_time_tokens (admin_email, purpose);
+
+ALTER TABLE admin_mfa ALTER COLUMN admin_email SET NOT NULL;
+ALTER TABLE admin_mfa ADD FOREIGN KEY (admin_email) REFERENCES admins(email) ON DELETE CASCADE;
+ALTER TABLE admin_mfa ADD PRIMARY KEY (admin_email);
+
+ALTER TABLE mfa_recovery_codes ALTER COLUMN admin_email SET NOT NULL;
+ALTER TABLE mfa_recovery_codes ADD FOREIGN KEY (admin_email) REFERENCES admins(email) ON DELETE CASCADE;
+CREATE INDEX mfa_recovery_codes_admin_email_idx ON mfa_recovery_codes (admin_email);
+
+ALTER TABLE password_history ALTER COLUMN admin_email SET NOT NULL;
+ALTER TABLE password_history ADD FOREIGN KEY (admin_email) REFERENCES admins(email) ON DELETE CASCADE;
+CREATE INDEX password_history_admin_email_idx ON password_history (admin_email, created_at);
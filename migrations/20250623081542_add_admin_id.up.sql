@@ -0,0 +1,71 @@
+-- admins get a surrogate key, so their email can change
+ALTER TABLE admins ADD COLUMN id UUID NOT NULL DEFAULT gen_random_uuid();
+
+-- every table pointing at an admin switches from the email to the id
+ALTER TABLE refresh_tokens ADD COLUMN admin_id UUID;
+UPDATE refresh_tokens t SET admin_id = a.id FROM admins a WHERE a.email = t.admin_email;
+ALTER TABLE refresh_tokens ALTER COLUMN admin_id SET NOT NULL;
+ALTER TABLE refresh_tokens DROP COLUMN admin_email;
+
+ALTER TABLE admin_roles ADD COLUMN admin_id UUID;
+UPDATE admin_roles r SET admin_id = a.id FROM admins a WHERE a.email = r.admin_email;
+ALTER TABLE admin_roles ALTER COLUMN admin_id SET NOT NULL;
+ALTER TABLE admin_roles DROP COLUMN admin_email;
+
+-- one-time tokens keep the address they were sent to, so an email change is
+-- confirmed from the new address and a verification only counts for its own
+ALTER TABLE one_time_tokens ADD COLUMN admin_id UUID;
+UPDATE one_time_tokens t SET admin_id = a.id FROM admins a WHERE a.email = t.admin_email;
+ALTER TABLE one_time_tokens ALTER COLUMN admin_id SET NOT NULL;
+ALTER TABLE one_time_tokens DROP CONSTRAINT one_time_tokens_admin_email_fkey;
+DROP INDEX one_time_tokens_admin_email_idx;
+ALTER TABLE one_time_tokens RENAME COLUMN admin_email TO email;
+
+ALTER TABLE admin_mfa ADD COLUMN admin_id UUID;
+UPDATE admin_mfa m SET admin_id = a.id FROM admins a WHERE a.email = m.admin_email;
+ALTER TABLE admin_mfa ALTER COLUMN admin_id SET NOT NULL;
+ALTER TABLE admin_mfa DROP COLUMN admin_email;
+
+ALTER TABLE mfa_recovery_codes ADD COLUMN admin_id UUID;
+UPDATE mfa_recovery_codes c SET admin_id = a.id FROM admins a WHERE a.email = c.admin_email;
+ALTER TABLE mfa_recovery_codes ALTER COLUMN admin_id SET NOT NULL;
+ALTER TABLE mfa_recovery_codes DROP COLUMN admin_email;
+
+ALTER TABLE password_history ADD COLUMN admin_id UUID;
+UPDATE password_history h SET admin_id = a.id FROM admins a WHERE a.email = h.admin_email;
+ALTER TABLE password_history ALTER COLUMN admin_id SET NOT NULL;
+ALTER TABLE password_history DROP COLUMN admin_email;
+
+-- revocations match the token subject, which is now the id. Those of deleted
+-- admins are dropped, tokens with an email subject are rejected anyway.
+ALTER TABLE admin_token_revocations ADD COLUMN admin_id UUID;
+UPDATE admin_token_revocations r SET admin_id = a.id FROM admins a WHERE a.email = r.admin_email;
+DELETE FROM admin_token_revocations WHERE admin_id IS NULL;
+ALTER TABLE admin_token_revocations DROP COLUMN admin_email;
+ALTER TABLE admin_token_revocations ADD PRIMARY KEY (admin_id);
+
+ALTER TABLE revoked_tokens RENAME COLUMN admin_email TO subject;
+UPDATE revoked_tokens t SET subject = a.id::TEXT FROM admins a WHERE a.email = t.subject;
+
+-- nothing refers to the email any more
+ALTER TABLE admins DROP CONSTRAINT users_pkey;
+ALTER TABLE admins ADD PRIMARY KEY (id);
+ALTER TABLE admins ADD CONSTRAINT admins_email_key UNIQUE (email);
+
+ALTER TABLE refresh_tokens ADD FOREIGN KEY (admin_id) REFERENCES admins(id) ON DELETE CASCADE;
+CREATE INDEX refresh_tokens_admin_id_idx ON refresh_tokens (admin_id);
+
+ALTER TABLE admin_roles ADD FOREIGN KEY (admin_id) REFERENCES admins(id) ON DELETE CASCADE;
+ALTER TABLE admin_roles ADD PRIMARY KEY (admin_id, role);
+
+ALTER TABLE one_time_tokens ADD FOREIGN KEY (admin_id) REFERENCES admins(id) ON DELETE CASCADE;
+CREATE INDEX one_time_tokens_admin_id_idx ON one_time_tokens (admin_id, purpose);
+
+ALTER TABLE admin_mfa ADD FOREIGN KEY (admin_id) REFERENCES admins(id) ON DELETE CASCADE;
+ALTER TABLE admin_mfa ADD PRIMARY KEY (admin_id);
+
+ALTER TABLE mfa_recovery_codes ADD FOREIGN KEY (admin_id) REFERENCES admins(id) ON DELETE CASCADE;
+CREATE INDEX mfa_recovery_codes_admin_id_idx ON mfa_recovery_codes (admin_id);
+
+ALTER TABLE password_history ADD FOREIGN KEY (admin_id) REFERENCES admins(id) ON DELETE CASCADE;
+CREATE INDEX password_history_admin_id_idx ON password_history (admin_id, created_at);
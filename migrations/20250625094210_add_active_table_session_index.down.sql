@@ -0,0 +1 @@
+DROP INDEX table_sessions_active_table_id_idx;
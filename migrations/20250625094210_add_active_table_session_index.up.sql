@@ -0,0 +1,11 @@
+-- older duplicates are closed so the index can be built, the newest session of a table stays active
+UPDATE table_sessions ts
+SET is_active = FALSE
+WHERE is_active AND EXISTS (
+	SELECT 1 FROM table_sessions newer
+	WHERE newer.table_id = ts.table_id
+		AND newer.is_active
+		AND (newer.created_at, newer.id) > (ts.created_at, ts.id)
+);
+
+CREATE UNIQUE INDEX table_sessions_active_table_id_idx ON table_sessions (table_id) WHERE is_active;
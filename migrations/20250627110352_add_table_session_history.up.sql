@@ -0,0 +1,13 @@
+-- every change of a session's active flag, starting with its creation
+CREATE TABLE table_session_history (
+	id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+	table_session_id UUID NOT NULL REFERENCES table_sessions(id) ON DELETE CASCADE,
+	is_active BOOLEAN NOT NULL,
+
+	created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX table_session_history_table_session_id_idx ON table_session_history (table_session_id, created_at);
+
+INSERT INTO table_session_history (table_session_id, is_active, created_at)
+SELECT id, TRUE, created_at FROM table_sessions;
@@ -0,0 +1,19 @@
+ALTER TABLE table_session_history ADD COLUMN is_active BOOLEAN;
+UPDATE table_session_history SET is_active = status <> 'closed';
+ALTER TABLE table_session_history ALTER COLUMN is_active SET NOT NULL;
+ALTER TABLE table_session_history DROP COLUMN status;
+
+DROP INDEX table_sessions_active_table_id_idx;
+ALTER TABLE table_sessions ADD COLUMN is_active_flag BOOLEAN NOT NULL DEFAULT TRUE;
+UPDATE table_sessions SET is_active_flag = is_active;
+ALTER TABLE table_sessions DROP COLUMN is_active;
+ALTER TABLE table_sessions RENAME COLUMN is_active_flag TO is_active;
+CREATE UNIQUE INDEX table_sessions_active_table_id_idx ON table_sessions (table_id) WHERE is_active;
+
+ALTER TABLE table_sessions
+	DROP COLUMN status,
+	DROP COLUMN checked_out_at,
+	DROP COLUMN paid_at,
+	DROP COLUMN closed_at;
+
+DROP TYPE table_session_status;
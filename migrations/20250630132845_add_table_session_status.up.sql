@@ -0,0 +1,32 @@
+CREATE TYPE table_session_status AS ENUM ('ordering', 'awaiting_payment', 'paid', 'closed');
+
+ALTER TABLE table_sessions
+	ADD COLUMN status table_session_status NOT NULL DEFAULT 'ordering',
+	ADD COLUMN checked_out_at TIMESTAMPTZ,
+	ADD COLUMN paid_at TIMESTAMPTZ,
+	ADD COLUMN closed_at TIMESTAMPTZ;
+
+-- when a checkout was set is unknown, the time of the upgrade stands in for it
+UPDATE table_sessions ts
+SET status = CASE
+		WHEN NOT ts.is_active THEN 'closed'
+		WHEN ts.checkout_id IS NOT NULL THEN 'awaiting_payment'
+		ELSE 'ordering'
+	END::table_session_status,
+	checked_out_at = CASE WHEN ts.checkout_id IS NOT NULL THEN NOW() END,
+	closed_at = CASE WHEN NOT ts.is_active THEN COALESCE(
+		(SELECT MAX(h.created_at) FROM table_session_history h WHERE h.table_session_id = ts.id AND NOT h.is_active),
+		NOW()
+	) END;
+
+-- the flag follows the status from now on, the partial index moves along with it
+DROP INDEX table_sessions_active_table_id_idx;
+ALTER TABLE table_sessions DROP COLUMN is_active;
+ALTER TABLE table_sessions ADD COLUMN is_active BOOLEAN NOT NULL GENERATED ALWAYS AS (status <> 'closed') STORED;
+CREATE UNIQUE INDEX table_sessions_active_table_id_idx ON table_sessions (table_id) WHERE is_active;
+
+ALTER TABLE table_session_history ADD COLUMN status table_session_status;
+UPDATE table_session_history
+SET status = CASE WHEN is_active THEN 'ordering' ELSE 'closed' END::table_session_status;
+ALTER TABLE table_session_history ALTER COLUMN status SET NOT NULL;
+ALTER TABLE table_session_history DROP COLUMN is_active;
@@ -0,0 +1,18 @@
+-- enum values cannot be dropped, so the type is rebuilt without it
+UPDATE table_sessions SET status = 'closed' WHERE status = 'expired';
+UPDATE table_session_history SET status = 'closed' WHERE status = 'expired';
+
+DROP INDEX table_sessions_active_table_id_idx;
+ALTER TABLE table_sessions DROP COLUMN is_active;
+
+ALTER TYPE table_session_status RENAME TO table_session_status_old;
+CREATE TYPE table_session_status AS ENUM ('ordering', 'awaiting_payment', 'paid', 'closed');
+
+ALTER TABLE table_sessions ALTER COLUMN status DROP DEFAULT;
+ALTER TABLE table_sessions ALTER COLUMN status TYPE table_session_status USING status::TEXT::table_session_status;
+ALTER TABLE table_sessions ALTER COLUMN status SET DEFAULT 'ordering';
+ALTER TABLE table_session_history ALTER COLUMN status TYPE table_session_status USING status::TEXT::table_session_status;
+DROP TYPE table_session_status_old;
+
+ALTER TABLE table_sessions ADD COLUMN is_active BOOLEAN NOT NULL GENERATED ALWAYS AS (status <> 'closed') STORED;
+CREATE UNIQUE INDEX table_sessions_active_table_id_idx ON table_sessions (table_id) WHERE is_active;
@@ -0,0 +1,2 @@
+-- its own migration, a new enum value cannot be used in the transaction that adds it
+ALTER TYPE table_session_status ADD VALUE 'expired';
@@ -0,0 +1,12 @@
+DROP INDEX table_sessions_expires_at_idx;
+
+UPDATE table_sessions SET status = 'closed' WHERE status = 'expired';
+
+DROP INDEX table_sessions_active_table_id_idx;
+ALTER TABLE table_sessions DROP COLUMN is_active;
+ALTER TABLE table_sessions ADD COLUMN is_active BOOLEAN NOT NULL GENERATED ALWAYS AS (status <> 'closed') STORED;
+CREATE UNIQUE INDEX table_sessions_active_table_id_idx ON table_sessions (table_id) WHERE is_active;
+
+ALTER TABLE table_sessions
+	DROP COLUMN last_seen_at,
+	DROP COLUMN expires_at;
@@ -0,0 +1,15 @@
+ALTER TABLE table_sessions
+	ADD COLUMN last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+	ADD COLUMN expires_at TIMESTAMPTZ;
+
+-- open sessions get the default idle TTL from now on rather than expiring all at once
+UPDATE table_sessions SET expires_at = NOW() + INTERVAL '4 hours' WHERE status <> 'closed';
+
+-- expired sessions free the table just like closed ones
+DROP INDEX table_sessions_active_table_id_idx;
+ALTER TABLE table_sessions DROP COLUMN is_active;
+ALTER TABLE table_sessions ADD COLUMN is_active BOOLEAN NOT NULL
+	GENERATED ALWAYS AS (status NOT IN ('closed', 'expired')) STORED;
+CREATE UNIQUE INDEX table_sessions_active_table_id_idx ON table_sessions (table_id) WHERE is_active;
+
+CREATE INDEX table_sessions_expires_at_idx ON table_sessions (expires_at) WHERE is_active;
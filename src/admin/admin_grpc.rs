@@ -1,35 +1,293 @@
+use std::sync::Arc;
+
 use tonic::{Request, Response, Status};
+use uuid::Uuid;
 
-use crate::token::TokenService;
+use crate::lockout::{LockoutService, LockoutServiceError};
+use crate::mfa::{MfaService, MfaServiceError};
+use crate::role::{Permission, RoleService, RoleServiceError};
+use crate::token::{
+    MFA_SCOPE, PASSWORD_CHANGE_SCOPE, RefreshTokenService, RevocationService, TokenService, admin_id_from,
+    claims_from,
+};
 
-use super::AdminService;
+use super::{
+    AdminModel, AdminService, AdminServiceError, AuthenticatedAdmin, EmailVerificationService, LoginStatus, ValidatedChangePasswordRequest,
+    ValidatedCreateAdminRequest, ValidatedExpiredPasswordRequest, ValidatedUpdateAdminRequest,
+};
 use super::proto;
 
+/// Mutating RPCs expect the server to be wrapped in `AuthInterceptor`.
 pub struct AdminGrpc {
     admin_service: AdminService,
-    token_service: TokenService,
+    role_service: RoleService,
+    token_service: Arc<TokenService>,
+    refresh_token_service: RefreshTokenService,
+    revocation_service: RevocationService,
+    email_verification_service: Arc<EmailVerificationService>,
+    mfa_service: MfaService,
+    lockout_service: LockoutService,
 }
 
 impl AdminGrpc {
-    pub fn new(admin_service: AdminService, token_service: TokenService) -> Self {
-        Self { admin_service, token_service }
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        admin_service: AdminService,
+        role_service: RoleService,
+        token_service: Arc<TokenService>,
+        refresh_token_service: RefreshTokenService,
+        revocation_service: RevocationService,
+        email_verification_service: Arc<EmailVerificationService>,
+        mfa_service: MfaService,
+        lockout_service: LockoutService,
+    ) -> Self {
+        Self {
+            admin_service,
+            role_service,
+            token_service,
+            refresh_token_service,
+            revocation_service,
+            email_verification_service,
+            mfa_service,
+            lockout_service,
+        }
+    }
+
+    async fn issue_token_pair(&self, admin_id: Uuid) -> Result<proto::TokenResponse, Status> {
+        let roles = self.role_service.roles_for(admin_id).await?;
+
+        let issued = self
+            .refresh_token_service
+            .issue(admin_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        let token = self
+            .token_service
+            .create_jwt_with_roles(admin_id.to_string(), roles.iter().map(|role| role.to_string()).collect())
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(proto::TokenResponse {
+            token,
+            refresh_token: issued.token,
+            ..Default::default()
+        })
+    }
+
+    /// A token pair, unless the password expired and must be changed first.
+    async fn finish_login(&self, admin_id: Uuid, status: LoginStatus) -> Result<proto::TokenResponse, Status> {
+        if status == LoginStatus::PasswordExpired {
+            let password_change_token = self
+                .token_service
+                .create_password_change_jwt(admin_id.to_string())
+                .map_err(|e| Status::internal(e.to_string()))?;
+
+            return Ok(proto::TokenResponse { password_change_token, ..Default::default() });
+        }
+
+        self.issue_token_pair(admin_id).await
+    }
+
+    /// Every RPC answers with this shape of admin.
+    async fn admin_message(&self, admin: AdminModel) -> Result<proto::Admin, Status> {
+        // callers enforce access using these, so they come from the database
+        // rather than the possibly stale token
+        let roles = self.role_service.roles_for(admin.id).await?;
+        let permissions = self.role_service.permissions_for(admin.id).await?;
+
+        Ok(proto::Admin {
+            roles: roles.iter().map(|role| role.to_string()).collect(),
+            permissions,
+            ..admin.into()
+        })
+    }
+}
+
+impl From<LockoutServiceError> for Status {
+    fn from(value: LockoutServiceError) -> Self {
+        match value {
+            LockoutServiceError::Locked { retry_after } => {
+                let mut status = Status::resource_exhausted(value.to_string());
+                if let Ok(value) = retry_after.num_seconds().to_string().parse() {
+                    status.metadata_mut().insert("retry-after", value);
+                }
+                status
+            }
+            LockoutServiceError::Repository(_) => Status::internal(value.to_string()),
+        }
+    }
+}
+
+impl From<RoleServiceError> for Status {
+    fn from(value: RoleServiceError) -> Self {
+        match value {
+            RoleServiceError::Forbidden(_) => Status::permission_denied(value.to_string()),
+            RoleServiceError::LastOwner => Status::failed_precondition(value.to_string()),
+            RoleServiceError::Repository(_) => Status::internal(value.to_string()),
+        }
     }
 }
 
 #[tonic::async_trait]
 impl proto::admin_service_server::AdminService for AdminGrpc {
+    /// A taken email is answered like a new admin without roles, under an `id`
+    /// that belongs to no one, so the RPC does not reveal which emails exist.
     async fn create_admin(
         &self,
-        _: Request<proto::CreateAdminRequest>,
+        request: Request<proto::CreateAdminRequest>,
     ) -> Result<Response<proto::AdminResponse>, Status> {
-        Err(Status::unimplemented("Not available through gRPC"))
+        let admin_id = admin_id_from(&request)?;
+        self.role_service.require(admin_id, Permission::CreateAdmin).await?;
+
+        let data = ValidatedCreateAdminRequest::try_from(request.into_inner())?;
+
+        let email = data.email.clone();
+
+        let admin = match self.admin_service.register_admin(data.email, data.name, data.password, data.roles).await {
+            Ok(admin) => {
+                self.email_verification_service.spawn_verification(admin.email.clone());
+
+                self.admin_message(admin).await?
+            }
+            Err(AdminServiceError::EmailTaken) => proto::Admin {
+                id: Uuid::new_v4().to_string(),
+                email,
+                ..Default::default()
+            },
+            Err(e @ AdminServiceError::PasswordBlocked) => return Err(Status::invalid_argument(e.to_string())),
+            Err(e) => return Err(Status::internal(e.to_string())),
+        };
+
+        Ok(Response::new(proto::AdminResponse { admin: Some(admin) }))
     }
 
     async fn login_admin(
         &self,
-        _: Request<proto::LoginAdminRequest>,
+        request: Request<proto::LoginAdminRequest>,
+    ) -> Result<Response<proto::TokenResponse>, Status> {
+        let ip = request.remote_addr().map(|addr| addr.ip());
+        let proto::LoginAdminRequest { email, password } = request.into_inner();
+
+        self.lockout_service.check(&email, ip).await?;
+
+        let AuthenticatedAdmin { id, status } = match self.admin_service.authenticate(email.clone(), password).await {
+            Ok(authenticated) => authenticated,
+            Err(e @ AdminServiceError::InvalidCredentials) => {
+                self.lockout_service.record_failure(&email, ip).await?;
+                return Err(Status::unauthenticated(e.to_string()));
+            }
+            Err(e @ AdminServiceError::EmailNotVerified) => return Err(Status::permission_denied(e.to_string())),
+            Err(e) => return Err(Status::internal(e.to_string())),
+        };
+
+        let mfa_enabled = self
+            .mfa_service
+            .is_enabled(id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        if mfa_enabled {
+            let mfa_token = self
+                .token_service
+                .create_mfa_jwt(id.to_string())
+                .map_err(|e| Status::internal(e.to_string()))?;
+
+            return Ok(Response::new(proto::TokenResponse { mfa_token, ..Default::default() }));
+        }
+
+        // only once every factor passed, so failed codes keep counting
+        self.lockout_service.record_success(&email).await?;
+
+        Ok(Response::new(self.finish_login(id, status).await?))
+    }
+
+    async fn verify_mfa(
+        &self,
+        request: Request<proto::VerifyMfaRequest>,
+    ) -> Result<Response<proto::TokenResponse>, Status> {
+        let ip = request.remote_addr().map(|addr| addr.ip());
+        let proto::VerifyMfaRequest { mfa_token, code } = request.into_inner();
+
+        let claims = self
+            .token_service
+            .decode_scoped(mfa_token, MFA_SCOPE)
+            .map_err(|_| Status::unauthenticated("Unauthenticated"))?;
+
+        // lockouts are kept per email, as that is what a password guess names
+        let admin = match claims.admin_id() {
+            Ok(admin_id) => self
+                .admin_service
+                .find_by_id(admin_id)
+                .await
+                .map_err(|e| Status::internal(e.to_string()))?,
+            Err(_) => None,
+        }
+        .ok_or_else(|| Status::unauthenticated("Unauthenticated"))?;
+
+        self.lockout_service.check(&admin.email, ip).await?;
+
+        match self.mfa_service.verify(admin.id, code).await {
+            Ok(()) => {}
+            Err(e @ (MfaServiceError::InvalidCode | MfaServiceError::NotEnrolled)) => {
+                self.lockout_service.record_failure(&admin.email, ip).await?;
+                return Err(Status::unauthenticated(e.to_string()));
+            }
+            Err(e) => return Err(Status::internal(e.to_string())),
+        }
+
+        self.lockout_service.record_success(&admin.email).await?;
+
+        // the mfa token is single use
+        self.revocation_service
+            .revoke_token(&claims)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        let status = match self.admin_service.login_status(admin.id).await {
+            Ok(status) => status,
+            Err(e @ AdminServiceError::EmailNotVerified) => return Err(Status::permission_denied(e.to_string())),
+            Err(e) => return Err(Status::internal(e.to_string())),
+        };
+
+        Ok(Response::new(self.finish_login(admin.id, status).await?))
+    }
+
+    /// Finishes a login whose password expired by setting a new one.
+    async fn change_expired_password(
+        &self,
+        request: Request<proto::ChangeExpiredPasswordRequest>,
     ) -> Result<Response<proto::TokenResponse>, Status> {
-        Err(Status::unimplemented("Not available through gRPC"))
+        let data = ValidatedExpiredPasswordRequest::try_from(request.into_inner())?;
+
+        let claims = self
+            .token_service
+            .decode_scoped(data.password_change_token, PASSWORD_CHANGE_SCOPE)
+            .map_err(|_| Status::unauthenticated("Unauthenticated"))?;
+        let admin_id = claims.admin_id().map_err(|_| Status::unauthenticated("Unauthenticated"))?;
+
+        self.admin_service
+            .set_password(admin_id, data.new_password)
+            .await
+            .map_err(|e| match e {
+                AdminServiceError::PasswordReused | AdminServiceError::PasswordBlocked => {
+                    Status::invalid_argument(e.to_string())
+                }
+                _ => Status::internal(e.to_string()),
+            })?;
+
+        // the password change token is single use, revoke it by `jti` since the
+        // revoke-all below misses tokens issued in the same second
+        self.revocation_service
+            .revoke_token(&claims)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        self.revocation_service
+            .revoke_all_for_admin(admin_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(self.issue_token_pair(admin_id).await?))
     }
 
     async fn verify_admin(
@@ -38,32 +296,95 @@ impl proto::admin_service_server::AdminService for AdminGrpc {
     ) -> Result<Response<proto::AdminResponse>, Status> {
         let proto::TokenRequest { token } = request.into_inner();
 
-        let claims = self
+        let admin_id = self
             .token_service
             .decode_jwt(token)
+            .and_then(|claims| claims.admin_id())
             .map_err(|e| Status::invalid_argument(e.to_string()))?;
         
         let admin = self
             .admin_service
-            .find_one(claims.sub)
+            .find_by_id(admin_id)
             .await
             .map_err(|e| Status::invalid_argument(e.to_string()))?
             .ok_or_else(|| Status::not_found("Admin not found"))?;
 
-        Ok(Response::new(proto::AdminResponse { admin: Some(admin.into()) }))
+        Ok(Response::new(proto::AdminResponse { admin: Some(self.admin_message(admin).await?) }))
     }
 
     async fn update_admin(
         &self,
-        _: Request<proto::UpdateAdminRequest>,
+        request: Request<proto::UpdateAdminRequest>,
     ) -> Result<Response<proto::AdminResponse>, Status> {
-        Err(Status::unimplemented("Not available through gRPC"))
+        let admin_id = admin_id_from(&request)?;
+        let data = ValidatedUpdateAdminRequest::try_from(request.into_inner())?;
+
+        let admin = self
+            .admin_service
+            .update_one(admin_id, data.new_name)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::not_found("Admin not found"))?;
+
+        Ok(Response::new(proto::AdminResponse { admin: Some(self.admin_message(admin).await?) }))
     }
 
     async fn delete_admin(
         &self,
-        _: Request<proto::DeleteAdminRequest>,
+        request: Request<proto::DeleteAdminRequest>,
     ) -> Result<Response<()>, Status> {
-        Err(Status::unimplemented("Not available through gRPC"))
+        let admin_id = admin_id_from(&request)?;
+        self.role_service.require(admin_id, Permission::DeleteAdmin).await?;
+
+        let admin = self
+            .admin_service
+            .find_by_id(admin_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?
+            .ok_or_else(|| Status::not_found("Admin not found"))?;
+
+        self.role_service.delete_admin(admin.id).await?;
+
+        self.revocation_service
+            .revoke_all_for_admin(admin.id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(()))
+    }
+
+    /// Revokes every other session of the admin and returns a fresh token pair
+    /// for the caller.
+    async fn change_password(
+        &self,
+        request: Request<proto::ChangePasswordRequest>,
+    ) -> Result<Response<proto::TokenResponse>, Status> {
+        let claims = claims_from(&request)?;
+        let admin_id = admin_id_from(&request)?;
+        let data = ValidatedChangePasswordRequest::try_from(request.into_inner())?;
+
+        self.admin_service
+            .change_password(admin_id, data.current_password, data.new_password)
+            .await
+            .map_err(|e| match e {
+                AdminServiceError::InvalidCredentials
+                | AdminServiceError::PasswordReused
+                | AdminServiceError::PasswordBlocked => Status::invalid_argument(e.to_string()),
+                _ => Status::internal(e.to_string()),
+            })?;
+
+        // revoke-all misses tokens issued in the same second, so the presented
+        // one is revoked by `jti` as well
+        self.revocation_service
+            .revoke_token(&claims)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        self.revocation_service
+            .revoke_all_for_admin(admin_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        Ok(Response::new(self.issue_token_pair(admin_id).await?))
     }
 }
@@ -1,20 +1,32 @@
 use std::borrow::Cow;
 
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 use validator::{Validate, ValidationError};
 
+use crate::role::Role;
+
 use super::proto;
 
 #[derive(Debug, Clone, Serialize)]
 pub struct AdminModel {
+    pub id: Uuid,
     pub email: String,
     pub name: String,
     pub password: String,
+    pub email_verified_at: Option<DateTime<Utc>>,
+    pub password_changed_at: DateTime<Utc>,
 }
 
-impl Into<proto::Admin> for AdminModel {
-    fn into(self) -> proto::Admin {
-        proto::Admin { email: self.email }
+impl From<AdminModel> for proto::Admin {
+    fn from(value: AdminModel) -> Self {
+        proto::Admin {
+            id: value.id.to_string(),
+            email: value.email,
+            roles: Vec::new(),
+            permissions: Vec::new(),
+        }
     }
 }
 
@@ -28,6 +40,9 @@ pub struct ValidatedCreateAdminRequest {
 
     #[validate(custom(function = "validate_password"))]
     pub password: String,
+
+    #[serde(default)]
+    pub roles: Vec<Role>,
 }
 
 impl TryFrom<proto::CreateAdminRequest> for ValidatedCreateAdminRequest {
@@ -38,6 +53,7 @@ impl TryFrom<proto::CreateAdminRequest> for ValidatedCreateAdminRequest {
             email: value.email,
             name: value.name,
             password: value.password,
+            roles: Vec::new(),
         };
 
         v.validate().map_err(|e| {
@@ -48,6 +64,36 @@ impl TryFrom<proto::CreateAdminRequest> for ValidatedCreateAdminRequest {
     }
 }
 
+/// Moves an admin over from another system without knowing their password.
+#[derive(Debug, Validate, Deserialize)]
+pub struct ValidatedImportAdminRequest {
+    #[validate(email(message = "Email must be valid"))]
+    pub email: String,
+
+    #[validate(length(max = 255))]
+    pub name: String,
+
+    pub password_hash: String,
+
+    #[serde(default)]
+    pub roles: Vec<Role>,
+}
+
+/// Creates the first owner using the token printed at startup.
+#[derive(Debug, Validate, Deserialize)]
+pub struct ValidatedSetupAdminRequest {
+    pub setup_token: String,
+
+    #[validate(email(message = "Email must be valid"))]
+    pub email: String,
+
+    #[validate(length(max = 255))]
+    pub name: String,
+
+    #[validate(custom(function = "validate_password"))]
+    pub password: String,
+}
+
 #[derive(Debug, Validate, Deserialize)]
 pub struct ValidatedUpdateAdminRequest {
     #[validate(length(max = 255))]
@@ -70,6 +116,94 @@ impl TryFrom<proto::UpdateAdminRequest> for ValidatedUpdateAdminRequest {
     }
 }
 
+#[derive(Debug, Validate, Deserialize)]
+pub struct ValidatedChangePasswordRequest {
+    pub current_password: String,
+
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
+impl TryFrom<proto::ChangePasswordRequest> for ValidatedChangePasswordRequest {
+    type Error = tonic::Status;
+
+    fn try_from(value: proto::ChangePasswordRequest) -> Result<Self, Self::Error> {
+        let v = Self {
+            current_password: value.current_password,
+            new_password: value.new_password,
+        };
+
+        v.validate().map_err(|e| {
+            tonic::Status::invalid_argument(format!("Validation failed: {}", e))
+        })?;
+
+        Ok(v)
+    }
+}
+
+/// Sets a new password during a login whose password expired.
+#[derive(Debug, Validate, Deserialize)]
+pub struct ValidatedExpiredPasswordRequest {
+    pub password_change_token: String,
+
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
+impl TryFrom<proto::ChangeExpiredPasswordRequest> for ValidatedExpiredPasswordRequest {
+    type Error = tonic::Status;
+
+    fn try_from(value: proto::ChangeExpiredPasswordRequest) -> Result<Self, Self::Error> {
+        let v = Self {
+            password_change_token: value.password_change_token,
+            new_password: value.new_password,
+        };
+
+        v.validate().map_err(|e| {
+            tonic::Status::invalid_argument(format!("Validation failed: {}", e))
+        })?;
+
+        Ok(v)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyEmailRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ResendVerificationRequest {
+    pub email: String,
+}
+
+/// Moves the admin to another address, confirmed from that address.
+#[derive(Debug, Validate, Deserialize)]
+pub struct ValidatedChangeEmailRequest {
+    #[validate(email(message = "Email must be valid"))]
+    pub new_email: String,
+
+    pub password: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ConfirmEmailChangeRequest {
+    pub token: String,
+}
+
+#[derive(Debug, Validate, Deserialize)]
+pub struct ValidatedConfirmPasswordResetRequest {
+    pub token: String,
+
+    #[validate(custom(function = "validate_password"))]
+    pub new_password: String,
+}
+
 fn validate_password(password: &str) -> Result<(), ValidationError> {
     let min_length = 8;
     let err = ValidationError::new("password");
@@ -111,3 +245,13 @@ pub struct LoginAdminRequest {
     pub email: String,
     pub password: String,
 }
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RefreshTokenRequest {
+    pub refresh_token: String,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct LogoutRequest {
+    pub refresh_token: Option<String>,
+}
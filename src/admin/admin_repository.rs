@@ -1,28 +1,56 @@
-use argon2::Argon2;
-use password_hash::rand_core::OsRng;
-use password_hash::{PasswordHasher, SaltString};
-use sqlx::{PgPool, query_as};
+use std::sync::Arc;
+
+use sqlx::{PgConnection, PgPool, query, query_as, query_scalar};
 use thiserror::Error;
+use uuid::Uuid;
 
-use super::AdminModel;
+use super::{AdminModel, PasswordCheck, PasswordHashing, PasswordHashingError};
 
 #[derive(Error, Debug)]
 pub enum AdminRepositoryError {
     #[error("An error occurred with the database: {0}")]
     Database(#[from] sqlx::Error),
 
-    #[error("An error occurred while creating admin")]
-    CreateAdmin,
+    #[error("{0}")]
+    PasswordHashing(#[from] PasswordHashingError),
+}
+
+impl AdminRepositoryError {
+    /// Whether the write clashed with an existing admin, e.g. over the email.
+    pub fn is_unique_violation(&self) -> bool {
+        matches!(self, Self::Database(sqlx::Error::Database(e)) if e.is_unique_violation())
+    }
 }
 
-#[allow(dead_code)]
 pub struct AdminRepository {
     pool: PgPool,
+    password_hashing: Arc<PasswordHashing>,
+    password_history: u32,
 }
 
 impl AdminRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            password_hashing: Arc::default(),
+            password_history: 0,
+        }
+    }
+
+    pub fn with_password_hashing(mut self, password_hashing: Arc<PasswordHashing>) -> Self {
+        self.password_hashing = password_hashing;
+        self
+    }
+
+    /// How many previous passwords are kept and may not be chosen again.
+    pub fn with_password_history(mut self, password_history: u32) -> Self {
+        self.password_history = password_history;
+        self
+    }
+
+    /// The hashing used for stored passwords, for verifying them.
+    pub fn password_hashing(&self) -> &PasswordHashing {
+        &self.password_hashing
     }
 
     pub async fn create(
@@ -31,23 +59,113 @@ impl AdminRepository {
         name: String,
         password: String,
     ) -> Result<AdminModel, AdminRepositoryError> {
-        let salt = SaltString::generate(&mut OsRng);
-        let password = Argon2::default()
-            .hash_password(password.as_bytes(), &salt)
-            .map_err(|_| AdminRepositoryError::CreateAdmin)?
-            .to_string();
+        self.create_with_roles(email, name, password, Vec::new()).await
+    }
 
-        Ok(query_as!(
+    /// Creates the admin together with its roles, a failure leaves neither behind.
+    pub async fn create_with_roles(
+        &self,
+        email: String,
+        name: String,
+        password: String,
+        roles: Vec<String>,
+    ) -> Result<AdminModel, AdminRepositoryError> {
+        let password = self.password_hashing.hash(&password)?;
+
+        self.insert_with_roles(email, name, password, roles).await
+    }
+
+    /// Stores a hash from another system as is, it is replaced by an Argon2
+    /// hash on the first successful login.
+    pub async fn import(
+        &self,
+        email: String,
+        name: String,
+        password_hash: String,
+        roles: Vec<String>,
+    ) -> Result<AdminModel, AdminRepositoryError> {
+        self.insert_with_roles(email, name, password_hash, roles).await
+    }
+
+    async fn insert_with_roles(
+        &self,
+        email: String,
+        name: String,
+        password: String,
+        roles: Vec<String>,
+    ) -> Result<AdminModel, AdminRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let admin = query_as!(
             AdminModel,
             r#"
             INSERT INTO admins (email, name, password)
             VALUES ($1, $2, $3)
-            RETURNING email, name, password
+            RETURNING id, email, name, password, email_verified_at, password_changed_at
+            "#,
+            email,
+            name,
+            password
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        insert_roles(&mut tx, admin.id, &roles).await?;
+
+        tx.commit().await?;
+
+        Ok(admin)
+    }
+
+    /// Creates the admin with its roles only if there are no admins yet. Returns
+    /// `None` otherwise. The email counts as verified, whoever bootstraps the
+    /// deployment controls it and there is no one else to vouch for the address.
+    pub async fn create_first(
+        &self,
+        email: String,
+        name: String,
+        password: String,
+        roles: Vec<String>,
+    ) -> Result<Option<AdminModel>, AdminRepositoryError> {
+        let password = self.password_hashing.hash(&password)?;
+        let mut tx = self.pool.begin().await?;
+
+        // serialise concurrent bootstraps, the NOT EXISTS check alone would let both through
+        sqlx::query("LOCK TABLE admins IN SHARE ROW EXCLUSIVE MODE")
+            .execute(&mut *tx)
+            .await?;
+
+        let admin = query_as!(
+            AdminModel,
+            r#"
+            INSERT INTO admins (email, name, password, email_verified_at)
+            SELECT $1, $2, $3, NOW()
+            WHERE NOT EXISTS (SELECT 1 FROM admins)
+            RETURNING id, email, name, password, email_verified_at, password_changed_at
             "#,
             email,
             name,
             password
         )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(admin) = &admin {
+            insert_roles(&mut tx, admin.id, &roles).await?;
+        }
+
+        tx.commit().await?;
+
+        Ok(admin)
+    }
+
+    pub async fn count(&self) -> Result<i64, AdminRepositoryError> {
+        Ok(query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM admins
+            "#
+        )
         .fetch_one(&self.pool)
         .await?)
     }
@@ -59,7 +177,7 @@ impl AdminRepository {
         Ok(query_as!(
             AdminModel,
             r#"
-            SELECT email, name, password
+            SELECT id, email, name, password, email_verified_at, password_changed_at
             FROM admins
             WHERE email = $1;
             "#,
@@ -69,9 +187,23 @@ impl AdminRepository {
         .await?)
     }
 
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<AdminModel>, AdminRepositoryError> {
+        Ok(query_as!(
+            AdminModel,
+            r#"
+            SELECT id, email, name, password, email_verified_at, password_changed_at
+            FROM admins
+            WHERE id = $1
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
     pub async fn update_one(
         &self,
-        email: String,
+        id: Uuid,
         name: String,
     ) -> Result<Option<AdminModel>, AdminRepositoryError> {
         Ok(query_as!(
@@ -79,27 +211,189 @@ impl AdminRepository {
             r#"
             UPDATE admins
             SET name = $1
-            WHERE email = $2
-            RETURNING email, name, password
+            WHERE id = $2
+            RETURNING id, email, name, password, email_verified_at, password_changed_at
             "#,
             name,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Replaces the hash only if it is still `current_hash`, so an upgrade
+    /// cannot undo a password change that happened in the meantime.
+    pub async fn rehash_password(
+        &self,
+        id: Uuid,
+        current_hash: String,
+        password: String,
+    ) -> Result<bool, AdminRepositoryError> {
+        let password = self.password_hashing.hash(&password)?;
+
+        let result = query!(
+            r#"
+            UPDATE admins
+            SET password = $1
+            WHERE id = $2 AND password = $3
+            "#,
+            password,
+            id,
+            current_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Whether `password` is the current one or among the kept previous ones.
+    /// Always false when no history is kept.
+    pub async fn is_password_reused(&self, id: Uuid, password: &str) -> Result<bool, AdminRepositoryError> {
+        if self.password_history == 0 {
+            return Ok(false);
+        }
+
+        let hashes = query_scalar!(
+            r#"
+            SELECT password AS "password!"
+            FROM admins
+            WHERE id = $1
+            UNION ALL
+            (
+                SELECT password
+                FROM password_history
+                WHERE admin_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+            )
+            "#,
+            id,
+            i64::from(self.password_history)
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        Ok(hashes.iter().any(|hash| {
+            matches!(
+                self.password_hashing.verify(password, hash),
+                Ok(PasswordCheck::Valid | PasswordCheck::ValidNeedsRehash)
+            )
+        }))
+    }
+
+    /// Moves the current hash into the history, which is trimmed to the
+    /// configured size, and restarts the password's age.
+    pub async fn update_password(
+        &self,
+        id: Uuid,
+        password: String,
+    ) -> Result<Option<AdminModel>, AdminRepositoryError> {
+        let password = self.password_hashing.hash(&password)?;
+        let mut tx = self.pool.begin().await?;
+
+        query!(
+            r#"
+            INSERT INTO password_history (admin_id, password)
+            SELECT id, password
+            FROM admins
+            WHERE id = $1
+            "#,
+            id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM password_history
+            WHERE admin_id = $1 AND id NOT IN (
+                SELECT id
+                FROM password_history
+                WHERE admin_id = $1
+                ORDER BY created_at DESC
+                LIMIT $2
+            )
+            "#,
+            id,
+            i64::from(self.password_history)
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let admin = query_as!(
+            AdminModel,
+            r#"
+            UPDATE admins
+            SET password = $1, password_changed_at = NOW()
+            WHERE id = $2
+            RETURNING id, email, name, password, email_verified_at, password_changed_at
+            "#,
+            password,
+            id
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(admin)
+    }
+
+    /// Only verifies `email` while it is still the admin's address.
+    pub async fn mark_email_verified(
+        &self,
+        id: Uuid,
+        email: String,
+    ) -> Result<Option<AdminModel>, AdminRepositoryError> {
+        Ok(query_as!(
+            AdminModel,
+            r#"
+            UPDATE admins
+            SET email_verified_at = COALESCE(email_verified_at, NOW())
+            WHERE id = $1 AND email = $2
+            RETURNING id, email, name, password, email_verified_at, password_changed_at
+            "#,
+            id,
             email
         )
         .fetch_optional(&self.pool)
         .await?)
     }
 
-    pub async fn delete_one(
+    /// Sets a new, already verified, email. Fails with a unique violation if
+    /// another admin has it.
+    pub async fn change_email(
         &self,
+        id: Uuid,
         email: String,
+    ) -> Result<Option<AdminModel>, AdminRepositoryError> {
+        Ok(query_as!(
+            AdminModel,
+            r#"
+            UPDATE admins
+            SET email = $2, email_verified_at = NOW()
+            WHERE id = $1
+            RETURNING id, email, name, password, email_verified_at, password_changed_at
+            "#,
+            id,
+            email
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    pub async fn delete_one(
+        &self,
+        id: Uuid,
     ) -> Result<(), AdminRepositoryError> {
         query_as!(
             AdminModel,
             r#"
             DELETE FROM admins
-            WHERE email = $1
+            WHERE id = $1
             "#,
-            email
+            id
         )
         .execute(&self.pool)
         .await?;
@@ -108,6 +402,22 @@ impl AdminRepository {
     }
 }
 
+async fn insert_roles(conn: &mut PgConnection, admin_id: Uuid, roles: &[String]) -> Result<(), sqlx::Error> {
+    query!(
+        r#"
+        INSERT INTO admin_roles (admin_id, role)
+        SELECT $1, role FROM UNNEST($2::VARCHAR[]) AS role
+        ON CONFLICT DO NOTHING
+        "#,
+        admin_id,
+        roles
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use crate::database::setup_test_db;
@@ -168,13 +478,13 @@ mod tests {
             .await
             .unwrap();
 
-        let Some(updated) = ar.update_one(admin.email.clone(), "ASDF".to_string()).await.unwrap() else {
+        let Some(updated) = ar.update_one(admin.id, "ASDF".to_string()).await.unwrap() else {
             panic!()
         };
 
         assert_eq!(updated.name, "ASDF".to_string());
 
-        let Some(found) = ar.find_one(admin.email).await.unwrap() else {
+        let Some(found) = ar.find_by_id(admin.id).await.unwrap() else {
             panic!()
         };
 
@@ -195,9 +505,155 @@ mod tests {
             .await
             .unwrap();
 
-        ar.delete_one(admin.email.clone()).await.unwrap();
+        ar.delete_one(admin.id).await.unwrap();
 
         let found = ar.find_one(admin.email).await.unwrap();
         assert!(found.is_none());
     }
+
+    #[tokio::test]
+    async fn test_mark_email_verified() {
+        let test_db = setup_test_db().await;
+
+        let ar = AdminRepository::new(test_db.pool);
+        let admin = ar
+            .create(
+                "asdf@gmail.com".to_string(),
+                "asdf".to_string(),
+                "HelloWorld123".to_string(),
+            )
+            .await
+            .unwrap();
+        assert!(admin.email_verified_at.is_none());
+
+        // a link sent to another address does not count
+        assert!(ar.mark_email_verified(admin.id, "old@gmail.com".to_string()).await.unwrap().is_none());
+
+        let Some(verified) = ar.mark_email_verified(admin.id, admin.email.clone()).await.unwrap() else {
+            panic!()
+        };
+        assert!(verified.email_verified_at.is_some());
+
+        // verifying again keeps the original timestamp
+        let Some(again) = ar.mark_email_verified(admin.id, admin.email).await.unwrap() else {
+            panic!()
+        };
+        assert_eq!(again.email_verified_at, verified.email_verified_at);
+    }
+
+    #[tokio::test]
+    async fn test_create_first_only_once() {
+        let test_db = setup_test_db().await;
+
+        let ar = AdminRepository::new(test_db.pool);
+        assert_eq!(ar.count().await.unwrap(), 0);
+
+        let first = ar
+            .create_first(
+                "asdf@gmail.com".to_string(),
+                "asdf".to_string(),
+                "HelloWorld123".to_string(),
+                vec!["owner".to_string()],
+            )
+            .await
+            .unwrap();
+        assert!(first.is_some());
+
+        let second = ar
+            .create_first(
+                "other@gmail.com".to_string(),
+                "other".to_string(),
+                "HelloWorld123".to_string(),
+                vec!["owner".to_string()],
+            )
+            .await
+            .unwrap();
+        assert!(second.is_none());
+        assert_eq!(ar.count().await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_create_first_rolls_back_without_roles() {
+        let test_db = setup_test_db().await;
+
+        let ar = AdminRepository::new(test_db.pool);
+
+        // an unknown role fails the insert into admin_roles
+        let result = ar
+            .create_first(
+                "asdf@gmail.com".to_string(),
+                "asdf".to_string(),
+                "HelloWorld123".to_string(),
+                vec!["nobody".to_string()],
+            )
+            .await;
+        assert!(result.is_err());
+        assert_eq!(ar.count().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_create_with_roles_is_atomic() {
+        let test_db = setup_test_db().await;
+
+        let ar = AdminRepository::new(test_db.pool);
+
+        let result = ar
+            .create_with_roles(
+                "asdf@gmail.com".to_string(),
+                "asdf".to_string(),
+                "HelloWorld123".to_string(),
+                vec!["waiter".to_string(), "nobody".to_string()],
+            )
+            .await;
+        assert!(result.is_err());
+        assert!(ar.find_one("asdf@gmail.com".to_string()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_password_history() {
+        let test_db = setup_test_db().await;
+        let email = "asdf@gmail.com".to_string();
+
+        let ar = AdminRepository::new(test_db.pool).with_password_history(2);
+        let admin = ar.create(email.clone(), "asdf".to_string(), "first".to_string()).await.unwrap();
+
+        for password in ["second", "third", "fourth"] {
+            ar.update_password(admin.id, password.to_string()).await.unwrap();
+        }
+
+        let updated = ar.find_one(email).await.unwrap().unwrap();
+        assert!(updated.password_changed_at > admin.password_changed_at);
+
+        // the current password and the two before it
+        assert!(ar.is_password_reused(admin.id, "fourth").await.unwrap());
+        assert!(ar.is_password_reused(admin.id, "third").await.unwrap());
+        assert!(ar.is_password_reused(admin.id, "second").await.unwrap());
+        assert!(!ar.is_password_reused(admin.id, "first").await.unwrap());
+        assert!(!ar.is_password_reused(admin.id, "fifth").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let test_db = setup_test_db().await;
+
+        let ar = AdminRepository::new(test_db.pool);
+        let admin = ar
+            .create("asdf@gmail.com".to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+        ar.create("taken@gmail.com".to_string(), "taken".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let Some(changed) = ar.change_email(admin.id, "new@gmail.com".to_string()).await.unwrap() else {
+            panic!()
+        };
+        assert_eq!(changed.id, admin.id);
+        assert_eq!(changed.email, "new@gmail.com");
+        assert!(changed.email_verified_at.is_some());
+        assert!(ar.find_one("asdf@gmail.com".to_string()).await.unwrap().is_none());
+
+        let result = ar.change_email(admin.id, "taken@gmail.com".to_string()).await;
+        assert!(result.unwrap_err().is_unique_violation());
+    }
 }
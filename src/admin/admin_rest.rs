@@ -1,78 +1,484 @@
 use axum::Router;
 use axum::routing;
 use axum::response::{IntoResponse, Response};
-use axum::extract::{Json, State};
+use axum::extract::{Json, Path, State};
 use axum_extra::headers::authorization::Bearer;
 use axum_extra::headers::Authorization;
 use axum_extra::TypedHeader;
 use hyper::StatusCode;
 use serde_json::json;
+use uuid::Uuid;
 
 use crate::app::RestState;
+use crate::lockout::ClientIp;
+use crate::mfa::{MfaLoginRequest, MfaServiceError};
+use crate::role::{Permission, RoleService};
+use crate::token::{
+    MFA_SCOPE, OneTimeTokenServiceError, PASSWORD_CHANGE_SCOPE, RefreshTokenServiceError, TokenService,
+};
 use crate::utils::ValidatedJson;
 
+use super::AdminServiceError;
+use super::AuthenticatedAdmin;
+use super::ConfirmEmailChangeRequest;
+use super::LoginStatus;
+use super::EmailVerificationServiceError;
 use super::LoginAdminRequest;
+use super::LogoutRequest;
+use super::PasswordResetRequest;
+use super::PasswordResetServiceError;
+use super::RefreshTokenRequest;
+use super::ResendVerificationRequest;
+use super::SetupServiceError;
+use super::ValidatedChangeEmailRequest;
+use super::ValidatedChangePasswordRequest;
+use super::ValidatedConfirmPasswordResetRequest;
 use super::ValidatedCreateAdminRequest;
+use super::ValidatedExpiredPasswordRequest;
+use super::ValidatedImportAdminRequest;
+use super::ValidatedSetupAdminRequest;
 use super::ValidatedUpdateAdminRequest;
+use super::VerifyEmailRequest;
 
 pub fn router() -> Router<RestState> {
     Router::new()
+        .route("/setup", routing::post(setup_admin_handler))
         .route("/login", routing::post(login_handler))
+        .route("/login/mfa", routing::post(login_mfa_handler))
+        .route("/login/password", routing::post(login_password_handler))
+        .route("/token/refresh", routing::post(refresh_token_handler))
+        .route("/logout", routing::post(logout_handler))
+        .route("/sessions/revoke", routing::post(revoke_sessions_handler))
+        .route("/password", routing::put(change_password_handler))
+        .route("/password/reset", routing::post(request_password_reset_handler))
+        .route("/password/reset/confirm", routing::post(confirm_password_reset_handler))
+        .route("/email/verify", routing::post(verify_email_handler))
+        .route("/email/verify/resend", routing::post(resend_verification_handler))
+        .route("/email", routing::post(change_email_handler))
+        .route("/email/confirm", routing::post(confirm_email_change_handler))
         .route("/", routing::post(create_admin_handler))
+        .route("/import", routing::post(import_admin_handler))
         .route("/", routing::get(read_admin_handler))
         .route("/", routing::put(update_admin_handler))
         .route("/", routing::delete(delete_admin_handler))
+        .route("/{id}", routing::delete(delete_other_admin_handler))
 }
 
+/// Admins with two-factor authentication get an `mfa_token` instead of a token
+/// pair, to be exchanged at `/login/mfa` together with a code. Admins whose
+/// password expired get a `password_change_token` for `/login/password`, only
+/// once any second factor is passed.
 pub async fn login_handler(
-    State(RestState { admin_service, token_service }): State<RestState>,
+    State(RestState { admin_service, token_service, refresh_token_service, role_service, mfa_service, lockout_service, .. }): State<RestState>,
+    ClientIp(ip): ClientIp,
     Json(LoginAdminRequest { email, password }): Json<LoginAdminRequest>,
 ) -> Response {
+    // checked before verifying the password, so locked out guesses stay cheap
+    if let Err(e) = lockout_service.check(&email, ip).await {
+        return e.into_response();
+    }
+
     match admin_service.authenticate(email.clone(), password).await {
-        Ok(_) => {
-            match token_service.create_jwt(email) {
-                Ok(token) => {
-                    (StatusCode::OK, Json(json!({ "token": token }))).into_response()
+        Ok(AuthenticatedAdmin { id, status }) => {
+            match mfa_service.is_enabled(id).await {
+                Ok(true) => {
+                    return match token_service.create_mfa_jwt(id.to_string()) {
+                        Ok(mfa_token) => {
+                            (StatusCode::OK, Json(json!({ "mfa_required": true, "mfa_token": mfa_token }))).into_response()
+                        }
+                        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+                    };
                 }
+                Ok(false) => {}
+                Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+            }
+
+            // only once every factor passed, so failed codes keep counting
+            if let Err(e) = lockout_service.record_success(&email).await {
+                return e.into_response();
+            }
+
+            if status == LoginStatus::PasswordExpired {
+                return password_change_response(&token_service, id);
+            }
+
+            match refresh_token_service.issue(id).await {
+                Ok(issued) => token_pair_response(&token_service, &role_service, issued.admin_id, issued.token).await,
                 Err(_) => {
                     (StatusCode::BAD_REQUEST, Json(json!({ "message": "Failed to create authentication token" }))).into_response()
                 },
             }
         },
 
-        Err(err) => (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+        Err(err @ AdminServiceError::EmailNotVerified) => {
+            (StatusCode::FORBIDDEN, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(err @ AdminServiceError::InvalidCredentials) => {
+            if let Err(e) = lockout_service.record_failure(&email, ip).await {
+                return e.into_response();
+            }
+
+            (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Failed codes count towards the same lockout as failed passwords.
+pub async fn login_mfa_handler(
+    State(RestState { admin_service, token_service, refresh_token_service, revocation_service, role_service, mfa_service, lockout_service, .. }): State<RestState>,
+    ClientIp(ip): ClientIp,
+    Json(MfaLoginRequest { mfa_token, code }): Json<MfaLoginRequest>,
+) -> Result<Response, Response> {
+    let claims = token_service
+        .decode_scoped(mfa_token, MFA_SCOPE)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    // lockouts are kept per email, as that is what a password guess names
+    let admin = match claims.admin_id() {
+        Ok(admin_id) => admin_service
+            .find_by_id(admin_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?,
+        Err(_) => None,
+    }
+    .ok_or_else(|| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    lockout_service
+        .check(&admin.email, ip)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    match mfa_service.verify(admin.id, code).await {
+        Ok(()) => {}
+        Err(err @ (MfaServiceError::InvalidCode | MfaServiceError::NotEnrolled)) => {
+            lockout_service
+                .record_failure(&admin.email, ip)
+                .await
+                .map_err(IntoResponse::into_response)?;
+
+            return Err((StatusCode::UNAUTHORIZED, Json(json!({ "message": err.to_string() }))).into_response());
+        }
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    }
+
+    lockout_service
+        .record_success(&admin.email)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    // the mfa token is single use
+    revocation_service
+        .revoke_token(&claims)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    let status = admin_service
+        .login_status(admin.id)
+        .await
+        .map_err(login_status_error)?;
+
+    if status == LoginStatus::PasswordExpired {
+        return Ok(password_change_response(&token_service, admin.id));
+    }
+
+    let issued = refresh_token_service
+        .issue(admin.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(token_pair_response(&token_service, &role_service, issued.admin_id, issued.token).await)
+}
+
+/// Finishes a login whose password expired by setting a new one.
+pub async fn login_password_handler(
+    State(RestState { admin_service, token_service, refresh_token_service, revocation_service, role_service, .. }): State<RestState>,
+    ValidatedJson(data): ValidatedJson<ValidatedExpiredPasswordRequest>,
+) -> Result<Response, Response> {
+    let claims = token_service
+        .decode_scoped(data.password_change_token, PASSWORD_CHANGE_SCOPE)
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+    let admin_id = claims
+        .admin_id()
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    admin_service
+        .set_password(admin_id, data.new_password)
+        .await
+        .map_err(|err| match err {
+            AdminServiceError::PasswordReused | AdminServiceError::PasswordBlocked => {
+                (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    // the password change token is single use, revoke it by `jti` since the
+    // revoke-all below misses tokens issued in the same second
+    revocation_service
+        .revoke_token(&claims)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    revocation_service
+        .revoke_all_for_admin(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    let issued = refresh_token_service
+        .issue(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(token_pair_response(&token_service, &role_service, issued.admin_id, issued.token).await)
+}
+
+fn password_change_response(token_service: &TokenService, admin_id: Uuid) -> Response {
+    match token_service.create_password_change_jwt(admin_id.to_string()) {
+        Ok(password_change_token) => (
+            StatusCode::OK,
+            Json(json!({ "password_expired": true, "password_change_token": password_change_token })),
+        )
+            .into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+fn login_status_error(err: AdminServiceError) -> Response {
+    match err {
+        AdminServiceError::EmailNotVerified => {
+            (StatusCode::FORBIDDEN, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        AdminServiceError::InvalidCredentials => (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response(),
+        _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Checks the admin like a login does, so a refresh token does not outlive an
+/// expired password.
+pub async fn refresh_token_handler(
+    State(RestState { admin_service, token_service, refresh_token_service, role_service, .. }): State<RestState>,
+    Json(RefreshTokenRequest { refresh_token }): Json<RefreshTokenRequest>,
+) -> Result<Response, Response> {
+    let issued = refresh_token_service
+        .rotate(refresh_token)
+        .await
+        .map_err(|err| match err {
+            RefreshTokenServiceError::InvalidToken | RefreshTokenServiceError::TokenReused => {
+                (StatusCode::UNAUTHORIZED, Json(json!({ "message": err.to_string() }))).into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    let status = admin_service.login_status(issued.admin_id).await;
+
+    if !matches!(status, Ok(LoginStatus::Authenticated)) {
+        // the rotated token is never handed out
+        refresh_token_service
+            .revoke(issued.token, issued.admin_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+        return match status {
+            Ok(_) => Ok(password_change_response(&token_service, issued.admin_id)),
+            Err(err) => Err(login_status_error(err)),
+        };
+    }
+
+    Ok(token_pair_response(&token_service, &role_service, issued.admin_id, issued.token).await)
+}
+
+pub async fn logout_handler(
+    State(RestState { token_service, refresh_token_service, revocation_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    request: Option<Json<LogoutRequest>>,
+) -> Result<Response, Response> {
+    let claims = token_service
+        .decode_jwt(bearer.token().to_string())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    revocation_service
+        .revoke_token(&claims)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    if let Some(Json(LogoutRequest { refresh_token: Some(refresh_token) })) = request {
+        let admin_id = claims
+            .admin_id()
+            .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+        refresh_token_service
+            .revoke(refresh_token, admin_id)
+            .await
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+    }
+
+    Ok(StatusCode::OK.into_response())
+}
+
+pub async fn revoke_sessions_handler(
+    State(RestState { token_service, revocation_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    revocation_service
+        .revoke_all_for_admin(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+async fn token_pair_response(
+    token_service: &TokenService,
+    role_service: &RoleService,
+    admin_id: Uuid,
+    refresh_token: String,
+) -> Response {
+    let roles = match role_service.roles_for(admin_id).await {
+        Ok(roles) => roles.iter().map(|role| role.to_string()).collect(),
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+
+    match token_service.create_jwt_with_roles(admin_id.to_string(), roles) {
+        Ok(token) => {
+            (StatusCode::OK, Json(json!({ "token": token, "refresh_token": refresh_token }))).into_response()
+        }
+        Err(_) => {
+            (StatusCode::BAD_REQUEST, Json(json!({ "message": "Failed to create authentication token" }))).into_response()
+        },
     }
 }
 
 pub async fn create_admin_handler(
-    State(RestState { admin_service, .. }): State<RestState>,
+    State(RestState { admin_service, token_service, role_service, email_verification_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
     ValidatedJson(data): ValidatedJson<ValidatedCreateAdminRequest>,
-) -> Response {
-    match admin_service.find_one(data.email.clone()).await {
-        Ok(None) => {
-            match admin_service
-                .register_admin(data.email, data.name, data.password)
-                .await
-            {
-                Ok(admin) => (StatusCode::OK, Json(admin)).into_response(),
-                Err(e) => (StatusCode::NOT_FOUND, format!("Failed to create admin: {}", e)).into_response(),
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    role_service
+        .require(admin_id, Permission::CreateAdmin)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if !data.roles.is_empty() {
+        role_service
+            .require(admin_id, Permission::AssignRoles)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    let email = data.email.clone();
+
+    match admin_service
+        .register_admin(data.email, data.name, data.password, data.roles)
+        .await
+    {
+        Ok(admin) => {
+            email_verification_service.spawn_verification(admin.email);
+        }
+        // answered like a success, so the endpoint does not reveal which emails exist
+        Err(AdminServiceError::EmailTaken) => {}
+        Err(err @ AdminServiceError::PasswordBlocked) => {
+            return Err((StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response());
+        }
+        Err(_) => return Err(StatusCode::INTERNAL_SERVER_ERROR.into_response()),
+    }
+
+    Ok(created_response(email))
+}
+
+/// Unlike creating an admin, tells whether the email was taken. Only the
+/// hash format is checked, the password is not known until the first login.
+pub async fn import_admin_handler(
+    State(RestState { admin_service, token_service, role_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    ValidatedJson(data): ValidatedJson<ValidatedImportAdminRequest>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    role_service
+        .require(admin_id, Permission::CreateAdmin)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    if !data.roles.is_empty() {
+        role_service
+            .require(admin_id, Permission::AssignRoles)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    let admin = admin_service
+        .import_admin(data.email, data.name, data.password_hash, data.roles)
+        .await
+        .map_err(|e| match e {
+            AdminServiceError::UnsupportedPasswordHash => {
+                (StatusCode::BAD_REQUEST, Json(json!({ "message": e.to_string() }))).into_response()
+            }
+            AdminServiceError::EmailTaken => {
+                (StatusCode::CONFLICT, Json(json!({ "message": e.to_string() }))).into_response()
             }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    Ok((StatusCode::CREATED, Json(json!({ "id": admin.id, "email": admin.email }))).into_response())
+}
+
+fn created_response(email: String) -> Response {
+    (
+        StatusCode::ACCEPTED,
+        Json(json!({
+            "email": email,
+            "message": "Unless the email is already in use, the admin was created and a verification link was sent",
+        })),
+    )
+        .into_response()
+}
+
+pub async fn setup_admin_handler(
+    State(RestState { setup_service, .. }): State<RestState>,
+    ValidatedJson(data): ValidatedJson<ValidatedSetupAdminRequest>,
+) -> Response {
+    match setup_service
+        .create_owner(data.setup_token, data.email, data.name, data.password)
+        .await
+    {
+        Ok(admin) => (StatusCode::OK, Json(admin)).into_response(),
+        Err(err @ SetupServiceError::InvalidToken) => {
+            (StatusCode::UNAUTHORIZED, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(err @ SetupServiceError::AlreadyInitialised) => {
+            (StatusCode::CONFLICT, Json(json!({ "message": err.to_string() }))).into_response()
         }
-        Ok(Some(_)) => (StatusCode::BAD_REQUEST, "Email already exists".to_string()).into_response(),
-        Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, format!("Database error: {}", e)).into_response(),
+        Err(err @ SetupServiceError::PasswordBlocked) => {
+            (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
     }
 }
 
 pub async fn read_admin_handler(
-    State(RestState { admin_service, token_service }): State<RestState>,
+    State(RestState { admin_service, token_service, .. }): State<RestState>,
     TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
 ) -> Result<Response, Response> {
-    let claims = token_service
+    let admin_id = token_service
         .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
         .map_err(|_| (StatusCode::UNAUTHORIZED, format!("Unauthenticated")).into_response())?;
     
     let admin = admin_service
-        .find_one(claims.sub)
+        .find_by_id(admin_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
         .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
@@ -81,45 +487,241 @@ pub async fn read_admin_handler(
 }
 
 pub async fn update_admin_handler(
-    State(RestState { admin_service, token_service }): State<RestState>,
+    State(RestState { admin_service, token_service, .. }): State<RestState>,
     TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
     ValidatedJson(data): ValidatedJson<ValidatedUpdateAdminRequest>,
 ) -> Result<Response, Response> {
-    let claims = token_service
+    let admin_id = token_service
         .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
         .map_err(|_| (StatusCode::UNAUTHORIZED, format!("Unauthenticated")).into_response())?;
-    
+
     let admin = admin_service
-        .find_one(claims.sub)
+        .update_one(admin_id, data.new_name)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
         .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
 
+    Ok((StatusCode::OK, Json(admin)).into_response())
+}
+
+/// Revokes every other session of the admin and returns a fresh token pair
+/// for the caller.
+pub async fn change_password_handler(
+    State(RestState { admin_service, token_service, refresh_token_service, revocation_service, role_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    ValidatedJson(data): ValidatedJson<ValidatedChangePasswordRequest>,
+) -> Result<Response, Response> {
+    let claims = token_service
+        .decode_jwt(bearer.token().to_string())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+    let admin_id = claims
+        .admin_id()
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    admin_service
+        .change_password(admin_id, data.current_password, data.new_password)
+        .await
+        .map_err(|err| match err {
+            AdminServiceError::InvalidCredentials
+            | AdminServiceError::PasswordReused
+            | AdminServiceError::PasswordBlocked => {
+                (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    // revoke-all misses tokens issued in the same second, so the presented
+    // one is revoked by `jti` as well
+    revocation_service
+        .revoke_token(&claims)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    revocation_service
+        .revoke_all_for_admin(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    let issued = refresh_token_service
+        .issue(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(token_pair_response(&token_service, &role_service, issued.admin_id, issued.token).await)
+}
+
+/// Always answers the same way, whether or not the email belongs to an admin.
+pub async fn request_password_reset_handler(
+    State(RestState { password_reset_service, .. }): State<RestState>,
+    Json(PasswordResetRequest { email }): Json<PasswordResetRequest>,
+) -> Response {
+    password_reset_service.spawn_reset(email);
+
+    (StatusCode::ACCEPTED, Json(json!({ "message": "If the email belongs to an admin, a reset link was sent" }))).into_response()
+}
+
+pub async fn confirm_password_reset_handler(
+    State(RestState { password_reset_service, revocation_service, .. }): State<RestState>,
+    ValidatedJson(data): ValidatedJson<ValidatedConfirmPasswordResetRequest>,
+) -> Result<Response, Response> {
+    let admin_id = password_reset_service
+        .confirm_reset(data.token, data.new_password)
+        .await
+        .map_err(|err| match err {
+            PasswordResetServiceError::OneTimeToken(OneTimeTokenServiceError::InvalidToken)
+            | PasswordResetServiceError::PasswordReused
+            | PasswordResetServiceError::PasswordBlocked => {
+                (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    // whoever knew the old password should not stay logged in
+    revocation_service
+        .revoke_all_for_admin(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(StatusCode::OK.into_response())
+}
+
+pub async fn verify_email_handler(
+    State(RestState { email_verification_service, .. }): State<RestState>,
+    Json(VerifyEmailRequest { token }): Json<VerifyEmailRequest>,
+) -> Response {
+    match email_verification_service.verify(token).await {
+        Ok(email) => (StatusCode::OK, Json(json!({ "email": email }))).into_response(),
+        Err(err @ EmailVerificationServiceError::OneTimeToken(OneTimeTokenServiceError::InvalidToken)) => {
+            (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+/// Always answers the same way, whether or not the email belongs to an admin.
+pub async fn resend_verification_handler(
+    State(RestState { email_verification_service, .. }): State<RestState>,
+    Json(ResendVerificationRequest { email }): Json<ResendVerificationRequest>,
+) -> Response {
+    email_verification_service.spawn_verification(email);
+
+    (StatusCode::ACCEPTED, Json(json!({ "message": "If the email needs verifying, a link was sent" }))).into_response()
+}
+
+/// Asks for the password again, so a stolen access token alone cannot move
+/// the account to another address. The email only changes once the link sent
+/// to the new address is used.
+pub async fn change_email_handler(
+    State(RestState { admin_service, token_service, email_verification_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    ValidatedJson(data): ValidatedJson<ValidatedChangeEmailRequest>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    admin_service
+        .verify_password(admin_id, data.password)
+        .await
+        .map_err(|err| match err {
+            AdminServiceError::InvalidCredentials => {
+                (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    email_verification_service
+        .request_email_change(admin_id, data.new_email)
+        .await
+        .map_err(|err| match err {
+            EmailVerificationServiceError::EmailTaken => {
+                (StatusCode::CONFLICT, Json(json!({ "message": err.to_string() }))).into_response()
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        })?;
+
+    Ok((StatusCode::ACCEPTED, Json(json!({ "message": "A confirmation link was sent to the new address" }))).into_response())
+}
+
+pub async fn confirm_email_change_handler(
+    State(RestState { email_verification_service, .. }): State<RestState>,
+    Json(ConfirmEmailChangeRequest { token }): Json<ConfirmEmailChangeRequest>,
+) -> Response {
+    match email_verification_service.confirm_email_change(token).await {
+        Ok(admin) => (StatusCode::OK, Json(json!({ "id": admin.id, "email": admin.email }))).into_response(),
+        Err(err @ EmailVerificationServiceError::OneTimeToken(OneTimeTokenServiceError::InvalidToken)) => {
+            (StatusCode::BAD_REQUEST, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(err @ EmailVerificationServiceError::EmailTaken) => {
+            (StatusCode::CONFLICT, Json(json!({ "message": err.to_string() }))).into_response()
+        }
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+pub async fn delete_admin_handler(
+    State(RestState { admin_service, token_service, revocation_service, role_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, format!("Unauthenticated")).into_response())?;
+
+    role_service
+        .require(admin_id, Permission::DeleteAdmin)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
     let admin = admin_service
-        .update_one(admin.email, data.new_name)
+        .find_by_id(admin_id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
         .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
 
-    Ok((StatusCode::OK, Json(admin)).into_response())
+    role_service
+        .delete_admin(admin.id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    revocation_service
+        .revoke_all_for_admin(admin.id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(StatusCode::OK.into_response())
 }
 
-pub async fn delete_admin_handler(
-    State(RestState { admin_service, token_service }): State<RestState>,
+pub async fn delete_other_admin_handler(
+    State(RestState { admin_service, token_service, revocation_service, role_service, .. }): State<RestState>,
     TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<Uuid>,
 ) -> Result<Response, Response> {
-    let claims = token_service
+    let admin_id = token_service
         .decode_jwt(bearer.token().to_string())
-        .map_err(|_| (StatusCode::UNAUTHORIZED, format!("Unauthenticated")).into_response())?;
-    
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    role_service
+        .require(admin_id, Permission::DeleteAdmin)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
     let admin = admin_service
-        .find_one(claims.sub)
+        .find_by_id(id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
         .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
 
-    admin_service
-        .delete_one(admin.email)
+    role_service
+        .delete_admin(admin.id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    revocation_service
+        .revoke_all_for_admin(admin.id)
         .await
         .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
 
@@ -1,7 +1,14 @@
-use argon2::{Argon2, PasswordHash, PasswordVerifier};
+use std::sync::Arc;
+
+use chrono::{Duration, Utc};
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::role::Role;
 
-use super::{AdminModel, AdminRepository, AdminRepositoryError};
+use super::{
+    AdminModel, AdminRepository, AdminRepositoryError, PasswordBlocklist, PasswordCheck, PasswordHashingError,
+};
 
 #[derive(Error, Debug)]
 pub enum AdminServiceError {
@@ -10,15 +17,75 @@ pub enum AdminServiceError {
 
     #[error("The provided credentials is incorrect")]
     InvalidCredentials,
+
+    #[error("The email address has not been verified")]
+    EmailNotVerified,
+
+    #[error("An admin with this email already exists")]
+    EmailTaken,
+
+    /// The email is only logged, as the message may reach the caller.
+    #[error("The stored password hash cannot be used")]
+    MalformedPasswordHash,
+
+    #[error("The password hash is not in a supported format")]
+    UnsupportedPasswordHash,
+
+    #[error("The new password was used recently, choose another one")]
+    PasswordReused,
+
+    #[error("Password is too common or has appeared in a data breach, choose another one")]
+    PasswordBlocked,
+
+    #[error("{0}")]
+    PasswordHashing(#[from] PasswordHashingError),
+}
+
+/// Outcome of a correct password.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoginStatus {
+    Authenticated,
+    /// Older than the maximum age, it must be changed before tokens are issued.
+    PasswordExpired,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthenticatedAdmin {
+    pub id: Uuid,
+    pub status: LoginStatus,
 }
 
 pub struct AdminService {
     repo: AdminRepository,
+    require_verified_email: bool,
+    password_max_age: Option<Duration>,
+    password_blocklist: Arc<PasswordBlocklist>,
 }
 
 impl AdminService {
     pub fn new(repo: AdminRepository) -> Self {
-        Self { repo }
+        Self {
+            repo,
+            require_verified_email: false,
+            password_max_age: None,
+            password_blocklist: Arc::new(PasswordBlocklist::default()),
+        }
+    }
+
+    pub fn with_require_verified_email(mut self, require_verified_email: bool) -> Self {
+        self.require_verified_email = require_verified_email;
+        self
+    }
+
+    /// `None` never expires passwords.
+    pub fn with_password_max_age(mut self, password_max_age: Option<Duration>) -> Self {
+        self.password_max_age = password_max_age;
+        self
+    }
+
+    pub fn with_password_blocklist(mut self, password_blocklist: Arc<PasswordBlocklist>) -> Self {
+        self.password_blocklist = password_blocklist;
+        self
     }
 
     pub async fn register_admin(
@@ -26,36 +93,178 @@ impl AdminService {
         email: String,
         name: String,
         password: String,
+        roles: Vec<Role>,
     ) -> Result<AdminModel, AdminServiceError> {
-        Ok(self.repo.create(email, name, password).await?)
+        if self.password_blocklist.contains(&password).await {
+            return Err(AdminServiceError::PasswordBlocked);
+        }
+
+        if self.repo.find_one(email.clone()).await?.is_some() {
+            // creating an admin is dominated by hashing the password, so
+            // spend the same time when the email is taken
+            self.repo.password_hashing().verify_dummy(&password);
+            return Err(AdminServiceError::EmailTaken);
+        }
+
+        let roles = roles.iter().map(|role| role.to_string()).collect();
+
+        Ok(self.repo.create_with_roles(email, name, password, roles).await?)
+    }
+
+    /// Takes the password hash from another system, which must be Argon2,
+    /// bcrypt or PBKDF2 in PHC format.
+    pub async fn import_admin(
+        &self,
+        email: String,
+        name: String,
+        password_hash: String,
+        roles: Vec<Role>,
+    ) -> Result<AdminModel, AdminServiceError> {
+        if !self.repo.password_hashing().is_supported(&password_hash) {
+            return Err(AdminServiceError::UnsupportedPasswordHash);
+        }
+
+        if self.repo.find_one(email.clone()).await?.is_some() {
+            return Err(AdminServiceError::EmailTaken);
+        }
+
+        let roles = roles.iter().map(|role| role.to_string()).collect();
+
+        Ok(self.repo.import(email, name, password_hash, roles).await?)
     }
 
     pub async fn find_one(&self, email: String) -> Result<Option<AdminModel>, AdminServiceError> {
         Ok(self.repo.find_one(email).await?)
     }
 
-    pub async fn update_one(&self, email: String, name: String) -> Result<Option<AdminModel>, AdminServiceError> {
-        Ok(self.repo.update_one(email, name).await?)
+    pub async fn find_by_id(&self, id: Uuid) -> Result<Option<AdminModel>, AdminServiceError> {
+        Ok(self.repo.find_by_id(id).await?)
+    }
+
+    pub async fn update_one(&self, id: Uuid, name: String) -> Result<Option<AdminModel>, AdminServiceError> {
+        Ok(self.repo.update_one(id, name).await?)
+    }
+
+    /// Requires the current password even though the caller is authenticated,
+    /// so a stolen access token alone cannot take over the account.
+    pub async fn change_password(
+        &self,
+        id: Uuid,
+        current_password: String,
+        new_password: String,
+    ) -> Result<(), AdminServiceError> {
+        self.verify_password(id, current_password).await?;
+        self.set_password(id, new_password).await
+    }
+
+    /// Sets a new password without asking for the current one, for callers
+    /// that already proved it, like a login with an expired password.
+    pub async fn set_password(&self, id: Uuid, new_password: String) -> Result<(), AdminServiceError> {
+        if self.password_blocklist.contains(&new_password).await {
+            return Err(AdminServiceError::PasswordBlocked);
+        }
+
+        if self.repo.is_password_reused(id, &new_password).await? {
+            return Err(AdminServiceError::PasswordReused);
+        }
+
+        self.repo.update_password(id, new_password).await?;
+
+        Ok(())
+    }
+
+    /// Checks an admin that proved who they are another way, like a second
+    /// factor or a refresh token, the same as `authenticate` would.
+    pub async fn login_status(&self, id: Uuid) -> Result<LoginStatus, AdminServiceError> {
+        let admin = self
+            .repo
+            .find_by_id(id)
+            .await?
+            .ok_or(AdminServiceError::InvalidCredentials)?;
+
+        self.login_status_of(&admin)
     }
 
-    pub async fn delete_one(&self, email: String) -> Result<(), AdminServiceError> {
-        Ok(self.repo.delete_one(email).await?)
+    fn login_status_of(&self, admin: &AdminModel) -> Result<LoginStatus, AdminServiceError> {
+        if self.require_verified_email && admin.email_verified_at.is_none() {
+            return Err(AdminServiceError::EmailNotVerified);
+        }
+
+        let expired = self
+            .password_max_age
+            .is_some_and(|max_age| admin.password_changed_at + max_age <= Utc::now());
+
+        match expired {
+            true => Ok(LoginStatus::PasswordExpired),
+            false => Ok(LoginStatus::Authenticated),
+        }
     }
 
+    /// Hashes stored with outdated parameters or imported from another
+    /// system are replaced on success.
     pub async fn authenticate(
         &self,
         email: String,
         password: String,
-    ) -> Result<(), AdminServiceError> {
-        match self.repo.find_one(email).await? {
-            Some(admin) => {
-                let hashed = PasswordHash::new(&admin.password).unwrap();
-                Argon2::default()
-                    .verify_password(password.as_bytes(), &hashed)
-                    .map_err(|_| AdminServiceError::InvalidCredentials)
+    ) -> Result<AuthenticatedAdmin, AdminServiceError> {
+        let Some(admin) = self.repo.find_one(email).await? else {
+            // takes as long as a wrong password, so it does not reveal which emails exist
+            self.repo.password_hashing().verify_dummy(&password);
+            return Err(AdminServiceError::InvalidCredentials);
+        };
+
+        self.check_password(admin, password).await
+    }
+
+    /// Like `authenticate`, for an admin that is already known, e.g. one
+    /// confirming a sensitive change.
+    pub async fn verify_password(
+        &self,
+        id: Uuid,
+        password: String,
+    ) -> Result<AuthenticatedAdmin, AdminServiceError> {
+        let Some(admin) = self.repo.find_by_id(id).await? else {
+            self.repo.password_hashing().verify_dummy(&password);
+            return Err(AdminServiceError::InvalidCredentials);
+        };
+
+        self.check_password(admin, password).await
+    }
+
+    async fn check_password(
+        &self,
+        admin: AdminModel,
+        password: String,
+    ) -> Result<AuthenticatedAdmin, AdminServiceError> {
+        let check = match self.repo.password_hashing().verify(&password, &admin.password) {
+            Ok(check) => check,
+            Err(e) if e.is_malformed_hash() => {
+                tracing::error!("Stored password hash of {} is malformed: {}", admin.email, e);
+                return Err(AdminServiceError::MalformedPasswordHash);
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        match check {
+            PasswordCheck::Invalid => return Err(AdminServiceError::InvalidCredentials),
+            PasswordCheck::Valid => {}
+            PasswordCheck::ValidNeedsRehash => {
+                // the login itself succeeded, so a failed upgrade is retried next time
+                let result = self
+                    .repo
+                    .rehash_password(admin.id, admin.password.clone(), password)
+                    .await;
+
+                if let Err(e) = result {
+                    tracing::warn!("Failed to rehash password of {}: {}", admin.email, e);
+                }
             }
-            None => Err(AdminServiceError::InvalidCredentials),
         }
+
+        // only checked after the password, so it does not reveal which emails exist
+        let status = self.login_status_of(&admin)?;
+
+        Ok(AuthenticatedAdmin { id: admin.id, status })
     }
 }
 
@@ -63,7 +272,12 @@ impl AdminService {
 mod tests {
     use crate::database::setup_test_db;
 
-    use super::{AdminRepository, AdminService};
+    use std::sync::Arc;
+
+    use crate::admin::PasswordHashing;
+    use crate::config::PasswordConfig;
+
+    use super::{AdminRepository, AdminService, AdminServiceError, LoginStatus};
 
     const EMAIL: &str = "asdf@gmail.com";
     const NAME: &str = "asdf";
@@ -79,7 +293,7 @@ mod tests {
         let repo = AdminRepository::new(test_db.pool);
         let serv = AdminService::new(repo);
 
-        let result = serv.register_admin(email, name, password).await.unwrap();
+        let result = serv.register_admin(email, name, password, Vec::new()).await.unwrap();
 
         assert_eq!(result.email, EMAIL.to_string());
     }
@@ -116,4 +330,243 @@ mod tests {
 
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn test_change_password() {
+        let email = EMAIL.to_string();
+        let name = NAME.to_string();
+        let password = PASSWORD.to_string();
+        let new_password = "NewPassword123!".to_string();
+        let test_db = setup_test_db().await;
+
+        let repo = AdminRepository::new(test_db.pool);
+        let admin = repo.create(email.clone(), name.clone(), password.clone()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+        serv.change_password(admin.id, password.clone(), new_password.clone()).await.unwrap();
+
+        assert!(serv.authenticate(email.clone(), password).await.is_err());
+        assert!(serv.authenticate(email, new_password).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_change_password_wrong_current() {
+        let email = EMAIL.to_string();
+        let name = NAME.to_string();
+        let password = PASSWORD.to_string();
+        let test_db = setup_test_db().await;
+
+        let repo = AdminRepository::new(test_db.pool);
+        let admin = repo.create(email.clone(), name.clone(), password.clone()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+        let result = serv
+            .change_password(admin.id, "wrong".to_string(), "NewPassword123!".to_string())
+            .await;
+
+        assert!(result.is_err());
+        assert!(serv.authenticate(email, password).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_unverified() {
+        let email = EMAIL.to_string();
+        let name = NAME.to_string();
+        let password = PASSWORD.to_string();
+        let test_db = setup_test_db().await;
+
+        let repo = AdminRepository::new(test_db.pool.clone());
+        let admin = repo.create(email.clone(), name.clone(), password.clone()).await.unwrap();
+
+        let serv = AdminService::new(repo).with_require_verified_email(true);
+        let result = serv.authenticate(email.clone(), password.clone()).await;
+        assert!(matches!(result, Err(AdminServiceError::EmailNotVerified)));
+        let result = serv.login_status(admin.id).await;
+        assert!(matches!(result, Err(AdminServiceError::EmailNotVerified)));
+
+        AdminRepository::new(test_db.pool).mark_email_verified(admin.id, email.clone()).await.unwrap();
+        assert!(serv.authenticate(email, password).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_unverified_not_required() {
+        let email = EMAIL.to_string();
+        let name = NAME.to_string();
+        let password = PASSWORD.to_string();
+        let test_db = setup_test_db().await;
+
+        let repo = AdminRepository::new(test_db.pool);
+        repo.create(email.clone(), name.clone(), password.clone()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+
+        assert!(serv.authenticate(email, password).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_unknown_email() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        let serv = AdminService::new(repo);
+
+        let result = serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await;
+
+        assert!(matches!(result, Err(AdminServiceError::InvalidCredentials)));
+    }
+
+    #[tokio::test]
+    async fn test_register_taken_email() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        let serv = AdminService::new(repo);
+
+        serv.register_admin(EMAIL.to_string(), NAME.to_string(), PASSWORD.to_string(), Vec::new()).await.unwrap();
+        let result = serv
+            .register_admin(EMAIL.to_string(), "other".to_string(), "other-password".to_string(), Vec::new())
+            .await;
+
+        assert!(matches!(result, Err(AdminServiceError::EmailTaken)));
+        assert!(serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_blocked_password() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        let admin = repo.create(EMAIL.to_string(), NAME.to_string(), PASSWORD.to_string()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+
+        let result = serv
+            .register_admin("other@gmail.com".to_string(), NAME.to_string(), "Password123!".to_string(), Vec::new())
+            .await;
+        assert!(matches!(result, Err(AdminServiceError::PasswordBlocked)));
+
+        let result = serv.set_password(admin.id, "Password123!".to_string()).await;
+        assert!(matches!(result, Err(AdminServiceError::PasswordBlocked)));
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_rehashes_outdated_hash() {
+        let test_db = setup_test_db().await;
+        let hashing = |memory_kib| {
+            let config = PasswordConfig { memory_kib, iterations: 1, ..PasswordConfig::default() };
+            Arc::new(PasswordHashing::from_config(&config).unwrap())
+        };
+
+        AdminRepository::new(test_db.pool.clone())
+            .with_password_hashing(hashing(1024))
+            .create(EMAIL.to_string(), NAME.to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let repo = AdminRepository::new(test_db.pool.clone()).with_password_hashing(hashing(2048));
+        let serv = AdminService::new(repo);
+
+        serv.authenticate(EMAIL.to_string(), "wrong".to_string()).await.unwrap_err();
+        let admin = serv.find_one(EMAIL.to_string()).await.unwrap().unwrap();
+        assert!(admin.password.contains("m=1024"));
+
+        serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.unwrap();
+        let admin = serv.find_one(EMAIL.to_string()).await.unwrap().unwrap();
+        assert!(admin.password.contains("m=2048"));
+
+        serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_malformed_hash() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        repo.import(EMAIL.to_string(), NAME.to_string(), "corrupt".to_string(), Vec::new()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+        let result = serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await;
+
+        assert!(matches!(result, Err(AdminServiceError::MalformedPasswordHash)));
+    }
+
+    #[tokio::test]
+    async fn test_import_admin_migrates_bcrypt_hash() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        let serv = AdminService::new(repo);
+
+        let result = serv
+            .import_admin(EMAIL.to_string(), NAME.to_string(), "plaintext".to_string(), Vec::new())
+            .await;
+        assert!(matches!(result, Err(AdminServiceError::UnsupportedPasswordHash)));
+
+        let hash = bcrypt::hash(PASSWORD, 4).unwrap();
+        serv.import_admin(EMAIL.to_string(), NAME.to_string(), hash.clone(), Vec::new()).await.unwrap();
+
+        let result = serv.import_admin(EMAIL.to_string(), NAME.to_string(), hash, Vec::new()).await;
+        assert!(matches!(result, Err(AdminServiceError::EmailTaken)));
+
+        serv.authenticate(EMAIL.to_string(), "wrong".to_string()).await.unwrap_err();
+        serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.unwrap();
+
+        let admin = serv.find_one(EMAIL.to_string()).await.unwrap().unwrap();
+        assert!(admin.password.starts_with("$argon2id$"));
+    }
+
+    #[tokio::test]
+    async fn test_change_password_rejects_reuse() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool).with_password_history(1);
+        let admin = repo.create(EMAIL.to_string(), NAME.to_string(), PASSWORD.to_string()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+        let result = serv
+            .change_password(admin.id, PASSWORD.to_string(), PASSWORD.to_string())
+            .await;
+        assert!(matches!(result, Err(AdminServiceError::PasswordReused)));
+
+        serv.change_password(admin.id, PASSWORD.to_string(), "second".to_string()).await.unwrap();
+        serv.change_password(admin.id, "second".to_string(), "third".to_string()).await.unwrap();
+
+        // only one previous password is kept
+        let result = serv.change_password(admin.id, "third".to_string(), "second".to_string()).await;
+        assert!(matches!(result, Err(AdminServiceError::PasswordReused)));
+        serv.change_password(admin.id, "third".to_string(), PASSWORD.to_string()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_authenticate_expired_password() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        let admin = repo.create(EMAIL.to_string(), NAME.to_string(), PASSWORD.to_string()).await.unwrap();
+
+        let serv = AdminService::new(repo).with_password_max_age(Some(chrono::Duration::zero()));
+
+        let authenticated = serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.unwrap();
+        assert_eq!(authenticated.id, admin.id);
+        assert_eq!(authenticated.status, LoginStatus::PasswordExpired);
+        assert_eq!(serv.login_status(admin.id).await.unwrap(), LoginStatus::PasswordExpired);
+
+        // a wrong password is still rejected
+        let result = serv.authenticate(EMAIL.to_string(), "wrong".to_string()).await;
+        assert!(matches!(result, Err(AdminServiceError::InvalidCredentials)));
+
+        let serv = serv.with_password_max_age(Some(chrono::Duration::days(90)));
+        let authenticated = serv.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.unwrap();
+        assert_eq!(authenticated.status, LoginStatus::Authenticated);
+    }
+
+    #[tokio::test]
+    async fn test_verify_password() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool);
+        let admin = repo.create(EMAIL.to_string(), NAME.to_string(), PASSWORD.to_string()).await.unwrap();
+
+        let serv = AdminService::new(repo);
+
+        assert_eq!(serv.verify_password(admin.id, PASSWORD.to_string()).await.unwrap().id, admin.id);
+
+        let result = serv.verify_password(admin.id, "wrong".to_string()).await;
+        assert!(matches!(result, Err(AdminServiceError::InvalidCredentials)));
+
+        let result = serv.verify_password(uuid::Uuid::new_v4(), PASSWORD.to_string()).await;
+        assert!(matches!(result, Err(AdminServiceError::InvalidCredentials)));
+    }
 }
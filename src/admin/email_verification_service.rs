@@ -0,0 +1,363 @@
+use std::sync::Arc;
+
+use chrono::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::mail::{Email, Mailer, MailerError};
+use crate::token::{OneTimeTokenService, OneTimeTokenServiceError, TokenPurpose};
+
+use super::{AdminModel, AdminRepository, AdminRepositoryError};
+
+#[derive(Error, Debug)]
+pub enum EmailVerificationServiceError {
+    #[error("{0}")]
+    Repository(#[from] AdminRepositoryError),
+
+    #[error("{0}")]
+    OneTimeToken(#[from] OneTimeTokenServiceError),
+
+    #[error("{0}")]
+    Mailer(#[from] MailerError),
+
+    #[error("An admin with this email already exists")]
+    EmailTaken,
+
+    #[error("Admin not found")]
+    AdminNotFound,
+}
+
+pub struct EmailVerificationService {
+    repo: AdminRepository,
+    one_time_token_service: OneTimeTokenService,
+    mailer: Arc<dyn Mailer>,
+    verify_url: String,
+    email_change_url: String,
+    ttl: Duration,
+}
+
+impl EmailVerificationService {
+    pub fn new(
+        repo: AdminRepository,
+        one_time_token_service: OneTimeTokenService,
+        mailer: Arc<dyn Mailer>,
+    ) -> Self {
+        Self {
+            repo,
+            one_time_token_service,
+            mailer,
+            verify_url: String::new(),
+            email_change_url: String::new(),
+            ttl: Duration::hours(24),
+        }
+    }
+
+    pub fn with_verify_url(mut self, verify_url: String) -> Self {
+        self.verify_url = verify_url;
+        self
+    }
+
+    pub fn with_email_change_url(mut self, email_change_url: String) -> Self {
+        self.email_change_url = email_change_url;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Sends the verification link off the request path, so the answer does not
+    /// wait on the mail server and its timing cannot tell whether the email
+    /// belongs to an unverified admin. Failures are only logged.
+    pub fn spawn_verification(self: &Arc<Self>, email: String) -> JoinHandle<()> {
+        let service = self.clone();
+
+        tokio::spawn(async move {
+            // the admin can ask for another link if this one is lost
+            if let Err(e) = service.send_verification(email).await {
+                tracing::error!("Failed to send verification email: {}", e);
+            }
+        })
+    }
+
+    /// Emails a verification link. Does nothing for unknown or already verified
+    /// emails, so it is safe to expose without authentication.
+    pub async fn send_verification(&self, email: String) -> Result<(), EmailVerificationServiceError> {
+        let Some(admin) = self.repo.find_one(email).await? else {
+            return Ok(());
+        };
+
+        if admin.email_verified_at.is_some() {
+            return Ok(());
+        }
+
+        let token = self
+            .one_time_token_service
+            .issue(admin.id, admin.email.clone(), TokenPurpose::EmailVerification, self.ttl)
+            .await?;
+
+        self.mailer
+            .send(Email {
+                to: admin.email,
+                subject: "Verify your email address".to_string(),
+                body: format!(
+                    "Hi {},\n\nUse the link below to verify your email address. It expires in {} hours.\n\n{}{}\n",
+                    admin.name,
+                    self.ttl.num_hours(),
+                    self.verify_url,
+                    token
+                ),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the email that was verified. A link sent to an address the
+    /// admin has since moved away from is rejected.
+    pub async fn verify(&self, token: String) -> Result<String, EmailVerificationServiceError> {
+        let token = self
+            .one_time_token_service
+            .consume(token, TokenPurpose::EmailVerification)
+            .await?;
+
+        self.repo
+            .mark_email_verified(token.admin_id, token.email.clone())
+            .await?
+            .ok_or(OneTimeTokenServiceError::InvalidToken)?;
+
+        Ok(token.email)
+    }
+
+    /// Emails a confirmation link to `new_email`. The email only changes once
+    /// the link is used, which proves the admin controls the new address.
+    pub async fn request_email_change(
+        &self,
+        admin_id: Uuid,
+        new_email: String,
+    ) -> Result<(), EmailVerificationServiceError> {
+        let admin = self
+            .repo
+            .find_by_id(admin_id)
+            .await?
+            .ok_or(EmailVerificationServiceError::AdminNotFound)?;
+
+        if self.repo.find_one(new_email.clone()).await?.is_some() {
+            return Err(EmailVerificationServiceError::EmailTaken);
+        }
+
+        let token = self
+            .one_time_token_service
+            .issue(admin.id, new_email.clone(), TokenPurpose::EmailChange, self.ttl)
+            .await?;
+
+        self.mailer
+            .send(Email {
+                to: new_email,
+                subject: "Confirm your new email address".to_string(),
+                body: format!(
+                    "Hi {},\n\nUse the link below to make this your email address. It expires in {} hours.\n\n{}{}\n\nIf you did not ask for this, you can ignore this email.\n",
+                    admin.name,
+                    self.ttl.num_hours(),
+                    self.email_change_url,
+                    token
+                ),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Moves the admin to the address the token was sent to, which counts as
+    /// verified. Links sent to the old address stop working and the old
+    /// address is told about the change.
+    pub async fn confirm_email_change(&self, token: String) -> Result<AdminModel, EmailVerificationServiceError> {
+        let token = self
+            .one_time_token_service
+            .consume(token, TokenPurpose::EmailChange)
+            .await?;
+
+        let old_email = self
+            .repo
+            .find_by_id(token.admin_id)
+            .await?
+            .ok_or(OneTimeTokenServiceError::InvalidToken)?
+            .email;
+
+        let admin = match self.repo.change_email(token.admin_id, token.email).await {
+            Ok(admin) => admin.ok_or(OneTimeTokenServiceError::InvalidToken)?,
+            // someone else took the address since the link was sent
+            Err(e) if e.is_unique_violation() => return Err(EmailVerificationServiceError::EmailTaken),
+            Err(e) => return Err(e.into()),
+        };
+
+        for purpose in [TokenPurpose::PasswordReset, TokenPurpose::EmailVerification] {
+            self.one_time_token_service.invalidate(admin.id, purpose).await?;
+        }
+
+        // the change already happened, a lost notice should not report a failure
+        let notice = Email {
+            to: old_email,
+            subject: "Your email address was changed".to_string(),
+            body: format!(
+                "Hi {},\n\nThe email address of your account was changed to {}.\n\nIf you did not do this, contact another admin right away.\n",
+                admin.name, admin.email
+            ),
+        };
+        if let Err(e) = self.mailer.send(notice).await {
+            tracing::error!("Failed to send email change notice: {}", e);
+        }
+
+        Ok(admin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::{AdminService, AdminServiceError};
+    use crate::database::setup_test_db;
+    use crate::mail::InMemoryMailer;
+    use crate::token::OneTimeTokenRepository;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+    const NEW_EMAIL: &str = "new@gmail.com";
+    const PASSWORD: &str = "HelloWorld123!";
+
+    fn setup_service(pool: sqlx::PgPool, mailer: Arc<InMemoryMailer>) -> EmailVerificationService {
+        EmailVerificationService::new(
+            AdminRepository::new(pool.clone()),
+            OneTimeTokenService::new(OneTimeTokenRepository::new(pool)),
+            mailer,
+        )
+        .with_verify_url("https://sigma.local/verify?token=".to_string())
+        .with_email_change_url("https://sigma.local/confirm-email-change?token=".to_string())
+    }
+
+    fn token_from(email: &Email) -> String {
+        let start = email.body.find("token=").unwrap() + "token=".len();
+        email.body[start..].split_whitespace().next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_verify_then_login() {
+        let test_db = setup_test_db().await;
+        AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let admin_service = AdminService::new(AdminRepository::new(test_db.pool.clone()))
+            .with_require_verified_email(true);
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        let result = admin_service.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await;
+        assert!(matches!(result, Err(AdminServiceError::EmailNotVerified)));
+
+        service.send_verification(EMAIL.to_string()).await.unwrap();
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+
+        let email = service.verify(token_from(&sent[0])).await.unwrap();
+        assert_eq!(email, EMAIL);
+
+        assert!(admin_service.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_invalid_token() {
+        let test_db = setup_test_db().await;
+        let service = setup_service(test_db.pool.clone(), Arc::default());
+
+        let result = service.verify("not-a-token".to_string()).await;
+
+        assert!(matches!(
+            result,
+            Err(EmailVerificationServiceError::OneTimeToken(OneTimeTokenServiceError::InvalidToken))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_already_verified() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool.clone());
+        let admin = repo
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+        repo.mark_email_verified(admin.id, EMAIL.to_string()).await.unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        service.send_verification(EMAIL.to_string()).await.unwrap();
+
+        assert!(mailer.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_change_email() {
+        let test_db = setup_test_db().await;
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        // a verification link for the old address stops working
+        service.send_verification(EMAIL.to_string()).await.unwrap();
+        let verification_token = token_from(&mailer.sent()[0]);
+
+        service.request_email_change(admin.id, NEW_EMAIL.to_string()).await.unwrap();
+        let sent = mailer.sent();
+        assert_eq!(sent[1].to, NEW_EMAIL);
+
+        let changed = service.confirm_email_change(token_from(&sent[1])).await.unwrap();
+        assert_eq!(changed.id, admin.id);
+        assert_eq!(changed.email, NEW_EMAIL);
+        assert!(changed.email_verified_at.is_some());
+
+        // the old address is told
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 3);
+        assert_eq!(sent[2].to, EMAIL);
+
+        assert!(service.verify(verification_token).await.is_err());
+
+        let admin_service = AdminService::new(AdminRepository::new(test_db.pool.clone()));
+        assert!(admin_service.authenticate(EMAIL.to_string(), PASSWORD.to_string()).await.is_err());
+        assert!(admin_service.authenticate(NEW_EMAIL.to_string(), PASSWORD.to_string()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_change_email_taken() {
+        let test_db = setup_test_db().await;
+        let repo = AdminRepository::new(test_db.pool.clone());
+        let admin = repo
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        service.request_email_change(admin.id, NEW_EMAIL.to_string()).await.unwrap();
+
+        // the address is taken between sending the link and using it
+        repo.create(NEW_EMAIL.to_string(), "other".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let result = service.confirm_email_change(token_from(&mailer.sent()[0])).await;
+        assert!(matches!(result, Err(EmailVerificationServiceError::EmailTaken)));
+
+        let result = service.request_email_change(admin.id, NEW_EMAIL.to_string()).await;
+        assert!(matches!(result, Err(EmailVerificationServiceError::EmailTaken)));
+    }
+}
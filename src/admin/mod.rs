@@ -7,42 +7,155 @@ mod admin_model;
 mod admin_repository;
 mod admin_rest;
 mod admin_service;
+mod email_verification_service;
+mod password_blocklist;
+mod password_hashing;
+mod password_reset_service;
+mod setup_service;
 
 pub use admin_grpc::*;
 pub use admin_model::*;
 pub use admin_repository::*;
 pub use admin_rest::*;
 pub use admin_service::*;
+pub use email_verification_service::*;
+pub use password_blocklist::*;
+pub use password_hashing::*;
+pub use password_reset_service::*;
+pub use setup_service::*;
 
 #[cfg(test)]
 mod tests {
+    use std::str::FromStr;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use async_trait::async_trait;
+    use sqlx::PgPool;
+    use tokio::sync::Semaphore;
     use tonic::Request;
+    use tonic::service::Interceptor;
+    use uuid::Uuid;
 
     use crate::database;
-    use crate::token::TokenService;
+    use crate::lockout::{LockoutRepository, LockoutService};
+    use crate::mail::{Email, InMemoryMailer, Mailer, MailerError};
+    use crate::mfa::{MfaRepository, MfaService};
+    use crate::role::{Role, RoleRepository, RoleService};
+    use crate::token::{
+        AuthInterceptor, OneTimeTokenRepository, OneTimeTokenService, PASSWORD_CHANGE_SCOPE,
+        RefreshTokenRepository, RefreshTokenService, RevocationRepository, RevocationService, TokenService,
+        TokenServiceError,
+    };
 
     use super::*;
     use super::proto::{self, admin_service_server::AdminService as _};
 
+    fn setup_grpc(pool: PgPool, token_service: Arc<TokenService>) -> AdminGrpc {
+        setup_grpc_with(pool.clone(), token_service, AdminService::new(AdminRepository::new(pool)))
+    }
+
+    fn setup_grpc_with(pool: PgPool, token_service: Arc<TokenService>, admin_service: AdminService) -> AdminGrpc {
+        setup_grpc_with_mailer(pool, token_service, admin_service, Arc::new(InMemoryMailer::default()))
+    }
+
+    fn setup_grpc_with_mailer(
+        pool: PgPool,
+        token_service: Arc<TokenService>,
+        admin_service: AdminService,
+        mailer: Arc<dyn Mailer>,
+    ) -> AdminGrpc {
+        AdminGrpc::new(
+            admin_service,
+            RoleService::new(RoleRepository::new(pool.clone())),
+            token_service.clone(),
+            RefreshTokenService::new(RefreshTokenRepository::new(pool.clone())),
+            RevocationService::new(
+                RevocationRepository::new(pool.clone()),
+                RefreshTokenRepository::new(pool.clone()),
+                token_service.revocations(),
+            ),
+            Arc::new(EmailVerificationService::new(
+                AdminRepository::new(pool.clone()),
+                OneTimeTokenService::new(OneTimeTokenRepository::new(pool.clone())),
+                mailer,
+            )),
+            MfaService::new(MfaRepository::new(pool.clone()), "sigma".to_string()),
+            LockoutService::new(LockoutRepository::new(pool)),
+        )
+    }
+
+    /// Holds every email until released, like a mail server that is slow to answer.
+    struct HeldMailer {
+        release: Semaphore,
+        sent: InMemoryMailer,
+    }
+
+    impl Default for HeldMailer {
+        fn default() -> Self {
+            Self { release: Semaphore::new(0), sent: InMemoryMailer::default() }
+        }
+    }
+
+    #[async_trait]
+    impl Mailer for HeldMailer {
+        async fn send(&self, email: Email) -> Result<(), MailerError> {
+            self.release.acquire().await.unwrap().forget();
+            self.sent.send(email).await
+        }
+    }
+
+    /// Runs the request through `AuthInterceptor` as the server would.
+    fn authenticated<T>(token_service: &Arc<TokenService>, admin_id: Uuid, message: T) -> Request<T> {
+        let token = token_service.create_jwt(admin_id.to_string()).unwrap();
+
+        authenticated_with(token_service, &token, message)
+    }
+
+    fn authenticated_with<T>(token_service: &Arc<TokenService>, token: &str, message: T) -> Request<T> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {token}").parse().unwrap());
+
+        let request = AuthInterceptor::new(token_service.clone()).call(request).unwrap();
+        let (metadata, extensions, _) = request.into_parts();
+
+        Request::from_parts(metadata, extensions, message)
+    }
+
+    async fn create_owner(pool: &PgPool, email: &str) -> Uuid {
+        let admin = AdminRepository::new(pool.clone())
+            .create(email.to_string(), "owner".to_string(), "HelloWorld123!".to_string())
+            .await
+            .unwrap();
+
+        RoleService::new(RoleRepository::new(pool.clone()))
+            .assign_roles(admin.id, vec![Role::Owner])
+            .await
+            .unwrap();
+
+        admin.id
+    }
+
     #[tokio::test]
     async fn test_verify_success() {
         let test_db = database::setup_test_db().await;
         let admin_repository = AdminRepository::new(test_db.pool.clone());
 
         // first, create admin
-        admin_repository.create(
+        let admin = admin_repository.create(
             "test@example.com".to_string(),
             "test".to_string(),
             "HelloWorld123!".to_string()
         ).await.unwrap();
 
-        let admin_service = AdminService::new(admin_repository);
-        let token_service = TokenService::new("asdf".to_string(), "asdf".to_string());
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
 
         // second, create token
-        let token = token_service.create_jwt("test@example.com".to_string()).unwrap();
+        let token = token_service.create_jwt(admin.id.to_string()).unwrap();
 
-        let admin_grpc = AdminGrpc::new(admin_service, token_service);
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service);
 
         // third, verify the token
         let result = admin_grpc.verify_admin(Request::new(proto::TokenRequest {
@@ -56,14 +169,12 @@ mod tests {
     #[tokio::test]
     async fn test_verify_fail_using_random_jwt() {
         let test_db = database::setup_test_db().await;
-        let admin_repository = AdminRepository::new(test_db.pool.clone());
-        let admin_service = AdminService::new(admin_repository);
-        let token_service = TokenService::new("asdf".to_string(), "asdf".to_string());
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
 
         // first, create the random token
-        let token = token_service.create_jwt("asdfadfasdf".to_string()).unwrap();
+        let token = token_service.create_jwt(Uuid::new_v4().to_string()).unwrap();
 
-        let admin_grpc = AdminGrpc::new(admin_service, token_service);
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service);
 
         // second, verify the token
         let result = admin_grpc.verify_admin(Request::new(proto::TokenRequest {
@@ -75,4 +186,493 @@ mod tests {
         assert_eq!(error.code(), tonic::Code::NotFound);
         assert_eq!(error.message(), "Admin not found".to_string());
     }
+
+    #[tokio::test]
+    async fn test_verify_returns_roles() {
+        let test_db = database::setup_test_db().await;
+        let admin_repository = AdminRepository::new(test_db.pool.clone());
+
+        let admin = admin_repository.create(
+            "test@example.com".to_string(),
+            "test".to_string(),
+            "HelloWorld123!".to_string()
+        ).await.unwrap();
+
+        let role_service = RoleService::new(RoleRepository::new(test_db.pool.clone()));
+        role_service.assign_roles(admin.id, vec![Role::Waiter]).await.unwrap();
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let token = token_service.create_jwt(admin.id.to_string()).unwrap();
+
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service);
+
+        let result = admin_grpc.verify_admin(Request::new(proto::TokenRequest {
+            token,
+        })).await.unwrap();
+
+        let admin = result.into_inner().admin.unwrap();
+        assert_eq!(admin.roles, vec!["waiter".to_string()]);
+        assert_eq!(admin.permissions, vec!["table_session:manage".to_string()]);
+    }
+
+    #[tokio::test]
+    async fn test_create_admin_as_owner() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, owner_id, proto::CreateAdminRequest {
+            email: "new@example.com".to_string(),
+            name: "new".to_string(),
+            password: "HelloWorld123!".to_string(),
+        });
+        let result = admin_grpc.create_admin(request).await.unwrap();
+
+        let admin = result.into_inner().admin.unwrap();
+        assert_eq!(admin.email, "new@example.com".to_string());
+
+        let created = AdminRepository::new(test_db.pool.clone())
+            .find_one("new@example.com".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(admin.id, created.id.to_string());
+        assert!(admin.roles.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_create_admin_does_not_wait_for_mail() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let mailer = Arc::new(HeldMailer::default());
+        let admin_grpc = setup_grpc_with_mailer(
+            test_db.pool.clone(),
+            token_service.clone(),
+            AdminService::new(AdminRepository::new(test_db.pool.clone())),
+            mailer.clone(),
+        );
+
+        let request = authenticated(&token_service, owner_id, proto::CreateAdminRequest {
+            email: "new@example.com".to_string(),
+            name: "new".to_string(),
+            password: "HelloWorld123!".to_string(),
+        });
+
+        // answered while the mail is still held, a taken email never gets that far
+        tokio::time::timeout(Duration::from_secs(10), admin_grpc.create_admin(request))
+            .await
+            .expect("the reply waited for the mail")
+            .unwrap();
+        assert!(mailer.sent.sent().is_empty());
+
+        mailer.release.add_permits(1);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while mailer.sent.sent().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the mail was never sent");
+        assert_eq!(mailer.sent.sent()[0].to, "new@example.com");
+    }
+
+    #[tokio::test]
+    async fn test_create_admin_with_taken_email() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let create = |email: &str| authenticated(&token_service, owner_id, proto::CreateAdminRequest {
+            email: email.to_string(),
+            name: "new".to_string(),
+            password: "HelloWorld123!".to_string(),
+        });
+
+        let fresh = admin_grpc.create_admin(create("new@example.com")).await.unwrap().into_inner();
+        let taken = admin_grpc.create_admin(create("owner@example.com")).await.unwrap().into_inner();
+
+        // both look the same to the caller
+        let fresh = fresh.admin.unwrap();
+        let taken = taken.admin.unwrap();
+        assert_eq!(fresh.email, "new@example.com".to_string());
+        assert_eq!(taken.email, "owner@example.com".to_string());
+        assert!(Uuid::from_str(&taken.id).is_ok());
+        assert_ne!(taken.id, owner_id.to_string());
+        assert_eq!((fresh.roles, fresh.permissions), (taken.roles, taken.permissions));
+
+        let owner = AdminRepository::new(test_db.pool.clone())
+            .find_one("owner@example.com".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(owner.name, "owner".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_create_admin_unauthenticated() {
+        let test_db = database::setup_test_db().await;
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service);
+
+        let result = admin_grpc.create_admin(Request::new(proto::CreateAdminRequest {
+            email: "new@example.com".to_string(),
+            name: "new".to_string(),
+            password: "HelloWorld123!".to_string(),
+        })).await;
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_create_admin_without_permission() {
+        let test_db = database::setup_test_db().await;
+        let waiter = AdminRepository::new(test_db.pool.clone())
+            .create("waiter@example.com".to_string(), "waiter".to_string(), "HelloWorld123!".to_string())
+            .await
+            .unwrap();
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, waiter.id, proto::CreateAdminRequest {
+            email: "new@example.com".to_string(),
+            name: "new".to_string(),
+            password: "HelloWorld123!".to_string(),
+        });
+        let result = admin_grpc.create_admin(request).await;
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_login_admin() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let result = admin_grpc.login_admin(Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: "HelloWorld123!".to_string(),
+        })).await.unwrap().into_inner();
+
+        let claims = token_service.decode_jwt(result.token).unwrap();
+        assert_eq!(claims.admin_id().unwrap(), owner_id);
+        assert_eq!(claims.roles, vec!["owner".to_string()]);
+        assert!(!result.refresh_token.is_empty());
+
+        let result = admin_grpc.login_admin(Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: "wrong".to_string(),
+        })).await;
+        assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_login_admin_with_mfa() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let mfa_service = MfaService::new(MfaRepository::new(test_db.pool.clone()), "sigma".to_string());
+        let enrollment = mfa_service.enroll(owner_id, "owner@example.com".to_string()).await.unwrap();
+        let totp = totp_rs::TOTP::from_url(enrollment.otpauth_uri).unwrap();
+        let recovery_codes = mfa_service
+            .confirm(owner_id, totp.generate_current().unwrap())
+            .await
+            .unwrap();
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let result = admin_grpc.login_admin(Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: "HelloWorld123!".to_string(),
+        })).await.unwrap().into_inner();
+
+        assert!(result.token.is_empty());
+        assert!(result.refresh_token.is_empty());
+        assert!(token_service.decode_jwt(result.mfa_token.clone()).is_err());
+
+        let verify = |code: &str| proto::VerifyMfaRequest {
+            mfa_token: result.mfa_token.clone(),
+            code: code.to_string(),
+        };
+
+        let wrong = admin_grpc.verify_mfa(Request::new(verify("aaaaa-aaaaa"))).await;
+        assert_eq!(wrong.unwrap_err().code(), tonic::Code::Unauthenticated);
+
+        let tokens = admin_grpc
+            .verify_mfa(Request::new(verify(&recovery_codes[0])))
+            .await
+            .unwrap()
+            .into_inner();
+        let claims = token_service.decode_jwt(tokens.token).unwrap();
+        assert_eq!(claims.admin_id().unwrap(), owner_id);
+        assert!(!tokens.refresh_token.is_empty());
+
+        // the mfa token cannot be exchanged twice
+        let replay = admin_grpc.verify_mfa(Request::new(verify(&recovery_codes[1]))).await;
+        assert_eq!(replay.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_login_admin_with_expired_password() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_service = AdminService::new(AdminRepository::new(test_db.pool.clone()).with_password_history(5))
+            .with_password_max_age(Some(chrono::Duration::days(90)));
+        let admin_grpc = setup_grpc_with(test_db.pool.clone(), token_service.clone(), admin_service);
+
+        sqlx::query("UPDATE admins SET password_changed_at = NOW() - INTERVAL '91 days'")
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+
+        let login = || Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: "HelloWorld123!".to_string(),
+        });
+
+        let result = admin_grpc.login_admin(login()).await.unwrap().into_inner();
+        assert!(result.token.is_empty());
+        assert!(token_service.decode_jwt(result.password_change_token.clone()).is_err());
+
+        let change = |new_password: &str| Request::new(proto::ChangeExpiredPasswordRequest {
+            password_change_token: result.password_change_token.clone(),
+            new_password: new_password.to_string(),
+        });
+
+        let reused = admin_grpc.change_expired_password(change("HelloWorld123!")).await;
+        assert_eq!(reused.unwrap_err().code(), tonic::Code::InvalidArgument);
+
+        let tokens = admin_grpc
+            .change_expired_password(change("NewPassword123!"))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(token_service.decode_jwt(tokens.token).unwrap().admin_id().unwrap(), owner_id);
+
+        // the password change token is single use, even within the second it was issued in
+        let replay = admin_grpc.change_expired_password(change("OtherPassword123!")).await;
+        assert_eq!(replay.unwrap_err().code(), tonic::Code::Unauthenticated);
+        assert!(matches!(
+            token_service.decode_scoped(result.password_change_token.clone(), PASSWORD_CHANGE_SCOPE),
+            Err(TokenServiceError::Revoked)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_login_admin_locked_out() {
+        let test_db = database::setup_test_db().await;
+        create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service);
+
+        let login = |password: &str| Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: password.to_string(),
+        });
+
+        for _ in 0..5 {
+            let result = admin_grpc.login_admin(login("wrong")).await;
+            assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+        }
+
+        // even the right password is refused while locked
+        let status = admin_grpc.login_admin(login("HelloWorld123!")).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+        assert!(status.metadata().get("retry-after").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_login_admin_locked_out_by_mfa_codes() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let mfa_service = MfaService::new(MfaRepository::new(test_db.pool.clone()), "sigma".to_string());
+        let enrollment = mfa_service.enroll(owner_id, "owner@example.com".to_string()).await.unwrap();
+        let totp = totp_rs::TOTP::from_url(enrollment.otpauth_uri).unwrap();
+        mfa_service.confirm(owner_id, totp.generate_current().unwrap()).await.unwrap();
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service);
+
+        let login = || Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: "HelloWorld123!".to_string(),
+        });
+
+        // the right password must not reset the failed codes
+        for _ in 0..5 {
+            let mfa_token = admin_grpc.login_admin(login()).await.unwrap().into_inner().mfa_token;
+            let result = admin_grpc
+                .verify_mfa(Request::new(proto::VerifyMfaRequest { mfa_token, code: "aaaaa-aaaaa".to_string() }))
+                .await;
+            assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+        }
+
+        let status = admin_grpc.login_admin(login()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn test_update_admin() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, owner_id, proto::UpdateAdminRequest {
+            new_name: "renamed".to_string(),
+        });
+        let updated = admin_grpc.update_admin(request).await.unwrap().into_inner().admin.unwrap();
+
+        // same shape as VerifyAdmin
+        let token = token_service.create_jwt(owner_id.to_string()).unwrap();
+        let verified = admin_grpc
+            .verify_admin(Request::new(proto::TokenRequest { token }))
+            .await
+            .unwrap()
+            .into_inner()
+            .admin
+            .unwrap();
+        assert_eq!(updated, verified);
+        assert_eq!(updated.roles, vec!["owner".to_string()]);
+        assert!(!updated.permissions.is_empty());
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .find_one("owner@example.com".to_string())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(admin.name, "renamed".to_string());
+    }
+
+    #[tokio::test]
+    async fn test_delete_last_owner() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, owner_id, proto::DeleteAdminRequest {});
+        let result = admin_grpc.delete_admin(request).await;
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::FailedPrecondition);
+    }
+
+    #[tokio::test]
+    async fn test_delete_admin() {
+        let test_db = database::setup_test_db().await;
+        create_owner(&test_db.pool, "owner@example.com").await;
+        let other_id = create_owner(&test_db.pool, "other@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, other_id, proto::DeleteAdminRequest {});
+        admin_grpc.delete_admin(request).await.unwrap();
+
+        let found = AdminRepository::new(test_db.pool.clone())
+            .find_one("other@example.com".to_string())
+            .await
+            .unwrap();
+        assert!(found.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_delete_admin_without_permission() {
+        let test_db = database::setup_test_db().await;
+        let waiter = AdminRepository::new(test_db.pool.clone())
+            .create("waiter@example.com".to_string(), "waiter".to_string(), "HelloWorld123!".to_string())
+            .await
+            .unwrap();
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, waiter.id, proto::DeleteAdminRequest {});
+        let result = admin_grpc.delete_admin(request).await;
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::PermissionDenied);
+    }
+
+    #[tokio::test]
+    async fn test_change_password_revokes_other_tokens() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let other_session = token_service.create_jwt(owner_id.to_string()).unwrap();
+
+        // make sure the other session was issued strictly before the change
+        tokio::time::sleep(Duration::from_secs(1)).await;
+
+        let request = authenticated(&token_service, owner_id, proto::ChangePasswordRequest {
+            current_password: "HelloWorld123!".to_string(),
+            new_password: "NewPassword123!".to_string(),
+        });
+        let result = admin_grpc.change_password(request).await.unwrap().into_inner();
+
+        assert!(token_service.decode_jwt(other_session).is_err());
+        assert!(token_service.decode_jwt(result.token).is_ok());
+
+        let result = admin_grpc.login_admin(Request::new(proto::LoginAdminRequest {
+            email: "owner@example.com".to_string(),
+            password: "NewPassword123!".to_string(),
+        })).await;
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_change_password_revokes_token_from_same_second() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        // start at the top of a second, so the token and the change share its `iat`
+        let millis = 1000 - chrono::Utc::now().timestamp_subsec_millis() as u64;
+        tokio::time::sleep(Duration::from_millis(millis)).await;
+
+        let token = token_service.create_jwt(owner_id.to_string()).unwrap();
+        let request = authenticated_with(&token_service, &token, proto::ChangePasswordRequest {
+            current_password: "HelloWorld123!".to_string(),
+            new_password: "NewPassword123!".to_string(),
+        });
+        admin_grpc.change_password(request).await.unwrap();
+
+        assert!(matches!(token_service.decode_jwt(token), Err(TokenServiceError::Revoked)));
+    }
+
+    #[tokio::test]
+    async fn test_change_password_wrong_current() {
+        let test_db = database::setup_test_db().await;
+        let owner_id = create_owner(&test_db.pool, "owner@example.com").await;
+
+        let token_service = Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()));
+        let admin_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+
+        let request = authenticated(&token_service, owner_id, proto::ChangePasswordRequest {
+            current_password: "wrong".to_string(),
+            new_password: "NewPassword123!".to_string(),
+        });
+        let result = admin_grpc.change_password(request).await;
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::InvalidArgument);
+    }
 }
@@ -0,0 +1,208 @@
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+use thiserror::Error;
+
+use crate::config::PasswordConfig;
+
+const COMMON_PASSWORDS: &str = include_str!("common_passwords.txt");
+
+/// Length of the hash prefix that names a range file.
+const RANGE_PREFIX_LENGTH: usize = 5;
+
+type Sha1Hash = [u8; 20];
+
+#[derive(Error, Debug)]
+pub enum PasswordBlocklistError {
+    #[error("Failed to read password blocklist {path}: {source}")]
+    Read { path: String, source: std::io::Error },
+}
+
+/// Common and breached passwords that may not be chosen as a new password.
+///
+/// The bundled list is always included. `blocklist_path` adds either a file
+/// with one SHA-1 hash (optionally followed by `:count`) or plain password per
+/// line, or a directory of k-anonymity range files such as those of Have I
+/// Been Pwned, named `<first 5 hex digits of the hash>.txt` and holding the
+/// remaining digits. Plain passwords match in any casing. Files are kept in memory, range files are read on lookup.
+pub struct PasswordBlocklist {
+    hashes: Vec<Sha1Hash>,
+    range_dir: Option<PathBuf>,
+}
+
+impl Default for PasswordBlocklist {
+    fn default() -> Self {
+        let mut hashes = Vec::new();
+        parse_list(COMMON_PASSWORDS, &mut hashes);
+
+        Self::from_hashes(hashes, None)
+    }
+}
+
+impl PasswordBlocklist {
+    pub fn from_config(config: &PasswordConfig) -> Result<Self, PasswordBlocklistError> {
+        let mut hashes = Vec::new();
+        parse_list(COMMON_PASSWORDS, &mut hashes);
+
+        let Some(path) = &config.blocklist_path else {
+            return Ok(Self::from_hashes(hashes, None));
+        };
+
+        let read_error = |source| PasswordBlocklistError::Read { path: path.clone(), source };
+        let path = Path::new(path);
+
+        if fs::metadata(path).map_err(read_error)?.is_dir() {
+            return Ok(Self::from_hashes(hashes, Some(path.to_path_buf())));
+        }
+
+        parse_list(&fs::read_to_string(path).map_err(read_error)?, &mut hashes);
+
+        Ok(Self::from_hashes(hashes, None))
+    }
+
+    /// The lowercased password is checked too, so capitalising a listed
+    /// password is not enough.
+    pub async fn contains(&self, password: &str) -> bool {
+        let lowercase = password.to_lowercase();
+
+        for candidate in [password, lowercase.as_str()] {
+            let hash: Sha1Hash = Sha1::digest(candidate.as_bytes()).into();
+
+            if self.hashes.binary_search(&hash).is_ok() || self.in_range_file(&hash).await {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn from_hashes(mut hashes: Vec<Sha1Hash>, range_dir: Option<PathBuf>) -> Self {
+        hashes.sort_unstable();
+        hashes.dedup();
+
+        Self { hashes, range_dir }
+    }
+
+    async fn in_range_file(&self, hash: &Sha1Hash) -> bool {
+        let Some(range_dir) = &self.range_dir else {
+            return false;
+        };
+
+        let hex: String = hash.iter().map(|byte| format!("{byte:02X}")).collect();
+        let (prefix, suffix) = hex.split_at(RANGE_PREFIX_LENGTH);
+        let path = range_dir.join(format!("{prefix}.txt"));
+
+        let range = match tokio::fs::read_to_string(&path).await {
+            Ok(range) => range,
+            Err(e) if e.kind() == ErrorKind::NotFound => return false,
+            Err(e) => {
+                // the list is a safeguard, a broken file should not stop password changes
+                tracing::error!("Failed to read password blocklist {}: {}", path.display(), e);
+                return false;
+            }
+        };
+
+        range
+            .lines()
+            .filter_map(|line| line.split(':').next())
+            .any(|entry| entry.trim().eq_ignore_ascii_case(suffix))
+    }
+}
+
+fn parse_list(list: &str, hashes: &mut Vec<Sha1Hash>) {
+    for line in list.lines() {
+        let line = line.trim_end_matches('\r');
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let entry = line.split(':').next().unwrap_or(line).trim();
+        // lookups also try the lowercased password, which finds any casing of a plain entry
+        let hash = parse_hex(entry).unwrap_or_else(|| Sha1::digest(line.to_lowercase().as_bytes()).into());
+
+        hashes.push(hash);
+    }
+}
+
+fn parse_hex(entry: &str) -> Option<Sha1Hash> {
+    if entry.len() != 40 {
+        return None;
+    }
+
+    let mut hash = [0u8; 20];
+    for (byte, pair) in hash.iter_mut().zip(entry.as_bytes().chunks(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(pair).ok()?, 16).ok()?;
+    }
+
+    Some(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(blocklist_path: &Path) -> PasswordConfig {
+        PasswordConfig {
+            blocklist_path: Some(blocklist_path.to_string_lossy().into_owned()),
+            ..PasswordConfig::default()
+        }
+    }
+
+    fn temp_path() -> PathBuf {
+        std::env::temp_dir().join(format!("blocklist-{}", uuid::Uuid::new_v4()))
+    }
+
+    fn sha1_hex(password: &str) -> String {
+        Sha1::digest(password.as_bytes()).iter().map(|byte| format!("{byte:02X}")).collect()
+    }
+
+    #[tokio::test]
+    async fn test_bundled_list() {
+        let blocklist = PasswordBlocklist::default();
+
+        assert!(blocklist.contains("password").await);
+        assert!(blocklist.contains("Password123!").await);
+        assert!(blocklist.contains("Summer2024!").await);
+        assert!(!blocklist.contains("correct-Horse-battery-9").await);
+    }
+
+    #[tokio::test]
+    async fn test_file_list() {
+        let path = temp_path();
+        fs::write(&path, format!("{}:42\r\nHunter2-Hunter2\n", sha1_hex("Tr0ub4dor&3"))).unwrap();
+
+        let blocklist = PasswordBlocklist::from_config(&config(&path)).unwrap();
+
+        assert!(blocklist.contains("Tr0ub4dor&3").await);
+        assert!(blocklist.contains("hunter2-Hunter2").await);
+        assert!(blocklist.contains("password").await);
+        assert!(!blocklist.contains("correct-Horse-battery-9").await);
+
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_range_dir() {
+        let dir = temp_path();
+        let hash = sha1_hex("Tr0ub4dor&3");
+        let (prefix, suffix) = hash.split_at(RANGE_PREFIX_LENGTH);
+        fs::create_dir(&dir).unwrap();
+        fs::write(dir.join(format!("{prefix}.txt")), format!("{}:3\r\n{suffix}:7\r\n", "0".repeat(35))).unwrap();
+
+        let blocklist = PasswordBlocklist::from_config(&config(&dir)).unwrap();
+
+        assert!(blocklist.contains("Tr0ub4dor&3").await);
+        assert!(!blocklist.contains("correct-Horse-battery-9").await);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_missing_path() {
+        let result = PasswordBlocklist::from_config(&config(Path::new("/nonexistent/blocklist.txt")));
+
+        assert!(matches!(result, Err(PasswordBlocklistError::Read { .. })));
+    }
+}
@@ -0,0 +1,358 @@
+use std::sync::OnceLock;
+
+use argon2::{Algorithm, Argon2, KeyId, Params, ParamsBuilder, Version};
+use password_hash::rand_core::{OsRng, RngCore};
+use password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString};
+use pbkdf2::Pbkdf2;
+use thiserror::Error;
+
+use crate::config::PasswordConfig;
+
+/// Stored in the `keyid` field of peppered hashes, so hashes made before a
+/// pepper was configured can still be verified, and then upgraded.
+const PEPPER_KEY_ID: &[u8] = b"pepper";
+
+#[derive(Error, Debug)]
+pub enum PasswordHashingError {
+    #[error("Invalid Argon2 configuration: {0}")]
+    Config(argon2::Error),
+
+    #[error("Failed to hash password: {0}")]
+    Hash(password_hash::Error),
+
+    #[error("Stored password hash is malformed: {0}")]
+    MalformedHash(password_hash::Error),
+
+    #[error("Stored bcrypt hash is malformed: {0}")]
+    MalformedBcryptHash(bcrypt::BcryptError),
+
+    #[error("Stored password hash uses the unsupported algorithm {0}")]
+    UnsupportedAlgorithm(String),
+
+    #[error("Stored password hash needs a pepper, but none is configured")]
+    MissingPepper,
+}
+
+impl PasswordHashingError {
+    /// The stored hash itself is at fault, rather than the configuration.
+    pub fn is_malformed_hash(&self) -> bool {
+        matches!(
+            self,
+            Self::MalformedHash(_) | Self::MalformedBcryptHash(_) | Self::UnsupportedAlgorithm(_)
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordCheck {
+    Invalid,
+    Valid,
+    /// Correct, but stored with other parameters than the current ones.
+    ValidNeedsRehash,
+}
+
+/// Argon2id with configurable costs and an optional pepper, a secret kept
+/// outside the database. bcrypt and PBKDF2 hashes imported from other systems
+/// are verified too, and always reported as needing a rehash.
+pub struct PasswordHashing {
+    params: Params,
+    pepper: Option<Vec<u8>>,
+    dummy_hash: OnceLock<String>,
+}
+
+impl Default for PasswordHashing {
+    fn default() -> Self {
+        Self {
+            params: Params::DEFAULT,
+            pepper: None,
+            dummy_hash: OnceLock::new(),
+        }
+    }
+}
+
+impl PasswordHashing {
+    pub fn from_config(config: &PasswordConfig) -> Result<Self, PasswordHashingError> {
+        let pepper = config.pepper.as_ref().map(|pepper| pepper.as_bytes().to_vec());
+
+        let mut builder = ParamsBuilder::new();
+        builder
+            .m_cost(config.memory_kib)
+            .t_cost(config.iterations)
+            .p_cost(config.parallelism);
+
+        if pepper.is_some() {
+            builder.keyid(KeyId::new(PEPPER_KEY_ID).map_err(PasswordHashingError::Config)?);
+        }
+
+        let hashing = Self {
+            params: builder.build().map_err(PasswordHashingError::Config)?,
+            pepper,
+            dummy_hash: OnceLock::new(),
+        };
+
+        // checks the pepper length, and keeps the first login with an unknown
+        // email from being slower than the rest
+        hashing.dummy_hash()?;
+
+        Ok(hashing)
+    }
+
+    pub fn hash(&self, password: &str) -> Result<String, PasswordHashingError> {
+        let salt = SaltString::generate(&mut OsRng);
+
+        Ok(self
+            .argon2(self.pepper.is_some())?
+            .hash_password(password.as_bytes(), &salt)
+            .map_err(PasswordHashingError::Hash)?
+            .to_string())
+    }
+
+    pub fn verify(&self, password: &str, stored: &str) -> Result<PasswordCheck, PasswordHashingError> {
+        if is_bcrypt(stored) {
+            let valid = bcrypt::verify(password, stored).map_err(PasswordHashingError::MalformedBcryptHash)?;
+            return Ok(legacy_check(valid));
+        }
+
+        let hash = PasswordHash::new(stored).map_err(PasswordHashingError::MalformedHash)?;
+
+        if is_pbkdf2(&hash) {
+            return match Pbkdf2.verify_password(password.as_bytes(), &hash) {
+                Ok(()) => Ok(legacy_check(true)),
+                Err(password_hash::Error::Password) => Ok(legacy_check(false)),
+                Err(e) => Err(PasswordHashingError::MalformedHash(e)),
+            };
+        }
+
+        if !is_argon2(&hash) {
+            return Err(PasswordHashingError::UnsupportedAlgorithm(hash.algorithm.to_string()));
+        }
+
+        let params = Params::try_from(&hash).map_err(PasswordHashingError::MalformedHash)?;
+        let peppered = !params.keyid().is_empty();
+
+        if peppered && self.pepper.is_none() {
+            return Err(PasswordHashingError::MissingPepper);
+        }
+
+        if self.argon2(peppered)?.verify_password(password.as_bytes(), &hash).is_err() {
+            return Ok(PasswordCheck::Invalid);
+        }
+
+        let current = hash.algorithm == Algorithm::Argon2id.ident()
+            && hash.version == Some(Version::V0x13.into())
+            && params.m_cost() == self.params.m_cost()
+            && params.t_cost() == self.params.t_cost()
+            && params.p_cost() == self.params.p_cost()
+            && peppered == self.pepper.is_some();
+
+        Ok(if current {
+            PasswordCheck::Valid
+        } else {
+            PasswordCheck::ValidNeedsRehash
+        })
+    }
+
+    /// Whether `stored` is in a format `verify` understands, without checking
+    /// any password against it.
+    pub fn is_supported(&self, stored: &str) -> bool {
+        if is_bcrypt(stored) {
+            return stored.parse::<bcrypt::HashParts>().is_ok();
+        }
+
+        match PasswordHash::new(stored) {
+            Ok(hash) if is_pbkdf2(&hash) => pbkdf2::Params::try_from(&hash).is_ok(),
+            Ok(hash) if is_argon2(&hash) => Params::try_from(&hash).is_ok(),
+            _ => false,
+        }
+    }
+
+    /// Costs as much as verifying a real password, for when there is none to
+    /// check against.
+    pub fn verify_dummy(&self, password: &str) {
+        if let Ok(dummy_hash) = self.dummy_hash() {
+            let _ = self.verify(password, dummy_hash);
+        }
+    }
+
+    fn dummy_hash(&self) -> Result<&str, PasswordHashingError> {
+        if let Some(dummy_hash) = self.dummy_hash.get() {
+            return Ok(dummy_hash);
+        }
+
+        let mut password = [0u8; 32];
+        OsRng.fill_bytes(&mut password);
+
+        let dummy_hash = self
+            .argon2(self.pepper.is_some())?
+            .hash_password(&password, &SaltString::generate(&mut OsRng))
+            .map_err(PasswordHashingError::Hash)?
+            .to_string();
+
+        Ok(self.dummy_hash.get_or_init(|| dummy_hash))
+    }
+
+    /// Verification takes its parameters from the stored hash, only the
+    /// secret comes from here.
+    fn argon2(&self, peppered: bool) -> Result<Argon2<'_>, PasswordHashingError> {
+        match (&self.pepper, peppered) {
+            (Some(pepper), true) => {
+                Argon2::new_with_secret(pepper, Algorithm::Argon2id, Version::V0x13, self.params.clone())
+                    .map_err(PasswordHashingError::Config)
+            }
+            _ => Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, self.unkeyed_params()?)),
+        }
+    }
+
+    fn unkeyed_params(&self) -> Result<Params, PasswordHashingError> {
+        Params::new(
+            self.params.m_cost(),
+            self.params.t_cost(),
+            self.params.p_cost(),
+            self.params.output_len(),
+        )
+        .map_err(PasswordHashingError::Config)
+    }
+}
+
+fn legacy_check(valid: bool) -> PasswordCheck {
+    if valid {
+        PasswordCheck::ValidNeedsRehash
+    } else {
+        PasswordCheck::Invalid
+    }
+}
+
+fn is_bcrypt(stored: &str) -> bool {
+    ["$2a$", "$2b$", "$2x$", "$2y$"]
+        .iter()
+        .any(|prefix| stored.starts_with(prefix))
+}
+
+fn is_pbkdf2(hash: &PasswordHash) -> bool {
+    pbkdf2::Algorithm::try_from(hash.algorithm).is_ok()
+}
+
+fn is_argon2(hash: &PasswordHash) -> bool {
+    Algorithm::try_from(hash.algorithm).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PASSWORD: &str = "HelloWorld123!";
+
+    fn config(memory_kib: u32, pepper: Option<&str>) -> PasswordConfig {
+        PasswordConfig {
+            memory_kib,
+            iterations: 1,
+            parallelism: 1,
+            pepper: pepper.map(str::to_string),
+            ..PasswordConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_hash_and_verify() {
+        let hashing = PasswordHashing::from_config(&config(1024, None)).unwrap();
+
+        let hash = hashing.hash(PASSWORD).unwrap();
+
+        assert!(hash.starts_with("$argon2id$v=19$m=1024,t=1,p=1$"));
+        assert_eq!(hashing.verify(PASSWORD, &hash).unwrap(), PasswordCheck::Valid);
+        assert_eq!(hashing.verify("wrong", &hash).unwrap(), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn test_weaker_params_need_rehash() {
+        let old = PasswordHashing::from_config(&config(1024, None)).unwrap();
+        let new = PasswordHashing::from_config(&config(2048, None)).unwrap();
+
+        let hash = old.hash(PASSWORD).unwrap();
+
+        assert_eq!(new.verify(PASSWORD, &hash).unwrap(), PasswordCheck::ValidNeedsRehash);
+        assert_eq!(new.verify("wrong", &hash).unwrap(), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn test_pepper() {
+        let plain = PasswordHashing::from_config(&config(1024, None)).unwrap();
+        let peppered = PasswordHashing::from_config(&config(1024, Some("pepper"))).unwrap();
+        let other_pepper = PasswordHashing::from_config(&config(1024, Some("other"))).unwrap();
+
+        // hashes from before the pepper was configured get upgraded
+        let hash = plain.hash(PASSWORD).unwrap();
+        assert_eq!(peppered.verify(PASSWORD, &hash).unwrap(), PasswordCheck::ValidNeedsRehash);
+
+        let hash = peppered.hash(PASSWORD).unwrap();
+        assert_eq!(peppered.verify(PASSWORD, &hash).unwrap(), PasswordCheck::Valid);
+        assert_eq!(other_pepper.verify(PASSWORD, &hash).unwrap(), PasswordCheck::Invalid);
+        assert!(matches!(plain.verify(PASSWORD, &hash), Err(PasswordHashingError::MissingPepper)));
+    }
+
+    #[test]
+    fn test_malformed_hash() {
+        let hashing = PasswordHashing::default();
+
+        let result = hashing.verify(PASSWORD, "not a hash");
+
+        assert!(matches!(result, Err(PasswordHashingError::MalformedHash(_))));
+    }
+
+    #[test]
+    fn test_legacy_bcrypt_hash() {
+        let hashing = PasswordHashing::default();
+        let hash = bcrypt::hash(PASSWORD, 4).unwrap();
+
+        assert!(hashing.is_supported(&hash));
+        assert_eq!(hashing.verify(PASSWORD, &hash).unwrap(), PasswordCheck::ValidNeedsRehash);
+        assert_eq!(hashing.verify("wrong", &hash).unwrap(), PasswordCheck::Invalid);
+
+        let result = hashing.verify(PASSWORD, "$2b$04$tooshort");
+        assert!(matches!(result, Err(PasswordHashingError::MalformedBcryptHash(_))));
+    }
+
+    #[test]
+    fn test_legacy_pbkdf2_hash() {
+        let hashing = PasswordHashing::default();
+        let salt = SaltString::generate(&mut OsRng);
+        let hash = Pbkdf2
+            .hash_password_customized(
+                PASSWORD.as_bytes(),
+                None,
+                None,
+                pbkdf2::Params {
+                    rounds: 1000,
+                    output_length: 32,
+                },
+                &salt,
+            )
+            .unwrap()
+            .to_string();
+
+        assert!(hash.starts_with("$pbkdf2-sha256$"));
+        assert!(hashing.is_supported(&hash));
+        assert_eq!(hashing.verify(PASSWORD, &hash).unwrap(), PasswordCheck::ValidNeedsRehash);
+        assert_eq!(hashing.verify("wrong", &hash).unwrap(), PasswordCheck::Invalid);
+    }
+
+    #[test]
+    fn test_unsupported_algorithm() {
+        let hashing = PasswordHashing::default();
+        let hash = "$scrypt$ln=16,r=8,p=1$aM15713r3Xsvxbi31lqr1Q$nFNh2CVHVjNldFVKDHDlm4CbdRSCdEBsjjJxD+iCs5E";
+
+        assert!(!hashing.is_supported(hash));
+        assert!(!hashing.is_supported("not a hash"));
+        assert!(matches!(
+            hashing.verify(PASSWORD, hash),
+            Err(PasswordHashingError::UnsupportedAlgorithm(_))
+        ));
+    }
+
+    #[test]
+    fn test_invalid_config() {
+        let result = PasswordHashing::from_config(&config(1, None));
+
+        assert!(matches!(result, Err(PasswordHashingError::Config(_))));
+    }
+}
@@ -0,0 +1,293 @@
+use std::sync::Arc;
+
+use chrono::Duration;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::mail::{Email, Mailer, MailerError};
+use crate::token::{OneTimeTokenService, OneTimeTokenServiceError, TokenPurpose};
+
+use super::{AdminRepository, AdminRepositoryError, PasswordBlocklist};
+
+#[derive(Error, Debug)]
+pub enum PasswordResetServiceError {
+    #[error("{0}")]
+    Repository(#[from] AdminRepositoryError),
+
+    #[error("{0}")]
+    OneTimeToken(#[from] OneTimeTokenServiceError),
+
+    #[error("{0}")]
+    Mailer(#[from] MailerError),
+
+    #[error("The new password was used recently, choose another one")]
+    PasswordReused,
+
+    #[error("Password is too common or has appeared in a data breach, choose another one")]
+    PasswordBlocked,
+}
+
+pub struct PasswordResetService {
+    repo: AdminRepository,
+    one_time_token_service: OneTimeTokenService,
+    mailer: Arc<dyn Mailer>,
+    reset_url: String,
+    ttl: Duration,
+    password_blocklist: Arc<PasswordBlocklist>,
+}
+
+impl PasswordResetService {
+    pub fn new(
+        repo: AdminRepository,
+        one_time_token_service: OneTimeTokenService,
+        mailer: Arc<dyn Mailer>,
+    ) -> Self {
+        Self {
+            repo,
+            one_time_token_service,
+            mailer,
+            reset_url: String::new(),
+            ttl: Duration::hours(1),
+            password_blocklist: Arc::new(PasswordBlocklist::default()),
+        }
+    }
+
+    pub fn with_reset_url(mut self, reset_url: String) -> Self {
+        self.reset_url = reset_url;
+        self
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn with_password_blocklist(mut self, password_blocklist: Arc<PasswordBlocklist>) -> Self {
+        self.password_blocklist = password_blocklist;
+        self
+    }
+
+    /// Requests the reset off the request path, so the answer takes as long for
+    /// unknown emails as for admins. Failures are only logged.
+    pub fn spawn_reset(self: &Arc<Self>, email: String) -> JoinHandle<()> {
+        let service = self.clone();
+
+        tokio::spawn(async move {
+            if let Err(e) = service.request_reset(email).await {
+                tracing::error!("Failed to send password reset email: {}", e);
+            }
+        })
+    }
+
+    /// Emails a reset link. Unknown emails are silently ignored so the endpoint
+    /// cannot be used to find out which admins exist.
+    pub async fn request_reset(&self, email: String) -> Result<(), PasswordResetServiceError> {
+        let Some(admin) = self.repo.find_one(email).await? else {
+            return Ok(());
+        };
+
+        let token = self
+            .one_time_token_service
+            .issue(admin.id, admin.email.clone(), TokenPurpose::PasswordReset, self.ttl)
+            .await?;
+
+        self.mailer
+            .send(Email {
+                to: admin.email,
+                subject: "Reset your password".to_string(),
+                body: format!(
+                    "Hi {},\n\nUse the link below to choose a new password. It expires in {} minutes.\n\n{}{}\n\nIf you did not ask for this, you can ignore this email.\n",
+                    admin.name,
+                    self.ttl.num_minutes(),
+                    self.reset_url,
+                    token
+                ),
+            })
+            .await?;
+
+        Ok(())
+    }
+
+    /// Returns the id of the admin whose password was reset. A reused or
+    /// blocked password is rejected before the token is used up, so the link can be
+    /// tried again.
+    pub async fn confirm_reset(
+        &self,
+        token: String,
+        new_password: String,
+    ) -> Result<Uuid, PasswordResetServiceError> {
+        let peeked = self
+            .one_time_token_service
+            .peek(token.clone(), TokenPurpose::PasswordReset)
+            .await?;
+
+        if self.password_blocklist.contains(&new_password).await {
+            return Err(PasswordResetServiceError::PasswordBlocked);
+        }
+
+        if self.repo.is_password_reused(peeked.admin_id, &new_password).await? {
+            return Err(PasswordResetServiceError::PasswordReused);
+        }
+
+        let consumed = self
+            .one_time_token_service
+            .consume(token, TokenPurpose::PasswordReset)
+            .await?;
+
+        self.repo.update_password(consumed.admin_id, new_password).await?;
+
+        Ok(consumed.admin_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminService;
+    use crate::database::setup_test_db;
+    use crate::mail::InMemoryMailer;
+    use crate::token::OneTimeTokenRepository;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+    const PASSWORD: &str = "HelloWorld123!";
+    const NEW_PASSWORD: &str = "NewPassword123!";
+
+    fn setup_service(pool: sqlx::PgPool, mailer: Arc<InMemoryMailer>) -> PasswordResetService {
+        PasswordResetService::new(
+            AdminRepository::new(pool.clone()),
+            OneTimeTokenService::new(OneTimeTokenRepository::new(pool)),
+            mailer,
+        )
+        .with_reset_url("https://sigma.local/reset?token=".to_string())
+    }
+
+    fn token_from(email: &Email) -> String {
+        let start = email.body.find("token=").unwrap() + "token=".len();
+        email.body[start..].split_whitespace().next().unwrap().to_string()
+    }
+
+    #[tokio::test]
+    async fn test_reset_password() {
+        let test_db = setup_test_db().await;
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        service.request_reset(EMAIL.to_string()).await.unwrap();
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, EMAIL);
+
+        let admin_id = service
+            .confirm_reset(token_from(&sent[0]), NEW_PASSWORD.to_string())
+            .await
+            .unwrap();
+        assert_eq!(admin_id, admin.id);
+
+        let admin_service = AdminService::new(AdminRepository::new(test_db.pool.clone()));
+        assert!(admin_service.authenticate(EMAIL.to_string(), NEW_PASSWORD.to_string()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_spawn_reset() {
+        let test_db = setup_test_db().await;
+        AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = Arc::new(setup_service(test_db.pool.clone(), mailer.clone()));
+
+        service.spawn_reset(EMAIL.to_string()).await.unwrap();
+
+        assert_eq!(mailer.sent().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reused_password_keeps_token() {
+        let test_db = setup_test_db().await;
+        AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = PasswordResetService::new(
+            AdminRepository::new(test_db.pool.clone()).with_password_history(5),
+            OneTimeTokenService::new(OneTimeTokenRepository::new(test_db.pool.clone())),
+            mailer.clone(),
+        )
+        .with_reset_url("https://sigma.local/reset?token=".to_string());
+
+        service.request_reset(EMAIL.to_string()).await.unwrap();
+        let token = token_from(&mailer.sent()[0]);
+
+        let result = service.confirm_reset(token.clone(), PASSWORD.to_string()).await;
+        assert!(matches!(result, Err(PasswordResetServiceError::PasswordReused)));
+
+        service.confirm_reset(token, NEW_PASSWORD.to_string()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_token_is_single_use() {
+        let test_db = setup_test_db().await;
+        AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        service.request_reset(EMAIL.to_string()).await.unwrap();
+        let token = token_from(&mailer.sent()[0]);
+
+        service.confirm_reset(token.clone(), NEW_PASSWORD.to_string()).await.unwrap();
+        let result = service.confirm_reset(token, PASSWORD.to_string()).await;
+
+        assert!(matches!(
+            result,
+            Err(PasswordResetServiceError::OneTimeToken(OneTimeTokenServiceError::InvalidToken))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_expired_token() {
+        let test_db = setup_test_db().await;
+        AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone())
+            .with_ttl(Duration::seconds(-1));
+
+        service.request_reset(EMAIL.to_string()).await.unwrap();
+        let result = service
+            .confirm_reset(token_from(&mailer.sent()[0]), NEW_PASSWORD.to_string())
+            .await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_unknown_email() {
+        let test_db = setup_test_db().await;
+
+        let mailer = Arc::new(InMemoryMailer::default());
+        let service = setup_service(test_db.pool.clone(), mailer.clone());
+
+        service.request_reset("nobody@gmail.com".to_string()).await.unwrap();
+
+        assert!(mailer.sent().is_empty());
+    }
+}
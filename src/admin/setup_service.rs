@@ -0,0 +1,174 @@
+use std::sync::{Arc, Mutex};
+
+use thiserror::Error;
+
+use crate::role::Role;
+use crate::utils::{generate_token, hash_token};
+
+use super::{AdminModel, AdminRepository, AdminRepositoryError, PasswordBlocklist};
+
+#[derive(Error, Debug)]
+pub enum SetupServiceError {
+    #[error("{0}")]
+    Repository(#[from] AdminRepositoryError),
+
+    #[error("The setup token is invalid")]
+    InvalidToken,
+
+    #[error("An admin already exists")]
+    AlreadyInitialised,
+
+    #[error("Password is too common or has appeared in a data breach, choose another one")]
+    PasswordBlocked,
+}
+
+/// Creates the first owner of a fresh deployment using a one-time setup token
+/// that is printed at startup.
+pub struct SetupService {
+    admin_repo: AdminRepository,
+    token_hash: Mutex<Option<String>>,
+    password_blocklist: Arc<PasswordBlocklist>,
+}
+
+impl SetupService {
+    pub fn new(admin_repo: AdminRepository) -> Self {
+        Self {
+            admin_repo,
+            token_hash: Mutex::new(None),
+            password_blocklist: Arc::new(PasswordBlocklist::default()),
+        }
+    }
+
+    pub fn with_password_blocklist(mut self, password_blocklist: Arc<PasswordBlocklist>) -> Self {
+        self.password_blocklist = password_blocklist;
+        self
+    }
+
+    /// Generates a setup token if there are no admins yet. Each replica has its own.
+    pub async fn prepare(&self) -> Result<Option<String>, SetupServiceError> {
+        if self.admin_repo.count().await? > 0 {
+            return Ok(None);
+        }
+
+        let token = generate_token();
+        *self.token_hash.lock().unwrap() = Some(hash_token(&token));
+
+        Ok(Some(token))
+    }
+
+    pub async fn create_owner(
+        &self,
+        setup_token: String,
+        email: String,
+        name: String,
+        password: String,
+    ) -> Result<AdminModel, SetupServiceError> {
+        let valid = self
+            .token_hash
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|hash| *hash == hash_token(&setup_token));
+
+        if !valid {
+            return Err(SetupServiceError::InvalidToken);
+        }
+
+        if self.password_blocklist.contains(&password).await {
+            return Err(SetupServiceError::PasswordBlocked);
+        }
+
+        let admin = self
+            .admin_repo
+            .create_first(email, name, password, vec![Role::Owner.to_string()])
+            .await?
+            .ok_or(SetupServiceError::AlreadyInitialised)?;
+
+        *self.token_hash.lock().unwrap() = None;
+
+        Ok(admin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::setup_test_db;
+    use crate::role::RoleRepository;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+    const PASSWORD: &str = "HelloWorld123!";
+
+    fn setup_service(pool: sqlx::PgPool) -> SetupService {
+        SetupService::new(AdminRepository::new(pool))
+    }
+
+    #[tokio::test]
+    async fn test_create_owner() {
+        let test_db = setup_test_db().await;
+        let service = setup_service(test_db.pool.clone());
+
+        let token = service.prepare().await.unwrap().unwrap();
+        let admin = service
+            .create_owner(token.clone(), EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let roles = RoleRepository::new(test_db.pool.clone())
+            .find_roles(admin.id)
+            .await
+            .unwrap();
+        assert_eq!(roles, vec!["owner"]);
+        assert!(admin.email_verified_at.is_some());
+
+        // the token only works once
+        let result = service
+            .create_owner(token, "other@gmail.com".to_string(), "other".to_string(), PASSWORD.to_string())
+            .await;
+        assert!(matches!(result, Err(SetupServiceError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_wrong_token() {
+        let test_db = setup_test_db().await;
+        let service = setup_service(test_db.pool);
+
+        service.prepare().await.unwrap();
+        let result = service
+            .create_owner("wrong".to_string(), EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await;
+
+        assert!(matches!(result, Err(SetupServiceError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_no_token_once_initialised() {
+        let test_db = setup_test_db().await;
+        AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let service = setup_service(test_db.pool);
+
+        assert!(service.prepare().await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_already_initialised_by_other_replica() {
+        let test_db = setup_test_db().await;
+        let service = setup_service(test_db.pool.clone());
+        let token = service.prepare().await.unwrap().unwrap();
+
+        AdminRepository::new(test_db.pool)
+            .create(EMAIL.to_string(), "asdf".to_string(), PASSWORD.to_string())
+            .await
+            .unwrap();
+
+        let result = service
+            .create_owner(token, "other@gmail.com".to_string(), "other".to_string(), PASSWORD.to_string())
+            .await;
+        assert!(matches!(result, Err(SetupServiceError::AlreadyInitialised)));
+    }
+}
@@ -1,8 +1,10 @@
 use std::error::Error;
+use std::net::SocketAddr;
 use std::sync::Arc;
 
 use axum::routing;
 use axum::Router;
+use chrono::Duration;
 use sqlx::PgPool;
 use tokio::net::TcpListener;
 use tonic::transport::Server;
@@ -13,15 +15,30 @@ use tower_http::trace::TraceLayer;
 use tracing::Level;
 
 use crate::admin;
-use crate::admin::{AdminGrpc, AdminRepository, AdminService};
+use crate::config::Config;
+use crate::lockout::{LockoutRepository, LockoutService};
+use crate::mail::mailer_from_config;
+use crate::mfa::{self, MfaRepository, MfaService};
+use crate::token;
+use crate::admin::{
+    AdminGrpc, AdminRepository, AdminService, EmailVerificationService, PasswordBlocklist,
+    PasswordHashing, PasswordResetService, SetupService,
+};
 use crate::admin::proto::admin_service_server::AdminServiceServer;
+use crate::role::{self, RoleRepository, RoleService};
 use crate::table_session::{TableSessionGrpc, TableSessionRepository, TableSessionService};
 use crate::table_session::proto::table_session_service_server::TableSessionServiceServer;
-use crate::token::TokenService;
+use crate::token::{
+    AuthInterceptor, OAuthClients, OneTimeTokenRepository, OneTimeTokenService,
+    RefreshTokenRepository, RefreshTokenService, RevocationRepository, RevocationService,
+    TokenService,
+};
 
 #[derive(Default)]
 pub struct GrpcApp {
     pool: Option<PgPool>,
+    token_service: Option<Arc<TokenService>>,
+    config: Config,
 }
 
 impl GrpcApp {
@@ -30,20 +47,80 @@ impl GrpcApp {
         self
     }
 
+    pub fn with_token_service(mut self, token_service: Arc<TokenService>) -> Self {
+        self.token_service = Some(token_service);
+        self
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
     pub async fn run(self, addr: &str) -> Result<(), Box<dyn Error>> {
         let pool = self.pool.expect("`pool` not set!");
+        let token_service = self.token_service.expect("`token_service` not set!");
         let addr = addr.parse()?;
 
-        let admin_repository = AdminRepository::new(pool.clone());
-        let admin_service = AdminService::new(admin_repository);
+        let password_hashing = Arc::new(PasswordHashing::from_config(&self.config.password)?);
+        let password_blocklist = Arc::new(PasswordBlocklist::from_config(&self.config.password)?);
+
+        let admin_repository = AdminRepository::new(pool.clone())
+            .with_password_hashing(password_hashing)
+            .with_password_history(self.config.password.history);
+        let admin_service = AdminService::new(admin_repository)
+            .with_require_verified_email(self.config.admin.require_verified_email)
+            .with_password_max_age(self.config.password.max_age())
+            .with_password_blocklist(password_blocklist);
 
-        let table_session_repository = TableSessionRepository::new(pool.clone());
+        let table_session_repository = TableSessionRepository::new(pool.clone())
+            .with_idle_ttl(Duration::seconds(self.config.table_session.idle_ttl_seconds));
         let table_session_service = TableSessionService::new(table_session_repository);
 
-        let token_service = TokenService::new("asdf".to_string(), "asdf".to_string());
+        let role_repository = RoleRepository::new(pool.clone());
+        let role_service = RoleService::new(role_repository);
 
-        let admin_grpc = AdminGrpc::new(admin_service, token_service);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let refresh_token_repository = RefreshTokenRepository::new(pool.clone());
+        let refresh_token_service = RefreshTokenService::new(refresh_token_repository)
+            .with_ttl(Duration::seconds(self.config.token.refresh_ttl_seconds));
+
+        let revocation_service = RevocationService::new(
+            RevocationRepository::new(pool.clone()),
+            RefreshTokenRepository::new(pool.clone()),
+            token_service.revocations(),
+        );
+
+        let mailer = mailer_from_config(&self.config.mail)?;
+        let email_verification_service = EmailVerificationService::new(
+            AdminRepository::new(pool.clone()),
+            OneTimeTokenService::new(OneTimeTokenRepository::new(pool.clone())),
+            mailer,
+        )
+        .with_verify_url(self.config.mail.email_verification_url.clone())
+        .with_email_change_url(self.config.mail.email_change_url.clone())
+        .with_ttl(Duration::seconds(self.config.token.email_verification_ttl_seconds));
+
+        let mfa_service = MfaService::new(MfaRepository::new(pool.clone()), self.config.token.issuer.clone());
+
+        let lockout_service = LockoutService::new(LockoutRepository::new(pool.clone()))
+            .with_config(self.config.lockout.clone());
+
+        let auth_interceptor = AuthInterceptor::new(token_service.clone());
+        let admin_grpc = AdminGrpc::new(
+            admin_service,
+            role_service,
+            token_service.clone(),
+            refresh_token_service,
+            revocation_service,
+            Arc::new(email_verification_service),
+            mfa_service,
+            lockout_service,
+        );
+        let table_session_grpc = TableSessionGrpc::new(
+            table_session_service,
+            RoleService::new(RoleRepository::new(pool.clone())),
+            token_service,
+        );
 
         let trace_layer = TraceLayer::new_for_grpc()
             .make_span_with(DefaultMakeSpan::new().level(Level::INFO))
@@ -51,8 +128,8 @@ impl GrpcApp {
 
         Server::builder()
             .layer(trace_layer)
-            .add_service(AdminServiceServer::new(admin_grpc))
-            .add_service(TableSessionServiceServer::new(table_session_grpc))
+            .add_service(AdminServiceServer::with_interceptor(admin_grpc, auth_interceptor.clone()))
+            .add_service(TableSessionServiceServer::with_interceptor(table_session_grpc, auth_interceptor))
             .serve(addr)
             .await?;
 
@@ -63,12 +140,23 @@ impl GrpcApp {
 #[derive(Default)]
 pub struct RestApp {
     pool: Option<PgPool>,
+    token_service: Option<Arc<TokenService>>,
+    config: Config,
 }
 
 #[derive(Clone)]
 pub struct RestState {
     pub admin_service: Arc<AdminService>,
     pub token_service: Arc<TokenService>,
+    pub refresh_token_service: Arc<RefreshTokenService>,
+    pub revocation_service: Arc<RevocationService>,
+    pub oauth_clients: Arc<OAuthClients>,
+    pub role_service: Arc<RoleService>,
+    pub setup_service: Arc<SetupService>,
+    pub password_reset_service: Arc<PasswordResetService>,
+    pub email_verification_service: Arc<EmailVerificationService>,
+    pub mfa_service: Arc<MfaService>,
+    pub lockout_service: Arc<LockoutService>,
 }
 
 impl RestApp {
@@ -77,21 +165,98 @@ impl RestApp {
         self
     }
 
+    pub fn with_token_service(mut self, token_service: Arc<TokenService>) -> Self {
+        self.token_service = Some(token_service);
+        self
+    }
+
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
     pub async fn run(self, addr: &str) -> Result<(), Box<dyn Error>> {
         async fn hello() -> &'static str {
             "Hello from REST!"
         }
 
         let pool = self.pool.expect("`pool` not set!");
+        let token_service = self.token_service.expect("`token_service` not set!");
+
+        let password_hashing = Arc::new(PasswordHashing::from_config(&self.config.password)?);
+        let password_blocklist = Arc::new(PasswordBlocklist::from_config(&self.config.password)?);
+
+        let admin_repository = AdminRepository::new(pool.clone())
+            .with_password_hashing(password_hashing.clone())
+            .with_password_history(self.config.password.history);
+        let admin_service = AdminService::new(admin_repository)
+            .with_require_verified_email(self.config.admin.require_verified_email)
+            .with_password_max_age(self.config.password.max_age())
+            .with_password_blocklist(password_blocklist.clone());
+
+        let role_repository = RoleRepository::new(pool.clone());
+        let role_service = RoleService::new(role_repository);
+
+        let setup_service = SetupService::new(
+            AdminRepository::new(pool.clone()).with_password_hashing(password_hashing.clone()),
+        )
+        .with_password_blocklist(password_blocklist.clone());
+
+        if let Some(setup_token) = setup_service.prepare().await? {
+            tracing::warn!(
+                "No admins exist yet, create the first owner with POST /admin/setup using setup token {}",
+                setup_token
+            );
+        }
+
+        let refresh_token_repository = RefreshTokenRepository::new(pool.clone());
+        let refresh_token_service = RefreshTokenService::new(refresh_token_repository)
+            .with_ttl(Duration::seconds(self.config.token.refresh_ttl_seconds));
+
+        let revocation_service = RevocationService::new(
+            RevocationRepository::new(pool.clone()),
+            RefreshTokenRepository::new(pool.clone()),
+            token_service.revocations(),
+        );
+
+        let mailer = mailer_from_config(&self.config.mail)?;
+        let password_reset_service = PasswordResetService::new(
+            AdminRepository::new(pool.clone())
+                .with_password_hashing(password_hashing)
+                .with_password_history(self.config.password.history),
+            OneTimeTokenService::new(OneTimeTokenRepository::new(pool.clone())),
+            mailer.clone(),
+        )
+        .with_reset_url(self.config.mail.password_reset_url.clone())
+        .with_ttl(Duration::seconds(self.config.token.password_reset_ttl_seconds))
+        .with_password_blocklist(password_blocklist);
+
+        let email_verification_service = EmailVerificationService::new(
+            AdminRepository::new(pool.clone()),
+            OneTimeTokenService::new(OneTimeTokenRepository::new(pool.clone())),
+            mailer,
+        )
+        .with_verify_url(self.config.mail.email_verification_url.clone())
+        .with_email_change_url(self.config.mail.email_change_url.clone())
+        .with_ttl(Duration::seconds(self.config.token.email_verification_ttl_seconds));
 
-        let admin_repository = AdminRepository::new(pool.clone());
-        let admin_service = AdminService::new(admin_repository);
+        let mfa_service = MfaService::new(MfaRepository::new(pool.clone()), self.config.token.issuer.clone());
 
-        let token_service = TokenService::new("asdf".to_string(), "asdf".to_string());
+        let lockout_service = LockoutService::new(LockoutRepository::new(pool.clone()))
+            .with_config(self.config.lockout.clone());
 
         let state = RestState {
             admin_service: Arc::new(admin_service),
-            token_service: Arc::new(token_service),
+            token_service,
+            refresh_token_service: Arc::new(refresh_token_service),
+            revocation_service: Arc::new(revocation_service),
+            oauth_clients: Arc::new(OAuthClients::new(self.config.oauth.clients)),
+            role_service: Arc::new(role_service),
+            setup_service: Arc::new(setup_service),
+            password_reset_service: Arc::new(password_reset_service),
+            email_verification_service: Arc::new(email_verification_service),
+            mfa_service: Arc::new(mfa_service),
+            lockout_service: Arc::new(lockout_service),
         };
 
         let cors_layer = CorsLayer::permissive();
@@ -102,13 +267,19 @@ impl RestApp {
 
         let app = Router::new()
             .route("/", routing::get(hello))
+            .merge(token::router())
+            .merge(role::router())
+            .merge(mfa::router())
             .nest("/admin", admin::router())
             .layer(cors_layer)
             .layer(trace_layer)
             .with_state(state);
 
         let listener = TcpListener::bind(addr).await.unwrap();
-        axum::serve(listener, app).await.unwrap();
+        // the peer address is needed to throttle logins per client IP
+        axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+            .await
+            .unwrap();
 
         Ok(())
     }
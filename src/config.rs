@@ -0,0 +1,917 @@
+use std::env;
+use std::fs;
+use std::net::SocketAddr;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::Algorithm;
+use serde::Deserialize;
+use thiserror::Error;
+
+const DEFAULT_CONFIG_FILE: &str = "config.toml";
+const MIN_SECRET_LENGTH: usize = 32;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Unable to read config file {path}: {source}")]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Unable to parse config file: {0}")]
+    ParseFile(#[from] toml::de::Error),
+
+    #[error("Invalid value for {name}: {value}")]
+    InvalidEnv { name: &'static str, value: String },
+
+    #[error("JWT secret is missing, set JWT_SECRET or `token.secret`")]
+    MissingSecret,
+
+    #[error("JWT secret must be at least {MIN_SECRET_LENGTH} bytes long")]
+    SecretTooShort,
+
+    #[error("Signing algorithm {0:?} is not supported, use HS256, RS256, ES256 or EdDSA")]
+    UnsupportedAlgorithm(Algorithm),
+
+    #[error("{0:?} signing requires JWT_PRIVATE_KEY_PATH or `token.private_key_path`")]
+    MissingPrivateKey(Algorithm),
+
+    #[error("Duplicate signing key id {0}")]
+    DuplicateKid(String),
+
+    #[error("{0} must be greater than zero")]
+    NonPositive(&'static str),
+
+    #[error("Invalid bind address for {name}: {value}")]
+    InvalidAddr { name: &'static str, value: String },
+
+    #[error("OAuth client {0} has an empty secret")]
+    EmptyClientSecret(String),
+
+    #[error("SMTP mail backend requires SMTP_HOST or `mail.smtp_host`")]
+    MissingSmtpHost,
+
+    #[error("lockout.max_lockout_seconds must not be shorter than lockout.base_lockout_seconds")]
+    MaxLockoutTooShort,
+
+    #[error("Invalid Argon2 parameters: {0}")]
+    InvalidArgon2Params(argon2::Error),
+
+    #[error("PASSWORD_PEPPER is set but empty")]
+    EmptyPepper,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub grpc_addr: String,
+    pub rest_addr: String,
+    pub token: TokenConfig,
+    pub oauth: OAuthConfig,
+    pub mail: MailConfig,
+    pub admin: AdminConfig,
+    pub lockout: LockoutConfig,
+    pub password: PasswordConfig,
+    pub table_session: TableSessionConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TokenConfig {
+    pub issuer: String,
+    pub kid: String,
+    pub algorithm: Algorithm,
+    pub secret: String,
+    pub private_key_path: Option<String>,
+    /// Lifetime of access tokens, kept short since refresh tokens renew them.
+    pub ttl_seconds: i64,
+    pub refresh_ttl_seconds: i64,
+    /// How long a key keeps verifying tokens after it is rotated out at runtime.
+    pub rotation_grace_seconds: i64,
+    /// How often revocations made by other replicas are pulled into memory.
+    pub revocation_sync_seconds: u64,
+    pub password_reset_ttl_seconds: i64,
+    pub email_verification_ttl_seconds: i64,
+    /// Lifetime of the token handed out between password and TOTP code.
+    pub mfa_ttl_seconds: i64,
+    /// Lifetime of the token handed out when a login needs a new password first.
+    pub password_change_ttl_seconds: i64,
+    /// Lifetime of the token customers get for their table session, renewed
+    /// whenever they verify it.
+    pub table_session_ttl_seconds: i64,
+    /// Retired keys that still verify tokens but are never used for signing.
+    pub previous_keys: Vec<KeyConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct KeyConfig {
+    pub kid: String,
+    #[serde(default)]
+    pub algorithm: Algorithm,
+    #[serde(default)]
+    pub secret: String,
+    #[serde(default)]
+    pub private_key_path: Option<String>,
+    /// RFC 3339 timestamp after which the key is dropped. Kept until removed if unset.
+    #[serde(default)]
+    pub verify_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MailBackend {
+    Smtp,
+    /// Writes each message to `mail.file_dir`, for local development.
+    File,
+    /// Keeps messages in memory, for tests.
+    Memory,
+}
+
+impl FromStr for MailBackend {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "smtp" => Ok(MailBackend::Smtp),
+            "file" => Ok(MailBackend::File),
+            "memory" => Ok(MailBackend::Memory),
+            _ => Err(()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct MailConfig {
+    pub backend: MailBackend,
+    pub from: String,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    pub smtp_username: Option<String>,
+    pub smtp_password: Option<String>,
+    pub file_dir: String,
+    /// Link sent in password reset emails, the token is appended to it.
+    pub password_reset_url: String,
+    /// Link sent in verification emails, the token is appended to it.
+    pub email_verification_url: String,
+    /// Link sent to the new address when an admin changes their email.
+    pub email_change_url: String,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct AdminConfig {
+    /// Rejects logins until the admin has verified their email address.
+    pub require_verified_email: bool,
+}
+
+/// Argon2id costs for new password hashes. Existing hashes are upgraded on
+/// the next successful login when these change.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct PasswordConfig {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+    /// Secret mixed into every hash and never stored in the database.
+    /// Changing it makes every password hashed with it unusable.
+    pub pepper: Option<String>,
+    /// Breached passwords to reject on top of the bundled list, see
+    /// `PasswordBlocklist` for the accepted formats.
+    pub blocklist_path: Option<String>,
+    /// Previous passwords, besides the current one, that may not be chosen
+    /// again. 0 allows any password, including the current one.
+    pub history: u32,
+    /// Days after which a password must be changed before the next login
+    /// completes. 0 never expires passwords.
+    pub max_age_days: u32,
+}
+
+/// Brute-force protection for logins. After `max_*_failures` failed attempts
+/// the email or client IP is locked for `base_lockout_seconds`, doubling with
+/// every further failure up to `max_lockout_seconds`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LockoutConfig {
+    pub max_account_failures: i32,
+    pub max_ip_failures: i32,
+    /// Failures older than this are forgotten.
+    pub window_seconds: i64,
+    pub base_lockout_seconds: i64,
+    pub max_lockout_seconds: i64,
+    /// Number of proxies in front of the service that append to
+    /// `X-Forwarded-For`. The client IP is read that many entries from the
+    /// right, as entries further left are whatever the client sent. 0 ignores
+    /// the header.
+    pub trusted_proxies: usize,
+}
+
+/// Open table sessions nobody looked at for `idle_ttl_seconds` are expired by
+/// a sweep every `expiry_sweep_seconds`, `expiry_batch_size` rows at a time.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TableSessionConfig {
+    pub idle_ttl_seconds: i64,
+    pub expiry_sweep_seconds: u64,
+    pub expiry_batch_size: i64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct OAuthConfig {
+    /// Resource servers allowed to introspect tokens.
+    pub clients: Vec<OAuthClientConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct OAuthClientConfig {
+    pub client_id: String,
+    pub client_secret: String,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            grpc_addr: "[::]:50051".to_string(),
+            rest_addr: "0.0.0.0:8082".to_string(),
+            token: TokenConfig::default(),
+            oauth: OAuthConfig::default(),
+            mail: MailConfig::default(),
+            admin: AdminConfig::default(),
+            lockout: LockoutConfig::default(),
+            password: PasswordConfig::default(),
+            table_session: TableSessionConfig::default(),
+        }
+    }
+}
+
+impl Default for TokenConfig {
+    fn default() -> Self {
+        Self {
+            issuer: "sigma-authentication".to_string(),
+            kid: "default".to_string(),
+            algorithm: Algorithm::HS256,
+            secret: String::new(),
+            private_key_path: None,
+            ttl_seconds: 15 * 60,
+            refresh_ttl_seconds: 30 * 24 * 60 * 60,
+            rotation_grace_seconds: 24 * 60 * 60,
+            revocation_sync_seconds: 5,
+            password_reset_ttl_seconds: 60 * 60,
+            email_verification_ttl_seconds: 24 * 60 * 60,
+            mfa_ttl_seconds: 5 * 60,
+            password_change_ttl_seconds: 10 * 60,
+            table_session_ttl_seconds: 60 * 60,
+            previous_keys: Vec::new(),
+        }
+    }
+}
+
+impl Default for MailConfig {
+    fn default() -> Self {
+        Self {
+            backend: MailBackend::File,
+            from: "Sigma <no-reply@sigma.local>".to_string(),
+            smtp_host: String::new(),
+            smtp_port: 587,
+            smtp_username: None,
+            smtp_password: None,
+            file_dir: "mail".to_string(),
+            password_reset_url: "http://localhost:3000/reset-password?token=".to_string(),
+            email_verification_url: "http://localhost:3000/verify-email?token=".to_string(),
+            email_change_url: "http://localhost:3000/confirm-email-change?token=".to_string(),
+        }
+    }
+}
+
+/// The `argon2` crate defaults, which hashes created before this was
+/// configurable used.
+impl Default for PasswordConfig {
+    fn default() -> Self {
+        Self {
+            memory_kib: argon2::Params::DEFAULT_M_COST,
+            iterations: argon2::Params::DEFAULT_T_COST,
+            parallelism: argon2::Params::DEFAULT_P_COST,
+            pepper: None,
+            blocklist_path: None,
+            history: 5,
+            max_age_days: 0,
+        }
+    }
+}
+
+impl Default for LockoutConfig {
+    fn default() -> Self {
+        Self {
+            max_account_failures: 5,
+            max_ip_failures: 20,
+            window_seconds: 15 * 60,
+            base_lockout_seconds: 30,
+            max_lockout_seconds: 60 * 60,
+            trusted_proxies: 0,
+        }
+    }
+}
+
+impl Default for TableSessionConfig {
+    fn default() -> Self {
+        Self {
+            idle_ttl_seconds: 4 * 60 * 60,
+            expiry_sweep_seconds: 60,
+            expiry_batch_size: 500,
+        }
+    }
+}
+
+impl TokenConfig {
+    pub fn current_key(&self) -> KeyConfig {
+        KeyConfig {
+            kid: self.kid.clone(),
+            algorithm: self.algorithm,
+            secret: self.secret.clone(),
+            private_key_path: self.private_key_path.clone(),
+            verify_until: None,
+        }
+    }
+}
+
+impl PasswordConfig {
+    pub fn max_age(&self) -> Option<Duration> {
+        (self.max_age_days > 0).then(|| Duration::days(self.max_age_days.into()))
+    }
+}
+
+impl KeyConfig {
+    fn validate(&self) -> Result<(), ConfigError> {
+        match self.algorithm {
+            Algorithm::HS256 => {
+                if self.secret.is_empty() {
+                    return Err(ConfigError::MissingSecret);
+                }
+                if self.secret.len() < MIN_SECRET_LENGTH {
+                    return Err(ConfigError::SecretTooShort);
+                }
+            }
+            Algorithm::RS256 | Algorithm::ES256 | Algorithm::EdDSA => {
+                if self.private_key_path.is_none() {
+                    return Err(ConfigError::MissingPrivateKey(self.algorithm));
+                }
+            }
+            other => return Err(ConfigError::UnsupportedAlgorithm(other)),
+        }
+
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Loads the config from `CONFIG_FILE` (or `config.toml` if present),
+    /// then applies environment variable overrides and validates the result.
+    pub fn load() -> Result<Self, ConfigError> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => Some(read_file(&path)?),
+            Err(_) if fs::metadata(DEFAULT_CONFIG_FILE).is_ok() => {
+                Some(read_file(DEFAULT_CONFIG_FILE)?)
+            }
+            Err(_) => None,
+        };
+
+        Self::from_sources(file.as_deref(), |name| env::var(name).ok())
+    }
+
+    pub fn from_sources(
+        file: Option<&str>,
+        env: impl Fn(&str) -> Option<String>,
+    ) -> Result<Self, ConfigError> {
+        let mut config = match file {
+            Some(contents) => toml::from_str(contents)?,
+            None => Config::default(),
+        };
+
+        if let Some(value) = env("GRPC_ADDR") {
+            config.grpc_addr = value;
+        }
+        if let Some(value) = env("REST_ADDR") {
+            config.rest_addr = value;
+        }
+        if let Some(value) = env("JWT_ISSUER") {
+            config.token.issuer = value;
+        }
+        if let Some(value) = env("JWT_KID") {
+            config.token.kid = value;
+        }
+        if let Some(value) = env("JWT_ALGORITHM") {
+            config.token.algorithm = parse_env("JWT_ALGORITHM", value)?;
+        }
+        if let Some(value) = env("JWT_SECRET") {
+            config.token.secret = value;
+        }
+        if let Some(value) = env("JWT_PRIVATE_KEY_PATH") {
+            config.token.private_key_path = Some(value);
+        }
+        if let Some(value) = env("JWT_TTL_SECONDS") {
+            config.token.ttl_seconds = parse_env("JWT_TTL_SECONDS", value)?;
+        }
+        if let Some(value) = env("JWT_REFRESH_TTL_SECONDS") {
+            config.token.refresh_ttl_seconds = parse_env("JWT_REFRESH_TTL_SECONDS", value)?;
+        }
+        if let Some(value) = env("OAUTH_CLIENTS") {
+            config.oauth.clients = parse_clients(value)?;
+        }
+        if let Some(value) = env("MAIL_BACKEND") {
+            config.mail.backend = parse_env("MAIL_BACKEND", value)?;
+        }
+        if let Some(value) = env("MAIL_FROM") {
+            config.mail.from = value;
+        }
+        if let Some(value) = env("SMTP_HOST") {
+            config.mail.smtp_host = value;
+        }
+        if let Some(value) = env("SMTP_PORT") {
+            config.mail.smtp_port = parse_env("SMTP_PORT", value)?;
+        }
+        if let Some(value) = env("SMTP_USERNAME") {
+            config.mail.smtp_username = Some(value);
+        }
+        if let Some(value) = env("SMTP_PASSWORD") {
+            config.mail.smtp_password = Some(value);
+        }
+        if let Some(value) = env("PASSWORD_RESET_URL") {
+            config.mail.password_reset_url = value;
+        }
+        if let Some(value) = env("EMAIL_VERIFICATION_URL") {
+            config.mail.email_verification_url = value;
+        }
+        if let Some(value) = env("EMAIL_CHANGE_URL") {
+            config.mail.email_change_url = value;
+        }
+        if let Some(value) = env("REQUIRE_VERIFIED_EMAIL") {
+            config.admin.require_verified_email = parse_env("REQUIRE_VERIFIED_EMAIL", value)?;
+        }
+        if let Some(value) = env("ARGON2_MEMORY_KIB") {
+            config.password.memory_kib = parse_env("ARGON2_MEMORY_KIB", value)?;
+        }
+        if let Some(value) = env("ARGON2_ITERATIONS") {
+            config.password.iterations = parse_env("ARGON2_ITERATIONS", value)?;
+        }
+        if let Some(value) = env("ARGON2_PARALLELISM") {
+            config.password.parallelism = parse_env("ARGON2_PARALLELISM", value)?;
+        }
+        if let Some(value) = env("PASSWORD_PEPPER") {
+            config.password.pepper = Some(value);
+        }
+        if let Some(value) = env("PASSWORD_BLOCKLIST_PATH") {
+            config.password.blocklist_path = Some(value);
+        }
+        if let Some(value) = env("PASSWORD_HISTORY") {
+            config.password.history = parse_env("PASSWORD_HISTORY", value)?;
+        }
+        if let Some(value) = env("PASSWORD_MAX_AGE_DAYS") {
+            config.password.max_age_days = parse_env("PASSWORD_MAX_AGE_DAYS", value)?;
+        }
+        if let Some(value) = env("LOCKOUT_MAX_ACCOUNT_FAILURES") {
+            config.lockout.max_account_failures = parse_env("LOCKOUT_MAX_ACCOUNT_FAILURES", value)?;
+        }
+        if let Some(value) = env("LOCKOUT_MAX_IP_FAILURES") {
+            config.lockout.max_ip_failures = parse_env("LOCKOUT_MAX_IP_FAILURES", value)?;
+        }
+        if let Some(value) = env("LOCKOUT_TRUSTED_PROXIES") {
+            config.lockout.trusted_proxies = parse_env("LOCKOUT_TRUSTED_PROXIES", value)?;
+        }
+        if let Some(value) = env("TABLE_SESSION_IDLE_TTL_SECONDS") {
+            config.table_session.idle_ttl_seconds = parse_env("TABLE_SESSION_IDLE_TTL_SECONDS", value)?;
+        }
+
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        validate_addr("grpc_addr", &self.grpc_addr)?;
+        validate_addr("rest_addr", &self.rest_addr)?;
+
+        self.token.current_key().validate()?;
+
+        let mut kids = vec![self.token.kid.as_str()];
+        for key in &self.token.previous_keys {
+            key.validate()?;
+
+            if kids.contains(&key.kid.as_str()) {
+                return Err(ConfigError::DuplicateKid(key.kid.clone()));
+            }
+            kids.push(&key.kid);
+        }
+
+        if self.token.ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.ttl_seconds"));
+        }
+        if self.token.refresh_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.refresh_ttl_seconds"));
+        }
+        if self.token.rotation_grace_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.rotation_grace_seconds"));
+        }
+        if self.token.revocation_sync_seconds == 0 {
+            return Err(ConfigError::NonPositive("token.revocation_sync_seconds"));
+        }
+
+        if self.token.password_reset_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.password_reset_ttl_seconds"));
+        }
+        if self.token.email_verification_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.email_verification_ttl_seconds"));
+        }
+        if self.token.mfa_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.mfa_ttl_seconds"));
+        }
+        if self.token.password_change_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.password_change_ttl_seconds"));
+        }
+        if self.token.table_session_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("token.table_session_ttl_seconds"));
+        }
+
+        argon2::Params::new(
+            self.password.memory_kib,
+            self.password.iterations,
+            self.password.parallelism,
+            None,
+        )
+        .map_err(ConfigError::InvalidArgon2Params)?;
+        if self.password.pepper.as_deref() == Some("") {
+            return Err(ConfigError::EmptyPepper);
+        }
+
+        if self.lockout.max_account_failures <= 0 {
+            return Err(ConfigError::NonPositive("lockout.max_account_failures"));
+        }
+        if self.lockout.max_ip_failures <= 0 {
+            return Err(ConfigError::NonPositive("lockout.max_ip_failures"));
+        }
+        if self.lockout.window_seconds <= 0 {
+            return Err(ConfigError::NonPositive("lockout.window_seconds"));
+        }
+        if self.lockout.base_lockout_seconds <= 0 {
+            return Err(ConfigError::NonPositive("lockout.base_lockout_seconds"));
+        }
+        if self.lockout.max_lockout_seconds < self.lockout.base_lockout_seconds {
+            return Err(ConfigError::MaxLockoutTooShort);
+        }
+
+        if self.table_session.idle_ttl_seconds <= 0 {
+            return Err(ConfigError::NonPositive("table_session.idle_ttl_seconds"));
+        }
+        if self.table_session.expiry_sweep_seconds == 0 {
+            return Err(ConfigError::NonPositive("table_session.expiry_sweep_seconds"));
+        }
+        if self.table_session.expiry_batch_size <= 0 {
+            return Err(ConfigError::NonPositive("table_session.expiry_batch_size"));
+        }
+
+        if self.mail.backend == MailBackend::Smtp && self.mail.smtp_host.is_empty() {
+            return Err(ConfigError::MissingSmtpHost);
+        }
+
+        for client in &self.oauth.clients {
+            if client.client_secret.is_empty() {
+                return Err(ConfigError::EmptyClientSecret(client.client_id.clone()));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn read_file(path: &str) -> Result<String, ConfigError> {
+    fs::read_to_string(path).map_err(|source| ConfigError::ReadFile {
+        path: path.to_string(),
+        source,
+    })
+}
+
+fn parse_env<T: FromStr>(name: &'static str, value: String) -> Result<T, ConfigError> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidEnv { name, value })
+}
+
+/// Parses `OAUTH_CLIENTS`, a comma separated list of `client_id:client_secret` pairs.
+fn parse_clients(value: String) -> Result<Vec<OAuthClientConfig>, ConfigError> {
+    value
+        .split(',')
+        .filter(|entry| !entry.trim().is_empty())
+        .map(|entry| match entry.trim().split_once(':') {
+            Some((client_id, client_secret)) => Ok(OAuthClientConfig {
+                client_id: client_id.to_string(),
+                client_secret: client_secret.to_string(),
+            }),
+            None => Err(ConfigError::InvalidEnv {
+                name: "OAUTH_CLIENTS",
+                value: value.clone(),
+            }),
+        })
+        .collect()
+}
+
+fn validate_addr(name: &'static str, value: &str) -> Result<(), ConfigError> {
+    value
+        .parse::<SocketAddr>()
+        .map(|_| ())
+        .map_err(|_| ConfigError::InvalidAddr {
+            name,
+            value: value.to_string(),
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    const SECRET: &str = "0123456789abcdef0123456789abcdef";
+
+    fn env_from(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn test_load_from_env() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("JWT_TTL_SECONDS", "60")]),
+        )
+        .unwrap();
+
+        assert_eq!(config.token.secret, SECRET);
+        assert_eq!(config.token.ttl_seconds, 60);
+        assert_eq!(config.token.issuer, "sigma-authentication");
+        assert_eq!(config.rest_addr, "0.0.0.0:8082");
+    }
+
+    #[test]
+    fn test_load_from_file_with_env_override() {
+        let file = format!(
+            r#"
+            rest_addr = "127.0.0.1:9000"
+
+            [token]
+            issuer = "sigma"
+            secret = "{SECRET}"
+            "#
+        );
+
+        let config = Config::from_sources(
+            Some(&file),
+            env_from(&[("JWT_ISSUER", "overridden")]),
+        )
+        .unwrap();
+
+        assert_eq!(config.rest_addr, "127.0.0.1:9000");
+        assert_eq!(config.token.issuer, "overridden");
+        assert_eq!(config.token.secret, SECRET);
+    }
+
+    #[test]
+    fn test_missing_secret() {
+        let result = Config::from_sources(None, env_from(&[]));
+
+        assert!(matches!(result, Err(ConfigError::MissingSecret)));
+    }
+
+    #[test]
+    fn test_secret_too_short() {
+        let result = Config::from_sources(None, env_from(&[("JWT_SECRET", "asdf")]));
+
+        assert!(matches!(result, Err(ConfigError::SecretTooShort)));
+    }
+
+    #[test]
+    fn test_asymmetric_without_secret() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[("JWT_ALGORITHM", "ES256"), ("JWT_PRIVATE_KEY_PATH", "key.pem")]),
+        )
+        .unwrap();
+
+        assert_eq!(config.token.algorithm, Algorithm::ES256);
+        assert_eq!(config.token.private_key_path.as_deref(), Some("key.pem"));
+    }
+
+    #[test]
+    fn test_asymmetric_missing_key() {
+        let result = Config::from_sources(None, env_from(&[("JWT_ALGORITHM", "RS256")]));
+
+        assert!(matches!(result, Err(ConfigError::MissingPrivateKey(Algorithm::RS256))));
+    }
+
+    #[test]
+    fn test_unsupported_algorithm() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("JWT_ALGORITHM", "HS512")]),
+        );
+
+        assert!(matches!(result, Err(ConfigError::UnsupportedAlgorithm(Algorithm::HS512))));
+    }
+
+    #[test]
+    fn test_previous_keys() {
+        let file = format!(
+            r#"
+            [token]
+            kid = "2025-06"
+            secret = "{SECRET}"
+
+            [[token.previous_keys]]
+            kid = "2025-05"
+            secret = "{SECRET}"
+            verify_until = "2025-07-01T00:00:00Z"
+            "#
+        );
+
+        let config = Config::from_sources(Some(&file), env_from(&[])).unwrap();
+
+        assert_eq!(config.token.kid, "2025-06");
+        assert_eq!(config.token.previous_keys.len(), 1);
+        assert_eq!(config.token.previous_keys[0].kid, "2025-05");
+        assert!(config.token.previous_keys[0].verify_until.is_some());
+    }
+
+    #[test]
+    fn test_duplicate_kid() {
+        let file = format!(
+            r#"
+            [token]
+            secret = "{SECRET}"
+
+            [[token.previous_keys]]
+            kid = "default"
+            secret = "{SECRET}"
+            "#
+        );
+
+        let result = Config::from_sources(Some(&file), env_from(&[]));
+
+        assert!(matches!(result, Err(ConfigError::DuplicateKid(..))));
+    }
+
+    #[test]
+    fn test_invalid_ttl() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("JWT_TTL_SECONDS", "soon")]),
+        );
+
+        assert!(matches!(result, Err(ConfigError::InvalidEnv { .. })));
+    }
+
+    #[test]
+    fn test_invalid_addr() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("GRPC_ADDR", "localhost")]),
+        );
+
+        assert!(matches!(result, Err(ConfigError::InvalidAddr { .. })));
+    }
+
+    #[test]
+    fn test_oauth_clients_from_env() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("OAUTH_CLIENTS", "orders:abc, payments:def")]),
+        )
+        .unwrap();
+
+        assert_eq!(config.oauth.clients.len(), 2);
+        assert_eq!(config.oauth.clients[1].client_id, "payments");
+        assert_eq!(config.oauth.clients[1].client_secret, "def");
+    }
+
+    #[test]
+    fn test_oauth_client_empty_secret() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("OAUTH_CLIENTS", "orders:")]),
+        );
+
+        assert!(matches!(result, Err(ConfigError::EmptyClientSecret(..))));
+    }
+
+    #[test]
+    fn test_smtp_requires_host() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("MAIL_BACKEND", "smtp")]),
+        );
+        assert!(matches!(result, Err(ConfigError::MissingSmtpHost)));
+
+        let config = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("MAIL_BACKEND", "smtp"), ("SMTP_HOST", "mail.example.com")]),
+        )
+        .unwrap();
+        assert_eq!(config.mail.backend, MailBackend::Smtp);
+        assert_eq!(config.mail.smtp_host, "mail.example.com");
+    }
+
+    #[test]
+    fn test_require_verified_email() {
+        let config = Config::from_sources(None, env_from(&[("JWT_SECRET", SECRET)])).unwrap();
+        assert!(!config.admin.require_verified_email);
+
+        let config = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("REQUIRE_VERIFIED_EMAIL", "true")]),
+        )
+        .unwrap();
+        assert!(config.admin.require_verified_email);
+    }
+
+    #[test]
+    fn test_lockout_from_env() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[
+                ("JWT_SECRET", SECRET),
+                ("LOCKOUT_MAX_ACCOUNT_FAILURES", "3"),
+                ("LOCKOUT_TRUSTED_PROXIES", "2"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.lockout.max_account_failures, 3);
+        assert_eq!(config.lockout.max_ip_failures, 20);
+        assert_eq!(config.lockout.trusted_proxies, 2);
+    }
+
+    #[test]
+    fn test_lockout_non_positive() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("LOCKOUT_MAX_IP_FAILURES", "0")]),
+        );
+
+        assert!(matches!(result, Err(ConfigError::NonPositive("lockout.max_ip_failures"))));
+    }
+
+    #[test]
+    fn test_table_session_from_env() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("TABLE_SESSION_IDLE_TTL_SECONDS", "900")]),
+        )
+        .unwrap();
+
+        assert_eq!(config.table_session.idle_ttl_seconds, 900);
+        assert_eq!(config.table_session.expiry_batch_size, 500);
+
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("TABLE_SESSION_IDLE_TTL_SECONDS", "0")]),
+        );
+        assert!(matches!(result, Err(ConfigError::NonPositive("table_session.idle_ttl_seconds"))));
+    }
+
+    #[test]
+    fn test_password_from_env() {
+        let config = Config::from_sources(
+            None,
+            env_from(&[
+                ("JWT_SECRET", SECRET),
+                ("ARGON2_MEMORY_KIB", "65536"),
+                ("ARGON2_ITERATIONS", "3"),
+                ("PASSWORD_PEPPER", "pepper"),
+                ("PASSWORD_BLOCKLIST_PATH", "/etc/pwned"),
+                ("PASSWORD_MAX_AGE_DAYS", "90"),
+            ]),
+        )
+        .unwrap();
+
+        assert_eq!(config.password.memory_kib, 65536);
+        assert_eq!(config.password.iterations, 3);
+        assert_eq!(config.password.parallelism, 1);
+        assert_eq!(config.password.pepper.as_deref(), Some("pepper"));
+        assert_eq!(config.password.blocklist_path.as_deref(), Some("/etc/pwned"));
+        assert_eq!(config.password.history, 5);
+        assert_eq!(config.password.max_age_days, 90);
+    }
+
+    #[test]
+    fn test_invalid_argon2_params() {
+        let result = Config::from_sources(
+            None,
+            env_from(&[("JWT_SECRET", SECRET), ("ARGON2_ITERATIONS", "0")]),
+        );
+
+        assert!(matches!(result, Err(ConfigError::InvalidArgon2Params(_))));
+    }
+}
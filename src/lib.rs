@@ -1,7 +1,12 @@
 pub mod app;
+pub mod config;
 pub mod database;
+pub mod mail;
 pub mod utils;
 
 pub mod admin;
+pub mod lockout;
+pub mod mfa;
+pub mod role;
 pub mod table_session;
 pub mod token;
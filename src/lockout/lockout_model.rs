@@ -0,0 +1,25 @@
+use chrono::{DateTime, Utc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockoutKind {
+    Account,
+    Ip,
+}
+
+impl LockoutKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LockoutKind::Account => "account",
+            LockoutKind::Ip => "ip",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct LoginFailureModel {
+    pub kind: String,
+    pub subject: String,
+    pub failures: i32,
+    pub last_failure_at: DateTime<Utc>,
+    pub locked_until: Option<DateTime<Utc>>,
+}
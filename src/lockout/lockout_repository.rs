@@ -0,0 +1,202 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, query, query_as};
+use thiserror::Error;
+
+use super::LoginFailureModel;
+
+#[derive(Error, Debug)]
+pub enum LockoutRepositoryError {
+    #[error("An error occurred with the database: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct LockoutRepository {
+    pool: PgPool,
+}
+
+impl LockoutRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find(
+        &self,
+        kind: String,
+        subject: String,
+    ) -> Result<Option<LoginFailureModel>, LockoutRepositoryError> {
+        Ok(query_as!(
+            LoginFailureModel,
+            r#"
+            SELECT kind, subject, failures, last_failure_at, locked_until
+            FROM login_failures
+            WHERE kind = $1 AND subject = $2
+            "#,
+            kind,
+            subject
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Counts a failure, starting over if the previous one happened before
+    /// `window_start`. The increment is atomic, so concurrent attempts from
+    /// several replicas are all counted.
+    pub async fn record_failure(
+        &self,
+        kind: String,
+        subject: String,
+        window_start: DateTime<Utc>,
+    ) -> Result<LoginFailureModel, LockoutRepositoryError> {
+        Ok(query_as!(
+            LoginFailureModel,
+            r#"
+            INSERT INTO login_failures (kind, subject, failures)
+            VALUES ($1, $2, 1)
+            ON CONFLICT (kind, subject) DO UPDATE SET
+                failures = CASE
+                    WHEN login_failures.last_failure_at < $3 THEN 1
+                    ELSE login_failures.failures + 1
+                END,
+                last_failure_at = NOW()
+            RETURNING kind, subject, failures, last_failure_at, locked_until
+            "#,
+            kind,
+            subject,
+            window_start
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Never shortens an existing lock.
+    pub async fn lock(
+        &self,
+        kind: String,
+        subject: String,
+        locked_until: DateTime<Utc>,
+    ) -> Result<(), LockoutRepositoryError> {
+        query!(
+            r#"
+            UPDATE login_failures
+            SET locked_until = GREATEST(locked_until, $3)
+            WHERE kind = $1 AND subject = $2
+            "#,
+            kind,
+            subject,
+            locked_until
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn clear(&self, kind: String, subject: String) -> Result<(), LockoutRepositoryError> {
+        query!(
+            r#"
+            DELETE FROM login_failures
+            WHERE kind = $1 AND subject = $2
+            "#,
+            kind,
+            subject
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Drops entries that no longer count towards or hold a lock.
+    pub async fn delete_stale(&self, window_start: DateTime<Utc>) -> Result<(), LockoutRepositoryError> {
+        query!(
+            r#"
+            DELETE FROM login_failures
+            WHERE last_failure_at < $1 AND (locked_until IS NULL OR locked_until <= NOW())
+            "#,
+            window_start
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_failure_counts_within_window() {
+        let test_db = setup_test_db().await;
+        let repo = LockoutRepository::new(test_db.pool);
+        let window_start = Utc::now() - Duration::minutes(15);
+
+        repo.record_failure("account".to_string(), "a@b.com".to_string(), window_start).await.unwrap();
+        let failure = repo
+            .record_failure("account".to_string(), "a@b.com".to_string(), window_start)
+            .await
+            .unwrap();
+        let other = repo
+            .record_failure("ip".to_string(), "a@b.com".to_string(), window_start)
+            .await
+            .unwrap();
+
+        assert_eq!(failure.failures, 2);
+        assert_eq!(other.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_record_failure_resets_after_window() {
+        let test_db = setup_test_db().await;
+        let repo = LockoutRepository::new(test_db.pool);
+
+        repo.record_failure("account".to_string(), "a@b.com".to_string(), Utc::now()).await.unwrap();
+
+        // every earlier failure is outside a window starting in the future
+        let failure = repo
+            .record_failure("account".to_string(), "a@b.com".to_string(), Utc::now() + Duration::minutes(1))
+            .await
+            .unwrap();
+
+        assert_eq!(failure.failures, 1);
+    }
+
+    #[tokio::test]
+    async fn test_lock_never_shortens() {
+        let test_db = setup_test_db().await;
+        let repo = LockoutRepository::new(test_db.pool);
+        let later = Utc::now() + Duration::hours(1);
+
+        repo.record_failure("ip".to_string(), "127.0.0.1".to_string(), Utc::now()).await.unwrap();
+        repo.lock("ip".to_string(), "127.0.0.1".to_string(), later).await.unwrap();
+        repo.lock("ip".to_string(), "127.0.0.1".to_string(), Utc::now()).await.unwrap();
+
+        let found = repo.find("ip".to_string(), "127.0.0.1".to_string()).await.unwrap().unwrap();
+        assert_eq!(found.locked_until.unwrap().timestamp(), later.timestamp());
+    }
+
+    #[tokio::test]
+    async fn test_clear_and_delete_stale() {
+        let test_db = setup_test_db().await;
+        let repo = LockoutRepository::new(test_db.pool);
+
+        repo.record_failure("account".to_string(), "a@b.com".to_string(), Utc::now()).await.unwrap();
+        repo.record_failure("account".to_string(), "c@d.com".to_string(), Utc::now()).await.unwrap();
+        repo.record_failure("account".to_string(), "e@f.com".to_string(), Utc::now()).await.unwrap();
+        repo.lock("account".to_string(), "e@f.com".to_string(), Utc::now() + Duration::hours(1))
+            .await
+            .unwrap();
+
+        repo.clear("account".to_string(), "a@b.com".to_string()).await.unwrap();
+        assert!(repo.find("account".to_string(), "a@b.com".to_string()).await.unwrap().is_none());
+
+        repo.delete_stale(Utc::now() + Duration::minutes(1)).await.unwrap();
+        assert!(repo.find("account".to_string(), "c@d.com".to_string()).await.unwrap().is_none());
+        assert!(repo.find("account".to_string(), "e@f.com".to_string()).await.unwrap().is_some());
+    }
+}
@@ -0,0 +1,53 @@
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, FromRequestParts, Json};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use hyper::header::RETRY_AFTER;
+use serde_json::json;
+
+use crate::app::RestState;
+
+use super::LockoutServiceError;
+
+impl IntoResponse for LockoutServiceError {
+    fn into_response(self) -> Response {
+        match self {
+            LockoutServiceError::Locked { retry_after } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [(RETRY_AFTER, retry_after.num_seconds().to_string())],
+                Json(json!({ "message": self.to_string() })),
+            )
+                .into_response(),
+            LockoutServiceError::Repository(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+        }
+    }
+}
+
+/// Address of the client, `None` if the server was started without connect info.
+pub struct ClientIp(pub Option<IpAddr>);
+
+impl FromRequestParts<RestState> for ClientIp {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &RestState) -> Result<Self, Self::Rejection> {
+        let forwarded = parts
+            .headers
+            .get("x-forwarded-for")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| state.lockout_service.forwarded_client_ip(value));
+
+        if forwarded.is_some() {
+            return Ok(ClientIp(forwarded));
+        }
+
+        let peer = parts
+            .extensions
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+
+        Ok(ClientIp(peer))
+    }
+}
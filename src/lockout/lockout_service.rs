@@ -0,0 +1,248 @@
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use thiserror::Error;
+
+use crate::config::LockoutConfig;
+
+use super::{LockoutKind, LockoutRepository, LockoutRepositoryError};
+
+#[derive(Error, Debug)]
+pub enum LockoutServiceError {
+    #[error("{0}")]
+    Repository(#[from] LockoutRepositoryError),
+
+    #[error("Too many failed login attempts, try again in {} seconds", retry_after.num_seconds())]
+    Locked { retry_after: Duration },
+}
+
+/// Locks emails and client IPs after repeated failed logins. State lives in
+/// Postgres, so a lock holds on every replica.
+pub struct LockoutService {
+    repo: LockoutRepository,
+    config: LockoutConfig,
+}
+
+impl LockoutService {
+    pub fn new(repo: LockoutRepository) -> Self {
+        Self {
+            repo,
+            config: LockoutConfig::default(),
+        }
+    }
+
+    pub fn with_config(mut self, config: LockoutConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Picks the client IP out of an `X-Forwarded-For` value, counting the
+    /// trusted proxies from the right. `None` if no proxies are trusted or the
+    /// header is too short.
+    pub fn forwarded_client_ip(&self, forwarded_for: &str) -> Option<IpAddr> {
+        if self.config.trusted_proxies == 0 {
+            return None;
+        }
+
+        forwarded_for
+            .rsplit(',')
+            .nth(self.config.trusted_proxies - 1)
+            .and_then(|ip| ip.trim().parse().ok())
+    }
+
+    /// Fails with `Locked` if either the email or the IP is currently locked.
+    pub async fn check(&self, email: &str, ip: Option<IpAddr>) -> Result<(), LockoutServiceError> {
+        let now = Utc::now();
+        let mut locked_until = self.locked_until(LockoutKind::Account, account_key(email)).await?;
+
+        if let Some(ip) = ip {
+            locked_until = locked_until.max(self.locked_until(LockoutKind::Ip, ip.to_string()).await?);
+        }
+
+        match locked_until {
+            Some(until) if until > now => Err(LockoutServiceError::Locked {
+                retry_after: round_up_to_second(until - now),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn record_failure(&self, email: &str, ip: Option<IpAddr>) -> Result<(), LockoutServiceError> {
+        self.record(LockoutKind::Account, account_key(email), self.config.max_account_failures)
+            .await?;
+
+        if let Some(ip) = ip {
+            self.record(LockoutKind::Ip, ip.to_string(), self.config.max_ip_failures)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Only clears the account. A successful login must not reset the IP,
+    /// or an attacker with one valid account could keep guessing others.
+    pub async fn record_success(&self, email: &str) -> Result<(), LockoutServiceError> {
+        self.repo
+            .clear(LockoutKind::Account.as_str().to_string(), account_key(email))
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_stale(&self) -> Result<(), LockoutServiceError> {
+        self.repo.delete_stale(self.window_start()).await?;
+
+        Ok(())
+    }
+
+    async fn locked_until(
+        &self,
+        kind: LockoutKind,
+        subject: String,
+    ) -> Result<Option<DateTime<Utc>>, LockoutServiceError> {
+        Ok(self
+            .repo
+            .find(kind.as_str().to_string(), subject)
+            .await?
+            .and_then(|failure| failure.locked_until))
+    }
+
+    async fn record(&self, kind: LockoutKind, subject: String, max_failures: i32) -> Result<(), LockoutServiceError> {
+        let failure = self
+            .repo
+            .record_failure(kind.as_str().to_string(), subject.clone(), self.window_start())
+            .await?;
+
+        if let Some(lockout) = self.lockout_after(failure.failures, max_failures) {
+            self.repo
+                .lock(kind.as_str().to_string(), subject, Utc::now() + lockout)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// `base_lockout_seconds` at the threshold, doubled for every failure past it.
+    fn lockout_after(&self, failures: i32, max_failures: i32) -> Option<Duration> {
+        let excess = failures.checked_sub(max_failures).filter(|excess| *excess >= 0)?;
+        let seconds = self
+            .config
+            .base_lockout_seconds
+            .saturating_mul(1i64 << excess.min(32))
+            .min(self.config.max_lockout_seconds);
+
+        Some(Duration::seconds(seconds))
+    }
+
+    fn window_start(&self) -> DateTime<Utc> {
+        Utc::now() - Duration::seconds(self.config.window_seconds)
+    }
+}
+
+/// Emails are compared case-insensitively, so changing case does not reset the count.
+fn account_key(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn round_up_to_second(duration: Duration) -> Duration {
+    Duration::seconds((duration.num_milliseconds() + 999) / 1000)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+    const IP: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    async fn setup_service() -> (crate::database::TestDb, LockoutService) {
+        let test_db = setup_test_db().await;
+        let service = LockoutService::new(LockoutRepository::new(test_db.pool.clone())).with_config(LockoutConfig {
+            max_account_failures: 3,
+            max_ip_failures: 5,
+            ..LockoutConfig::default()
+        });
+
+        (test_db, service)
+    }
+
+    #[tokio::test]
+    async fn test_forwarded_client_ip() {
+        let (_test_db, service) = setup_service().await;
+        assert_eq!(service.forwarded_client_ip("10.0.0.1"), None);
+
+        let service = service.with_config(LockoutConfig { trusted_proxies: 2, ..LockoutConfig::default() });
+        let forwarded_for = "1.1.1.1, 10.0.0.1, 10.0.0.2";
+
+        // the leftmost entry is up to the client
+        assert_eq!(service.forwarded_client_ip(forwarded_for), Some("10.0.0.1".parse().unwrap()));
+        assert_eq!(service.forwarded_client_ip("10.0.0.2"), None);
+    }
+
+    #[tokio::test]
+    async fn test_account_locked_after_max_failures() {
+        let (_test_db, service) = setup_service().await;
+
+        for _ in 0..2 {
+            service.record_failure(EMAIL, None).await.unwrap();
+        }
+        assert!(service.check(EMAIL, None).await.is_ok());
+
+        service.record_failure(EMAIL, None).await.unwrap();
+
+        let Err(LockoutServiceError::Locked { retry_after }) = service.check(EMAIL, None).await else {
+            panic!()
+        };
+        assert!(retry_after > Duration::zero() && retry_after <= Duration::seconds(30));
+
+        // the email is locked regardless of case
+        assert!(service.check("ASDF@gmail.com", None).await.is_err());
+        assert!(service.check("other@gmail.com", None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_ip_locked_across_accounts() {
+        let (_test_db, service) = setup_service().await;
+
+        for i in 0..5 {
+            service.record_failure(&format!("user{i}@gmail.com"), Some(IP)).await.unwrap();
+        }
+
+        assert!(service.check("new@gmail.com", Some(IP)).await.is_err());
+        assert!(service.check("new@gmail.com", None).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_success_clears_account_but_not_ip() {
+        let (_test_db, service) = setup_service().await;
+
+        for _ in 0..2 {
+            service.record_failure(EMAIL, Some(IP)).await.unwrap();
+        }
+        service.record_success(EMAIL).await.unwrap();
+
+        // two more failures would have locked the account without the reset
+        for _ in 0..2 {
+            service.record_failure(EMAIL, Some(IP)).await.unwrap();
+        }
+        assert!(service.check(EMAIL, None).await.is_ok());
+
+        service.record_failure(EMAIL, Some(IP)).await.unwrap();
+        assert!(service.check("other@gmail.com", Some(IP)).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_lockout_backoff() {
+        let (_test_db, service) = setup_service().await;
+
+        assert_eq!(service.lockout_after(2, 3), None);
+        assert_eq!(service.lockout_after(3, 3), Some(Duration::seconds(30)));
+        assert_eq!(service.lockout_after(4, 3), Some(Duration::seconds(60)));
+        assert_eq!(service.lockout_after(6, 3), Some(Duration::seconds(240)));
+        assert_eq!(service.lockout_after(100, 3), Some(Duration::hours(1)));
+    }
+}
@@ -0,0 +1,9 @@
+mod lockout_model;
+mod lockout_repository;
+mod lockout_rest;
+mod lockout_service;
+
+pub use lockout_model::*;
+pub use lockout_repository::*;
+pub use lockout_rest::*;
+pub use lockout_service::*;
@@ -0,0 +1,55 @@
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+use lettre::message::Mailbox;
+use uuid::Uuid;
+
+use super::{Email, Mailer, MailerError, build_message};
+
+/// Writes every message as an `.eml` file instead of sending it.
+pub struct FileMailer {
+    from: Mailbox,
+    dir: PathBuf,
+}
+
+impl FileMailer {
+    pub fn new(from: Mailbox, dir: impl Into<PathBuf>) -> Self {
+        Self { from, dir: dir.into() }
+    }
+}
+
+#[async_trait]
+impl Mailer for FileMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        let message = build_message(self.from.clone(), email)?;
+
+        tokio::fs::create_dir_all(&self.dir).await?;
+        tokio::fs::write(self.dir.join(format!("{}.eml", Uuid::new_v4())), message.formatted()).await?;
+
+        Ok(())
+    }
+}
+
+/// Keeps sent messages so tests can read them back.
+#[derive(Default)]
+pub struct InMemoryMailer {
+    sent: Mutex<Vec<Email>>,
+}
+
+impl InMemoryMailer {
+    pub fn sent(&self) -> Vec<Email> {
+        self.sent.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl Mailer for InMemoryMailer {
+    async fn send(&self, email: Email) -> Result<(), MailerError> {
+        // catch bad addresses the same way the real backends would
+        email.to.parse::<Mailbox>()?;
+        self.sent.lock().unwrap().push(email);
+
+        Ok(())
+    }
+}
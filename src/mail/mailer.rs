@@ -0,0 +1,53 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use lettre::Message;
+use lettre::message::Mailbox;
+use thiserror::Error;
+
+use crate::config::{MailBackend, MailConfig};
+
+use super::{FileMailer, InMemoryMailer, SmtpMailer};
+
+#[derive(Error, Debug)]
+pub enum MailerError {
+    #[error("Invalid email address: {0}")]
+    InvalidAddress(#[from] lettre::address::AddressError),
+
+    #[error("Unable to build email: {0}")]
+    Build(#[from] lettre::error::Error),
+
+    #[error("Unable to send email: {0}")]
+    Smtp(#[from] lettre::transport::smtp::Error),
+
+    #[error("Unable to write email: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+#[derive(Debug, Clone)]
+pub struct Email {
+    pub to: String,
+    pub subject: String,
+    pub body: String,
+}
+
+#[async_trait]
+pub trait Mailer: Send + Sync {
+    async fn send(&self, email: Email) -> Result<(), MailerError>;
+}
+
+pub fn mailer_from_config(config: &MailConfig) -> Result<Arc<dyn Mailer>, MailerError> {
+    Ok(match config.backend {
+        MailBackend::Smtp => Arc::new(SmtpMailer::from_config(config)?),
+        MailBackend::File => Arc::new(FileMailer::new(config.from.parse()?, &config.file_dir)),
+        MailBackend::Memory => Arc::new(InMemoryMailer::default()),
+    })
+}
+
+pub(super) fn build_message(from: Mailbox, email: Email) -> Result<Message, MailerError> {
+    Ok(Message::builder()
+        .from(from)
+        .to(email.to.parse()?)
+        .subject(email.subject)
+        .body(email.body)?)
+}
@@ -0,0 +1,58 @@
+mod file_mailer;
+mod mailer;
+mod smtp_mailer;
+
+pub use file_mailer::*;
+pub use mailer::*;
+pub use smtp_mailer::*;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn email(to: &str) -> Email {
+        Email {
+            to: to.to_string(),
+            subject: "Hello".to_string(),
+            body: "World".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_mailer() {
+        let mailer = InMemoryMailer::default();
+
+        mailer.send(email("asdf@gmail.com")).await.unwrap();
+
+        let sent = mailer.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].to, "asdf@gmail.com");
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_mailer_invalid_address() {
+        let mailer = InMemoryMailer::default();
+
+        let result = mailer.send(email("not an address")).await;
+
+        assert!(matches!(result, Err(MailerError::InvalidAddress(..))));
+        assert!(mailer.sent().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_file_mailer() {
+        let dir = std::env::temp_dir().join(format!("sigma-mail-{}", uuid::Uuid::new_v4()));
+        let mailer = FileMailer::new("Sigma <no-reply@sigma.local>".parse().unwrap(), &dir);
+
+        mailer.send(email("asdf@gmail.com")).await.unwrap();
+
+        let entries: Vec<_> = std::fs::read_dir(&dir).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+
+        let contents = std::fs::read_to_string(entries[0].as_ref().unwrap().path()).unwrap();
+        assert!(contents.contains("To: asdf@gmail.com"));
+        assert!(contents.contains("Subject: Hello"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
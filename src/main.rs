@@ -1,12 +1,21 @@
 use std::error::Error;
+use std::sync::Arc;
 
 use sigma_authentication::app::{GrpcApp, RestApp};
+use sigma_authentication::config::Config;
 use sigma_authentication::database::setup_db;
+use sigma_authentication::lockout::{LockoutRepository, LockoutService};
+use sigma_authentication::table_session::{TableSessionRepository, TableSessionService};
+use sigma_authentication::token::{
+    RefreshTokenRepository, RevocationRepository, RevocationService, TokenService,
+};
+use tokio::signal::unix::{SignalKind, signal};
 use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     let _ = dotenvy::dotenv();
+    let config = Config::load().unwrap_or_else(|e| panic!("Invalid configuration: {e}"));
     let pool = setup_db().await;
 
     sqlx::migrate!("./migrations")
@@ -23,20 +32,101 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .with_level(true)
         .init();
 
+    let token_service = TokenService::from_config(&config.token)
+        .unwrap_or_else(|e| panic!("Unable to load signing key: {e}"));
+    let token_service = Arc::new(token_service);
+
+    // reload signing keys from configuration on SIGHUP
+    let token_service_ = token_service.clone();
+    tokio::spawn(async move {
+        let mut hangup = signal(SignalKind::hangup()).expect("Unable to listen for SIGHUP");
+
+        while hangup.recv().await.is_some() {
+            let result = Config::load()
+                .map_err(|e| e.to_string())
+                .and_then(|config| token_service_.reload(&config.token).map_err(|e| e.to_string()));
+
+            match result {
+                Ok(kid) => tracing::info!("Rotated signing keys, current kid is {}", kid),
+                Err(e) => tracing::error!("Failed to rotate signing keys: {}", e),
+            }
+        }
+    });
+
+    // pull revocations made by other replicas into the in-memory list
+    let revocation_service = RevocationService::new(
+        RevocationRepository::new(pool.clone()),
+        RefreshTokenRepository::new(pool.clone()),
+        token_service.revocations(),
+    );
+    let sync_interval = std::time::Duration::from_secs(config.token.revocation_sync_seconds);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(sync_interval);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = revocation_service.sync().await {
+                tracing::error!("Failed to sync token revocations: {}", e);
+            }
+        }
+    });
+
+    // forget login failures that no longer count towards a lockout
+    let lockout_service = LockoutService::new(LockoutRepository::new(pool.clone()))
+        .with_config(config.lockout.clone());
+    let cleanup_interval = std::time::Duration::from_secs(config.lockout.window_seconds as u64);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(cleanup_interval);
+
+        loop {
+            interval.tick().await;
+            if let Err(e) = lockout_service.delete_stale().await {
+                tracing::error!("Failed to delete stale login failures: {}", e);
+            }
+        }
+    });
+
+    // close table sessions that were left open, every replica may sweep
+    let table_session_service = TableSessionService::new(TableSessionRepository::new(pool.clone()));
+    let sweep_interval = std::time::Duration::from_secs(config.table_session.expiry_sweep_seconds);
+    let batch_size = config.table_session.expiry_batch_size;
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(sweep_interval);
+
+        loop {
+            interval.tick().await;
+            match table_session_service.expire_idle(batch_size).await {
+                Ok(0) => {}
+                Ok(expired) => tracing::info!("Expired {} idle table sessions", expired),
+                Err(e) => tracing::error!("Failed to expire idle table sessions: {}", e),
+            }
+        }
+    });
+
     let pool_ = pool.clone();
+    let token_service_ = token_service.clone();
+    let config_ = config.clone();
     let grpc_task = tokio::spawn(async move {
-        let addr = "[::]:50051";
+        let addr = config_.grpc_addr.clone();
         tracing::info!("Starting gRPC server at {}", addr);
-        let app = GrpcApp::default().with_pool(pool_);
-        app.run(addr).await.unwrap();
+        let app = GrpcApp::default()
+            .with_pool(pool_)
+            .with_token_service(token_service_)
+            .with_config(config_);
+        app.run(&addr).await.unwrap();
     });
 
     let pool_ = pool.clone();
+    let token_service_ = token_service.clone();
+    let config_ = config.clone();
     let rest_task = tokio::spawn(async move {
-        let addr = "0.0.0.0:8082";
+        let addr = config_.rest_addr.clone();
         tracing::info!("Starting REST server at {}", addr);
-        let app = RestApp::default().with_pool(pool_);
-        app.run(addr).await.unwrap();
+        let app = RestApp::default()
+            .with_pool(pool_)
+            .with_token_service(token_service_)
+            .with_config(config_);
+        app.run(&addr).await.unwrap();
     });
 
     let (grpc_result, rest_result) = tokio::join!(grpc_task, rest_task);
@@ -0,0 +1,268 @@
+use sqlx::{PgPool, query, query_as, query_scalar};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::MfaModel;
+
+#[derive(Error, Debug)]
+pub enum MfaRepositoryError {
+    #[error("An error occurred with the database: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct MfaRepository {
+    pool: PgPool,
+}
+
+impl MfaRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Stores a new unconfirmed secret, replacing an earlier unconfirmed one.
+    /// Returns `None` if the admin already has a confirmed secret.
+    pub async fn upsert_pending(
+        &self,
+        admin_id: Uuid,
+        secret: String,
+    ) -> Result<Option<MfaModel>, MfaRepositoryError> {
+        Ok(query_as!(
+            MfaModel,
+            r#"
+            INSERT INTO admin_mfa (admin_id, secret)
+            VALUES ($1, $2)
+            ON CONFLICT (admin_id)
+            DO UPDATE SET secret = EXCLUDED.secret, last_used_step = NULL, created_at = NOW()
+            WHERE admin_mfa.confirmed_at IS NULL
+            RETURNING admin_id, secret, confirmed_at, last_used_step, created_at
+            "#,
+            admin_id,
+            secret
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    pub async fn find(&self, admin_id: Uuid) -> Result<Option<MfaModel>, MfaRepositoryError> {
+        Ok(query_as!(
+            MfaModel,
+            r#"
+            SELECT admin_id, secret, confirmed_at, last_used_step, created_at
+            FROM admin_mfa
+            WHERE admin_id = $1
+            "#,
+            admin_id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Records `step` as used. Returns false if it is not newer than the last
+    /// accepted step, so two requests with the same code cannot both succeed.
+    pub async fn use_step(&self, admin_id: Uuid, step: i64) -> Result<bool, MfaRepositoryError> {
+        let result = query!(
+            r#"
+            UPDATE admin_mfa
+            SET last_used_step = $2
+            WHERE admin_id = $1 AND (last_used_step IS NULL OR last_used_step < $2)
+            "#,
+            admin_id,
+            step
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    /// Confirms the secret and replaces any recovery codes with `code_hashes`.
+    pub async fn confirm(
+        &self,
+        admin_id: Uuid,
+        code_hashes: Vec<String>,
+    ) -> Result<(), MfaRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        query!(
+            r#"
+            UPDATE admin_mfa
+            SET confirmed_at = NOW()
+            WHERE admin_id = $1
+            "#,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM mfa_recovery_codes
+            WHERE admin_id = $1
+            "#,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!(
+            r#"
+            INSERT INTO mfa_recovery_codes (admin_id, code_hash)
+            SELECT $1, code_hash FROM UNNEST($2::VARCHAR[]) AS code_hash
+            "#,
+            admin_id,
+            &code_hashes
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+
+    /// Marks an unused recovery code as used. Returns false if there is none.
+    pub async fn use_recovery_code(
+        &self,
+        admin_id: Uuid,
+        code_hash: String,
+    ) -> Result<bool, MfaRepositoryError> {
+        let result = query!(
+            r#"
+            UPDATE mfa_recovery_codes
+            SET used_at = NOW()
+            WHERE admin_id = $1 AND code_hash = $2 AND used_at IS NULL
+            "#,
+            admin_id,
+            code_hash
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected() == 1)
+    }
+
+    pub async fn count_unused_recovery_codes(&self, admin_id: Uuid) -> Result<i64, MfaRepositoryError> {
+        Ok(query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM mfa_recovery_codes
+            WHERE admin_id = $1 AND used_at IS NULL
+            "#,
+            admin_id
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    pub async fn delete(&self, admin_id: Uuid) -> Result<(), MfaRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        query!(
+            r#"
+            DELETE FROM mfa_recovery_codes
+            WHERE admin_id = $1
+            "#,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!(
+            r#"
+            DELETE FROM admin_mfa
+            WHERE admin_id = $1
+            "#,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    async fn setup_repository() -> (crate::database::TestDb, MfaRepository, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create("asdf@gmail.com".to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let repo = MfaRepository::new(test_db.pool.clone());
+        (test_db, repo, admin.id)
+    }
+
+    #[tokio::test]
+    async fn test_upsert_pending_replaces_unconfirmed_secret() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        repo.upsert_pending(id, "FIRST".to_string()).await.unwrap();
+        repo.upsert_pending(id, "SECOND".to_string()).await.unwrap();
+
+        let found = repo.find(id).await.unwrap().unwrap();
+        assert_eq!(found.secret, "SECOND");
+        assert!(found.confirmed_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_upsert_pending_keeps_confirmed_secret() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        repo.upsert_pending(id, "FIRST".to_string()).await.unwrap();
+        repo.confirm(id, Vec::new()).await.unwrap();
+
+        let result = repo.upsert_pending(id, "SECOND".to_string()).await.unwrap();
+        assert!(result.is_none());
+
+        let found = repo.find(id).await.unwrap().unwrap();
+        assert_eq!(found.secret, "FIRST");
+    }
+
+    #[tokio::test]
+    async fn test_use_step_only_moves_forward() {
+        let (_test_db, repo, id) = setup_repository().await;
+        repo.upsert_pending(id, "SECRET".to_string()).await.unwrap();
+
+        assert!(repo.use_step(id, 10).await.unwrap());
+        assert!(!repo.use_step(id, 10).await.unwrap());
+        assert!(!repo.use_step(id, 9).await.unwrap());
+        assert!(repo.use_step(id, 11).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_recovery_codes() {
+        let (_test_db, repo, id) = setup_repository().await;
+        repo.upsert_pending(id, "SECRET".to_string()).await.unwrap();
+
+        repo.confirm(id, vec!["a".to_string(), "b".to_string()])
+            .await
+            .unwrap();
+
+        assert!(repo.use_recovery_code(id, "a".to_string()).await.unwrap());
+        assert!(!repo.use_recovery_code(id, "a".to_string()).await.unwrap());
+        assert!(!repo.use_recovery_code(id, "c".to_string()).await.unwrap());
+        assert_eq!(repo.count_unused_recovery_codes(id).await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete() {
+        let (_test_db, repo, id) = setup_repository().await;
+        repo.upsert_pending(id, "SECRET".to_string()).await.unwrap();
+        repo.confirm(id, vec!["a".to_string()]).await.unwrap();
+
+        repo.delete(id).await.unwrap();
+
+        assert!(repo.find(id).await.unwrap().is_none());
+        assert_eq!(repo.count_unused_recovery_codes(id).await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,118 @@
+use axum::Router;
+use axum::extract::{Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing;
+use axum_extra::TypedHeader;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Bearer;
+use hyper::StatusCode;
+use serde_json::json;
+
+use crate::app::RestState;
+
+use super::{MfaCodeRequest, MfaServiceError};
+
+pub fn router() -> Router<RestState> {
+    Router::new()
+        .route("/admin/mfa", routing::get(read_mfa_handler))
+        .route("/admin/mfa", routing::delete(disable_mfa_handler))
+        .route("/admin/mfa/enroll", routing::post(enroll_mfa_handler))
+        .route("/admin/mfa/confirm", routing::post(confirm_mfa_handler))
+}
+
+impl IntoResponse for MfaServiceError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            MfaServiceError::AlreadyEnabled => StatusCode::CONFLICT,
+            MfaServiceError::NotEnrolled => StatusCode::NOT_FOUND,
+            MfaServiceError::InvalidCode => StatusCode::BAD_REQUEST,
+            MfaServiceError::Repository(_) | MfaServiceError::Totp(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, Json(json!({ "message": self.to_string() }))).into_response()
+    }
+}
+
+pub async fn read_mfa_handler(
+    State(RestState { token_service, mfa_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    let enabled = mfa_service
+        .is_enabled(admin_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let recovery_codes_remaining = mfa_service
+        .remaining_recovery_codes(admin_id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok((
+        StatusCode::OK,
+        Json(json!({ "enabled": enabled, "recovery_codes_remaining": recovery_codes_remaining })),
+    )
+        .into_response())
+}
+
+pub async fn enroll_mfa_handler(
+    State(RestState { admin_service, token_service, mfa_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    let admin = admin_service
+        .find_by_id(admin_id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+
+    let enrollment = mfa_service
+        .enroll(admin.id, admin.email)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok((StatusCode::OK, Json(enrollment)).into_response())
+}
+
+pub async fn confirm_mfa_handler(
+    State(RestState { token_service, mfa_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Json(MfaCodeRequest { code }): Json<MfaCodeRequest>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    let recovery_codes = mfa_service
+        .confirm(admin_id, code)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok((StatusCode::OK, Json(json!({ "recovery_codes": recovery_codes }))).into_response())
+}
+
+pub async fn disable_mfa_handler(
+    State(RestState { token_service, mfa_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Json(MfaCodeRequest { code }): Json<MfaCodeRequest>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    mfa_service
+        .disable(admin_id, code)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok(StatusCode::OK.into_response())
+}
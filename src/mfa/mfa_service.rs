@@ -0,0 +1,341 @@
+use chrono::Utc;
+use password_hash::rand_core::{OsRng, RngCore};
+use thiserror::Error;
+use totp_rs::{Algorithm, Secret, TOTP, TotpUrlError};
+use uuid::Uuid;
+
+use crate::utils::hash_token;
+
+use super::{MfaEnrollment, MfaModel, MfaRepository, MfaRepositoryError};
+
+const DIGITS: usize = 6;
+const STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Lower case letters and digits without the easily confused 0, 1, l and o.
+const RECOVERY_CODE_ALPHABET: &[u8; 32] = b"abcdefghijkmnpqrstuvwxyz23456789";
+
+#[derive(Error, Debug)]
+pub enum MfaServiceError {
+    #[error("{0}")]
+    Repository(#[from] MfaRepositoryError),
+
+    #[error("Failed to build the TOTP secret: {0}")]
+    Totp(#[from] TotpUrlError),
+
+    #[error("Two-factor authentication is already enabled")]
+    AlreadyEnabled,
+
+    #[error("Two-factor authentication has not been set up")]
+    NotEnrolled,
+
+    #[error("The code is invalid")]
+    InvalidCode,
+}
+
+/// RFC 6238 TOTP with SHA-1, 6 digits and 30 second steps, which is what
+/// authenticator apps expect.
+pub struct MfaService {
+    repo: MfaRepository,
+    issuer: String,
+}
+
+impl MfaService {
+    pub fn new(repo: MfaRepository, issuer: String) -> Self {
+        Self { repo, issuer }
+    }
+
+    /// Generates a new secret for the admin. It is not enforced until it is
+    /// confirmed with a code, so calling this again just replaces it. The
+    /// email only labels the entry in the authenticator app.
+    pub async fn enroll(&self, admin_id: Uuid, email: String) -> Result<MfaEnrollment, MfaServiceError> {
+        let Secret::Encoded(secret) = Secret::generate_secret().to_encoded() else {
+            unreachable!("`to_encoded` always returns an encoded secret")
+        };
+
+        let totp = self.totp(&secret, &email)?;
+
+        self.repo
+            .upsert_pending(admin_id, secret.clone())
+            .await?
+            .ok_or(MfaServiceError::AlreadyEnabled)?;
+
+        Ok(MfaEnrollment {
+            secret,
+            otpauth_uri: totp.get_url(),
+        })
+    }
+
+    /// Turns MFA on once the admin proves their app produces valid codes.
+    /// Returns the recovery codes, which are only ever shown this once.
+    pub async fn confirm(&self, admin_id: Uuid, code: String) -> Result<Vec<String>, MfaServiceError> {
+        let mfa = self
+            .repo
+            .find(admin_id)
+            .await?
+            .ok_or(MfaServiceError::NotEnrolled)?;
+
+        if mfa.confirmed_at.is_some() {
+            return Err(MfaServiceError::AlreadyEnabled);
+        }
+
+        self.check_totp(&mfa, &code).await?;
+
+        let codes: Vec<String> = (0..RECOVERY_CODE_COUNT).map(|_| generate_recovery_code()).collect();
+        let hashes = codes.iter().map(|code| hash_token(&normalize_recovery_code(code))).collect();
+
+        self.repo.confirm(admin_id, hashes).await?;
+
+        Ok(codes)
+    }
+
+    pub async fn is_enabled(&self, admin_id: Uuid) -> Result<bool, MfaServiceError> {
+        Ok(self
+            .repo
+            .find(admin_id)
+            .await?
+            .is_some_and(|mfa| mfa.confirmed_at.is_some()))
+    }
+
+    /// Accepts a TOTP code or an unused recovery code.
+    pub async fn verify(&self, admin_id: Uuid, code: String) -> Result<(), MfaServiceError> {
+        let mfa = self
+            .repo
+            .find(admin_id)
+            .await?
+            .filter(|mfa| mfa.confirmed_at.is_some())
+            .ok_or(MfaServiceError::NotEnrolled)?;
+
+        let code = code.trim();
+        if code.len() == DIGITS && code.bytes().all(|b| b.is_ascii_digit()) {
+            return self.check_totp(&mfa, code).await;
+        }
+
+        let code_hash = hash_token(&normalize_recovery_code(code));
+
+        if self.repo.use_recovery_code(admin_id, code_hash).await? {
+            Ok(())
+        } else {
+            Err(MfaServiceError::InvalidCode)
+        }
+    }
+
+    /// Needs a valid code, so a stolen access token alone cannot turn MFA off.
+    pub async fn disable(&self, admin_id: Uuid, code: String) -> Result<(), MfaServiceError> {
+        self.verify(admin_id, code).await?;
+        self.repo.delete(admin_id).await?;
+
+        Ok(())
+    }
+
+    pub async fn remaining_recovery_codes(&self, admin_id: Uuid) -> Result<i64, MfaServiceError> {
+        Ok(self.repo.count_unused_recovery_codes(admin_id).await?)
+    }
+
+    /// Allows one step of clock drift either way. Each step is only accepted
+    /// once, so an intercepted code cannot be replayed.
+    async fn check_totp(&self, mfa: &MfaModel, code: &str) -> Result<(), MfaServiceError> {
+        // the label plays no part in the codes
+        let totp = self.totp(&mfa.secret, &mfa.admin_id.to_string())?;
+        let current = Utc::now().timestamp() as u64 / STEP_SECONDS;
+
+        let step = [current - 1, current, current + 1]
+            .into_iter()
+            .find(|step| totp.generate(step * STEP_SECONDS) == code)
+            .ok_or(MfaServiceError::InvalidCode)?;
+
+        if !self.repo.use_step(mfa.admin_id, step as i64).await? {
+            return Err(MfaServiceError::InvalidCode);
+        }
+
+        Ok(())
+    }
+
+    fn totp(&self, secret: &str, label: &str) -> Result<TOTP, MfaServiceError> {
+        let secret = Secret::Encoded(secret.to_string())
+            .to_bytes()
+            .map_err(|_| MfaServiceError::InvalidCode)?;
+
+        // the otpauth label uses ':' as its separator
+        Ok(TOTP::new(
+            Algorithm::SHA1,
+            DIGITS,
+            1,
+            STEP_SECONDS,
+            secret,
+            Some(self.issuer.replace(':', "")),
+            label.replace(':', ""),
+        )?)
+    }
+}
+
+/// Ten characters, about 50 bits, shown as `xxxxx-xxxxx`. The dash is
+/// stripped before hashing.
+fn generate_recovery_code() -> String {
+    let mut bytes = [0u8; 10];
+    OsRng.fill_bytes(&mut bytes);
+
+    let chars: String = bytes
+        .iter()
+        .map(|b| RECOVERY_CODE_ALPHABET[(b % 32) as usize] as char)
+        .collect();
+
+    format!("{}-{}", &chars[..5], &chars[5..])
+}
+
+fn normalize_recovery_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| c.is_ascii_alphanumeric())
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+
+    async fn setup_service() -> (crate::database::TestDb, MfaService, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let repo = MfaRepository::new(test_db.pool.clone());
+        (test_db, MfaService::new(repo, "sigma".to_string()), admin.id)
+    }
+
+    fn code_at(service: &MfaService, secret: &str, offset_steps: i64) -> String {
+        let totp = service.totp(secret, EMAIL).unwrap();
+        let time = Utc::now().timestamp() + offset_steps * STEP_SECONDS as i64;
+        totp.generate(time as u64)
+    }
+
+    async fn enable(service: &MfaService, id: Uuid) -> Vec<String> {
+        let enrollment = service.enroll(id, EMAIL.to_string()).await.unwrap();
+        let code = code_at(service, &enrollment.secret, 0);
+        service.confirm(id, code).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_enroll_returns_otpauth_uri() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let enrollment = service.enroll(id, EMAIL.to_string()).await.unwrap();
+
+        assert!(enrollment.otpauth_uri.starts_with("otpauth://totp/sigma:asdf%40gmail.com?"));
+        assert!(enrollment.otpauth_uri.contains(&format!("secret={}", enrollment.secret)));
+        assert!(!service.is_enabled(id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_confirm_enables_mfa() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let recovery_codes = enable(&service, id).await;
+
+        assert!(service.is_enabled(id).await.unwrap());
+        assert_eq!(recovery_codes.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(
+            service.remaining_recovery_codes(id).await.unwrap(),
+            RECOVERY_CODE_COUNT as i64
+        );
+    }
+
+    #[tokio::test]
+    async fn test_confirm_with_wrong_code() {
+        let (_test_db, service, id) = setup_service().await;
+        service.enroll(id, EMAIL.to_string()).await.unwrap();
+
+        let result = service.confirm(id, "000000".to_string()).await;
+
+        // a one in a million chance of being the actual code is fine here
+        assert!(matches!(result, Err(MfaServiceError::InvalidCode)));
+        assert!(!service.is_enabled(id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_enroll_when_enabled() {
+        let (_test_db, service, id) = setup_service().await;
+        enable(&service, id).await;
+
+        let result = service.enroll(id, EMAIL.to_string()).await;
+
+        assert!(matches!(result, Err(MfaServiceError::AlreadyEnabled)));
+    }
+
+    #[tokio::test]
+    async fn test_verify_rejects_replayed_code() {
+        let (_test_db, service, id) = setup_service().await;
+        let enrollment = service.enroll(id, EMAIL.to_string()).await.unwrap();
+        let code = code_at(&service, &enrollment.secret, 0);
+        service.confirm(id, code.clone()).await.unwrap();
+
+        // the code used to confirm was already spent
+        let result = service.verify(id, code).await;
+        assert!(matches!(result, Err(MfaServiceError::InvalidCode)));
+
+        let next = code_at(&service, &enrollment.secret, 1);
+        service.verify(id, next.clone()).await.unwrap();
+
+        let result = service.verify(id, next).await;
+        assert!(matches!(result, Err(MfaServiceError::InvalidCode)));
+    }
+
+    #[tokio::test]
+    async fn test_verify_rejects_old_code() {
+        let (_test_db, service, id) = setup_service().await;
+        let enrollment = service.enroll(id, EMAIL.to_string()).await.unwrap();
+        let code = code_at(&service, &enrollment.secret, -1);
+        service.confirm(id, code).await.unwrap();
+
+        let stale = code_at(&service, &enrollment.secret, -3);
+        let result = service.verify(id, stale).await;
+
+        assert!(matches!(result, Err(MfaServiceError::InvalidCode)));
+    }
+
+    #[tokio::test]
+    async fn test_recovery_code_works_once() {
+        let (_test_db, service, id) = setup_service().await;
+        let recovery_codes = enable(&service, id).await;
+
+        let code = recovery_codes[0].to_uppercase();
+        service.verify(id, code.clone()).await.unwrap();
+
+        let result = service.verify(id, code).await;
+        assert!(matches!(result, Err(MfaServiceError::InvalidCode)));
+        assert_eq!(
+            service.remaining_recovery_codes(id).await.unwrap(),
+            RECOVERY_CODE_COUNT as i64 - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verify_without_mfa() {
+        let (_test_db, service, id) = setup_service().await;
+        service.enroll(id, EMAIL.to_string()).await.unwrap();
+
+        let result = service.verify(id, "123456".to_string()).await;
+
+        assert!(matches!(result, Err(MfaServiceError::NotEnrolled)));
+    }
+
+    #[tokio::test]
+    async fn test_disable() {
+        let (_test_db, service, id) = setup_service().await;
+        let recovery_codes = enable(&service, id).await;
+
+        service
+            .disable(id, recovery_codes[0].clone())
+            .await
+            .unwrap();
+
+        assert!(!service.is_enabled(id).await.unwrap());
+    }
+}
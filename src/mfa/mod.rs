@@ -0,0 +1,9 @@
+mod mfa_model;
+mod mfa_repository;
+mod mfa_rest;
+mod mfa_service;
+
+pub use mfa_model::*;
+pub use mfa_repository::*;
+pub use mfa_rest::*;
+pub use mfa_service::*;
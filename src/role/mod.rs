@@ -0,0 +1,9 @@
+mod role_model;
+mod role_repository;
+mod role_rest;
+mod role_service;
+
+pub use role_model::*;
+pub use role_repository::*;
+pub use role_rest::*;
+pub use role_service::*;
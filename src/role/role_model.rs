@@ -0,0 +1,81 @@
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+/// Mirrors the rows seeded into the `roles` table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Role {
+    Owner,
+    Manager,
+    Cashier,
+    Waiter,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Owner => "owner",
+            Role::Manager => "manager",
+            Role::Cashier => "cashier",
+            Role::Waiter => "waiter",
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "owner" => Ok(Role::Owner),
+            "manager" => Ok(Role::Manager),
+            "cashier" => Ok(Role::Cashier),
+            "waiter" => Ok(Role::Waiter),
+            other => Err(format!("Unknown role {other}")),
+        }
+    }
+}
+
+/// Mirrors the rows seeded into the `permissions` table. Which roles grant
+/// which permission lives in `role_permissions`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    CreateAdmin,
+    DeleteAdmin,
+    AssignRoles,
+    RotateKeys,
+    ManageTableSessions,
+    ManageCheckouts,
+}
+
+impl Permission {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Permission::CreateAdmin => "admin:create",
+            Permission::DeleteAdmin => "admin:delete",
+            Permission::AssignRoles => "admin:roles",
+            Permission::RotateKeys => "keys:rotate",
+            Permission::ManageTableSessions => "table_session:manage",
+            Permission::ManageCheckouts => "checkout:manage",
+        }
+    }
+}
+
+impl fmt::Display for Permission {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct AssignRolesRequest {
+    pub roles: Vec<Role>,
+}
@@ -0,0 +1,237 @@
+use sqlx::{PgConnection, PgPool, query, query_scalar};
+use thiserror::Error;
+use uuid::Uuid;
+
+#[derive(Error, Debug)]
+pub enum RoleRepositoryError {
+    #[error("An error occurred with the database: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct RoleRepository {
+    pool: PgPool,
+}
+
+impl RoleRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn find_roles(&self, admin_id: Uuid) -> Result<Vec<String>, RoleRepositoryError> {
+        Ok(query_scalar!(
+            r#"
+            SELECT role
+            FROM admin_roles
+            WHERE admin_id = $1
+            ORDER BY role
+            "#,
+            admin_id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub async fn find_permissions(&self, admin_id: Uuid) -> Result<Vec<String>, RoleRepositoryError> {
+        Ok(query_scalar!(
+            r#"
+            SELECT DISTINCT rp.permission
+            FROM admin_roles ar
+            JOIN role_permissions rp ON rp.role = ar.role
+            WHERE ar.admin_id = $1
+            ORDER BY rp.permission
+            "#,
+            admin_id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub async fn has_permission(
+        &self,
+        admin_id: Uuid,
+        permission: String,
+    ) -> Result<bool, RoleRepositoryError> {
+        Ok(query_scalar!(
+            r#"
+            SELECT EXISTS (
+                SELECT 1
+                FROM admin_roles ar
+                JOIN role_permissions rp ON rp.role = ar.role
+                WHERE ar.admin_id = $1 AND rp.permission = $2
+            ) AS "exists!"
+            "#,
+            admin_id,
+            permission
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Replaces the admin's roles, unless that leaves no owner. Returns `false` then.
+    pub async fn set_roles(
+        &self,
+        admin_id: Uuid,
+        roles: Vec<String>,
+    ) -> Result<bool, RoleRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let owners = lock_owners(&mut tx).await?;
+        if owners == [admin_id] && !roles.iter().any(|role| role == OWNER) {
+            return Ok(false);
+        }
+
+        query!(
+            r#"
+            DELETE FROM admin_roles
+            WHERE admin_id = $1
+            "#,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        query!(
+            r#"
+            INSERT INTO admin_roles (admin_id, role)
+            SELECT $1, role FROM UNNEST($2::VARCHAR[]) AS role
+            ON CONFLICT DO NOTHING
+            "#,
+            admin_id,
+            &roles
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    /// Deletes the admin, unless it is the last owner. Returns `false` then.
+    pub async fn delete_admin(&self, admin_id: Uuid) -> Result<bool, RoleRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let owners = lock_owners(&mut tx).await?;
+        if owners == [admin_id] {
+            return Ok(false);
+        }
+
+        query!(
+            r#"
+            DELETE FROM admins
+            WHERE id = $1
+            "#,
+            admin_id
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        Ok(true)
+    }
+
+    pub async fn count_with_role(&self, role: String) -> Result<i64, RoleRepositoryError> {
+        Ok(query_scalar!(
+            r#"
+            SELECT COUNT(*) AS "count!"
+            FROM admin_roles
+            WHERE role = $1
+            "#,
+            role
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+}
+
+const OWNER: &str = "owner";
+
+/// Locks the owners until the transaction ends, so two concurrent changes cannot
+/// each remove what they saw as the other owner.
+async fn lock_owners(conn: &mut PgConnection) -> Result<Vec<Uuid>, sqlx::Error> {
+    query_scalar!(
+        r#"
+        SELECT admin_id
+        FROM admin_roles
+        WHERE role = $1
+        FOR UPDATE
+        "#,
+        OWNER
+    )
+    .fetch_all(conn)
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    async fn setup_repository() -> (crate::database::TestDb, RoleRepository, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create("asdf@gmail.com".to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let repo = RoleRepository::new(test_db.pool.clone());
+        (test_db, repo, admin.id)
+    }
+
+    #[tokio::test]
+    async fn test_set_and_find_roles() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        repo.set_roles(id, vec!["waiter".to_string(), "cashier".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(repo.find_roles(id).await.unwrap(), vec!["cashier", "waiter"]);
+
+        repo.set_roles(id, vec!["owner".to_string()]).await.unwrap();
+        assert_eq!(repo.find_roles(id).await.unwrap(), vec!["owner"]);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_role() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        let result = repo.set_roles(id, vec!["janitor".to_string()]).await;
+
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_permissions() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        repo.set_roles(id, vec!["waiter".to_string()]).await.unwrap();
+
+        assert_eq!(
+            repo.find_permissions(id).await.unwrap(),
+            vec!["table_session:manage"]
+        );
+        assert!(repo
+            .has_permission(id, "table_session:manage".to_string())
+            .await
+            .unwrap());
+        assert!(!repo
+            .has_permission(id, "admin:create".to_string())
+            .await
+            .unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_count_with_role() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        assert_eq!(repo.count_with_role("owner".to_string()).await.unwrap(), 0);
+
+        repo.set_roles(id, vec!["owner".to_string()]).await.unwrap();
+
+        assert_eq!(repo.count_with_role("owner".to_string()).await.unwrap(), 1);
+    }
+}
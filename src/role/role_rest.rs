@@ -0,0 +1,92 @@
+use axum::Router;
+use axum::extract::{Json, Path, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing;
+use axum_extra::TypedHeader;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Bearer;
+use hyper::StatusCode;
+use serde_json::json;
+use uuid::Uuid;
+
+use crate::app::RestState;
+
+use super::{AssignRolesRequest, Permission, RoleServiceError};
+
+pub fn router() -> Router<RestState> {
+    Router::new()
+        .route("/admin/{id}/roles", routing::get(read_roles_handler))
+        .route("/admin/{id}/roles", routing::put(assign_roles_handler))
+}
+
+impl IntoResponse for RoleServiceError {
+    fn into_response(self) -> Response {
+        let status = match self {
+            RoleServiceError::Forbidden(_) => StatusCode::FORBIDDEN,
+            RoleServiceError::LastOwner => StatusCode::CONFLICT,
+            RoleServiceError::Repository(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+
+        (status, Json(json!({ "message": self.to_string() }))).into_response()
+    }
+}
+
+pub async fn read_roles_handler(
+    State(RestState { token_service, role_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<Uuid>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    // everyone may look at their own roles
+    if admin_id != id {
+        role_service
+            .require(admin_id, Permission::AssignRoles)
+            .await
+            .map_err(IntoResponse::into_response)?;
+    }
+
+    let roles = role_service
+        .roles_for(id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+    let permissions = role_service
+        .permissions_for(id)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok((StatusCode::OK, Json(json!({ "roles": roles, "permissions": permissions }))).into_response())
+}
+
+pub async fn assign_roles_handler(
+    State(RestState { admin_service, token_service, role_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+    Path(id): Path<Uuid>,
+    Json(AssignRolesRequest { roles }): Json<AssignRolesRequest>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    role_service
+        .require(admin_id, Permission::AssignRoles)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    admin_service
+        .find_by_id(id)
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?
+        .ok_or_else(|| StatusCode::NOT_FOUND.into_response())?;
+
+    let roles = role_service
+        .assign_roles(id, roles)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    Ok((StatusCode::OK, Json(json!({ "roles": roles }))).into_response())
+}
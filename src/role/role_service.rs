@@ -0,0 +1,199 @@
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{Permission, Role, RoleRepository, RoleRepositoryError};
+
+#[derive(Error, Debug)]
+pub enum RoleServiceError {
+    #[error("{0}")]
+    Repository(#[from] RoleRepositoryError),
+
+    #[error("Missing permission {0}")]
+    Forbidden(Permission),
+
+    #[error("There must be at least one owner")]
+    LastOwner,
+}
+
+pub struct RoleService {
+    repo: RoleRepository,
+}
+
+impl RoleService {
+    pub fn new(repo: RoleRepository) -> Self {
+        Self { repo }
+    }
+
+    pub async fn roles_for(&self, admin_id: Uuid) -> Result<Vec<Role>, RoleServiceError> {
+        Ok(self
+            .repo
+            .find_roles(admin_id)
+            .await?
+            .iter()
+            .filter_map(|role| role.parse().ok())
+            .collect())
+    }
+
+    pub async fn permissions_for(&self, admin_id: Uuid) -> Result<Vec<String>, RoleServiceError> {
+        Ok(self.repo.find_permissions(admin_id).await?)
+    }
+
+    /// Checked against the database rather than the token, so role changes apply
+    /// to tokens that were already issued.
+    pub async fn require(
+        &self,
+        admin_id: Uuid,
+        permission: Permission,
+    ) -> Result<(), RoleServiceError> {
+        match self.repo.has_permission(admin_id, permission.to_string()).await? {
+            true => Ok(()),
+            false => Err(RoleServiceError::Forbidden(permission)),
+        }
+    }
+
+    /// Fails for the only owner left, so the deployment cannot lock itself out
+    /// of managing admins.
+    pub async fn assign_roles(
+        &self,
+        admin_id: Uuid,
+        roles: Vec<Role>,
+    ) -> Result<Vec<Role>, RoleServiceError> {
+        let names = roles.iter().map(|role| role.to_string()).collect();
+
+        if !self.repo.set_roles(admin_id, names).await? {
+            return Err(RoleServiceError::LastOwner);
+        }
+
+        self.roles_for(admin_id).await
+    }
+
+    /// Deletes the admin. Fails for the only owner left, like `assign_roles`.
+    pub async fn delete_admin(&self, admin_id: Uuid) -> Result<(), RoleServiceError> {
+        match self.repo.delete_admin(admin_id).await? {
+            true => Ok(()),
+            false => Err(RoleServiceError::LastOwner),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    async fn setup_service() -> (crate::database::TestDb, RoleService, Uuid, Uuid) {
+        let test_db = setup_test_db().await;
+        let admin_repository = AdminRepository::new(test_db.pool.clone());
+
+        let mut ids = Vec::new();
+        for email in ["asdf@gmail.com", "other@gmail.com"] {
+            let admin = admin_repository
+                .create(email.to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+                .await
+                .unwrap();
+            ids.push(admin.id);
+        }
+
+        let service = RoleService::new(RoleRepository::new(test_db.pool.clone()));
+        (test_db, service, ids[0], ids[1])
+    }
+
+    #[tokio::test]
+    async fn test_waiter_manages_table_sessions_only() {
+        let (_test_db, service, id, _) = setup_service().await;
+
+        service.assign_roles(id, vec![Role::Waiter]).await.unwrap();
+
+        assert!(service
+            .require(id, Permission::ManageTableSessions)
+            .await
+            .is_ok());
+        assert!(matches!(
+            service.require(id, Permission::CreateAdmin).await,
+            Err(RoleServiceError::Forbidden(Permission::CreateAdmin))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_owner_has_every_permission() {
+        let (_test_db, service, id, _) = setup_service().await;
+
+        service.assign_roles(id, vec![Role::Owner]).await.unwrap();
+
+        for permission in [
+            Permission::CreateAdmin,
+            Permission::DeleteAdmin,
+            Permission::AssignRoles,
+            Permission::RotateKeys,
+            Permission::ManageTableSessions,
+            Permission::ManageCheckouts,
+        ] {
+            assert!(service.require(id, permission).await.is_ok());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_no_roles() {
+        let (_test_db, service, id, _) = setup_service().await;
+
+        assert!(service.roles_for(id).await.unwrap().is_empty());
+        assert!(service
+            .require(id, Permission::ManageTableSessions)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_cannot_demote_last_owner() {
+        let (_test_db, service, id, other_id) = setup_service().await;
+
+        service.assign_roles(id, vec![Role::Owner]).await.unwrap();
+
+        let result = service.assign_roles(id, vec![Role::Manager]).await;
+        assert!(matches!(result, Err(RoleServiceError::LastOwner)));
+
+        service.assign_roles(other_id, vec![Role::Owner]).await.unwrap();
+
+        let roles = service.assign_roles(id, vec![Role::Manager]).await.unwrap();
+        assert_eq!(roles, vec![Role::Manager]);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_demotions_keep_an_owner() {
+        let (test_db, service, id, other_id) = setup_service().await;
+        let other_service = RoleService::new(RoleRepository::new(test_db.pool.clone()));
+
+        service.assign_roles(id, vec![Role::Owner]).await.unwrap();
+        service.assign_roles(other_id, vec![Role::Owner]).await.unwrap();
+
+        let (first, second) = tokio::join!(
+            service.assign_roles(id, vec![Role::Manager]),
+            other_service.assign_roles(other_id, vec![Role::Manager]),
+        );
+        assert!(first.is_ok() != second.is_ok());
+
+        let owners = RoleRepository::new(test_db.pool)
+            .count_with_role(Role::Owner.to_string())
+            .await
+            .unwrap();
+        assert_eq!(owners, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_last_owner() {
+        let (_test_db, service, id, other_id) = setup_service().await;
+
+        service.assign_roles(id, vec![Role::Owner]).await.unwrap();
+
+        let result = service.delete_admin(id).await;
+        assert!(matches!(result, Err(RoleServiceError::LastOwner)));
+
+        service.assign_roles(other_id, vec![Role::Owner]).await.unwrap();
+        service.delete_admin(id).await.unwrap();
+
+        let result = service.delete_admin(other_id).await;
+        assert!(matches!(result, Err(RoleServiceError::LastOwner)));
+    }
+}
@@ -15,23 +15,70 @@ pub use table_session_service::*;
 #[cfg(test)]
 mod tests {
     use std::str::FromStr;
+    use std::sync::Arc;
 
+    use sqlx::PgPool;
     use tonic::Request;
+    use tonic::service::Interceptor;
     use uuid::Uuid;
 
+    use crate::admin::AdminRepository;
     use crate::database;
+    use crate::role::{Role, RoleRepository, RoleService};
+    use crate::token::{AuthInterceptor, TokenService};
 
     use super::*;
     use super::proto::table_session_service_server::TableSessionService as _;
 
+    fn token_service() -> Arc<TokenService> {
+        Arc::new(TokenService::new("asdf".to_string(), "asdf".to_string()))
+    }
+
+    fn setup_grpc(pool: PgPool, token_service: Arc<TokenService>) -> TableSessionGrpc {
+        TableSessionGrpc::new(
+            TableSessionService::new(TableSessionRepository::new(pool.clone())),
+            RoleService::new(RoleRepository::new(pool)),
+            token_service,
+        )
+    }
+
+    /// Runs the request through `AuthInterceptor` as the server would.
+    fn authenticated<T>(token_service: &Arc<TokenService>, admin_id: Uuid, message: T) -> Request<T> {
+        let token = token_service.create_jwt(admin_id.to_string()).unwrap();
+
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", format!("Bearer {token}").parse().unwrap());
+
+        let request = AuthInterceptor::new(token_service.clone()).call(request).unwrap();
+        let (metadata, extensions, _) = request.into_parts();
+
+        Request::from_parts(metadata, extensions, message)
+    }
+
+    async fn create_staff(pool: &PgPool, role: Role) -> Uuid {
+        let admin = AdminRepository::new(pool.clone())
+            .create(format!("{role}@example.com"), role.to_string(), "HelloWorld123!".to_string())
+            .await
+            .unwrap();
+
+        RoleService::new(RoleRepository::new(pool.clone()))
+            .assign_roles(admin.id, vec![role])
+            .await
+            .unwrap();
+
+        admin.id
+    }
+
     #[tokio::test]
     async fn test_create_table_session_success() {
         let test_db = database::setup_test_db().await;
-        let table_session_repository = TableSessionRepository::new(test_db.pool.clone());
-        let table_session_service = TableSessionService::new(table_session_repository);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
 
-        let request = Request::new(proto::TableIdRequest {
+        let request = authenticated(&token_service, manager_id, proto::TableIdRequest {
             table_id: Uuid::new_v4().to_string(),
             order_id: Uuid::new_v4().to_string(),
         });
@@ -45,40 +92,227 @@ mod tests {
         assert!(table_session.is_some());
     }
 
+    #[tokio::test]
+    async fn test_create_table_session_on_occupied_table() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
+
+        let table_id = Uuid::new_v4().to_string();
+        let create = || authenticated(&token_service, manager_id, proto::TableIdRequest {
+            table_id: table_id.clone(),
+            order_id: Uuid::new_v4().to_string(),
+        });
+
+        let response = table_session_grpc.create_table_session(create()).await.unwrap();
+        let session_id = response.into_inner().table_session.unwrap().id;
+
+        let status = table_session_grpc.create_table_session(create()).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+        assert!(status.message().contains(&session_id));
+        assert_eq!(status.metadata().get("session-id").unwrap(), session_id.as_str());
+    }
+
+    #[tokio::test]
+    async fn test_staff_rpcs_require_authentication() {
+        let test_db = database::setup_test_db().await;
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service());
+
+        let status = table_session_grpc.create_table_session(Request::new(proto::TableIdRequest {
+            table_id: Uuid::new_v4().to_string(),
+            order_id: Uuid::new_v4().to_string(),
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let status = table_session_grpc.set_is_active_to_table_session(Request::new(proto::IsActiveRequest {
+            id: Uuid::new_v4().to_string(),
+            value: false,
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let status = table_session_grpc.set_checkout_id_to_table_session(Request::new(proto::CheckoutIdRequest {
+            id: Uuid::new_v4().to_string(),
+            checkout_id: None,
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+
+        let status = table_session_grpc.mark_table_session_paid(Request::new(proto::SessionIdRequest {
+            session_id: Uuid::new_v4().to_string(),
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::Unauthenticated);
+    }
+
+    #[tokio::test]
+    async fn test_staff_rpcs_require_permission() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let waiter_id = create_staff(&test_db.pool, Role::Waiter).await;
+        let cashier_id = create_staff(&test_db.pool, Role::Cashier).await;
+
+        // cashiers only handle checkouts
+        let status = table_session_grpc.create_table_session(authenticated(&token_service, cashier_id, proto::TableIdRequest {
+            table_id: Uuid::new_v4().to_string(),
+            order_id: Uuid::new_v4().to_string(),
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let response = table_session_grpc.create_table_session(authenticated(&token_service, waiter_id, proto::TableIdRequest {
+            table_id: Uuid::new_v4().to_string(),
+            order_id: Uuid::new_v4().to_string(),
+        })).await.unwrap();
+        let session_id = response.into_inner().table_session.unwrap().id;
+
+        // waiters only handle table sessions
+        let status = table_session_grpc.set_checkout_id_to_table_session(authenticated(&token_service, waiter_id, proto::CheckoutIdRequest {
+            id: session_id.clone(),
+            checkout_id: Some(Uuid::new_v4().to_string()),
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        let status = table_session_grpc.mark_table_session_paid(authenticated(&token_service, waiter_id, proto::SessionIdRequest {
+            session_id: session_id.clone(),
+        })).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::PermissionDenied);
+
+        table_session_grpc.set_checkout_id_to_table_session(authenticated(&token_service, cashier_id, proto::CheckoutIdRequest {
+            id: session_id,
+            checkout_id: Some(Uuid::new_v4().to_string()),
+        })).await.unwrap();
+    }
+
     #[tokio::test]
     async fn test_verify_table_session_success() {
         let test_db = database::setup_test_db().await;
-        let table_session_repository = TableSessionRepository::new(test_db.pool.clone());
-        let table_session_service = TableSessionService::new(table_session_repository);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
 
         let table_id = Uuid::new_v4().to_string();
         let order_id = Uuid::new_v4().to_string();
 
-        let request = Request::new(proto::TableIdRequest {
+        let request = authenticated(&token_service, manager_id, proto::TableIdRequest {
             table_id: table_id.clone(),
             order_id: order_id.clone(),
         });
 
         let response = table_session_grpc.create_table_session(request).await.unwrap();
 
-        let session_id = response.into_inner().table_session.unwrap().id;
-        let request = Request::new(proto::SessionIdRequest { session_id });
+        let token = response.into_inner().customer_token.unwrap();
+        let request = Request::new(proto::TokenRequest { token });
         let response = table_session_grpc.verify_table_session(request).await.unwrap();
 
-        let table_session = response.into_inner().table_session.unwrap();
+        let response = response.into_inner();
+        let table_session = response.table_session.unwrap();
         assert_eq!(table_session.table_id, table_id);
+        assert!(response.customer_token.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_verify_table_session_rejects_bad_tokens() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
+
+        let response = table_session_grpc.create_table_session(authenticated(&token_service, manager_id, proto::TableIdRequest {
+            table_id: Uuid::new_v4().to_string(),
+            order_id: Uuid::new_v4().to_string(),
+        })).await.unwrap();
+        let response = response.into_inner();
+        let session = response.table_session.unwrap();
+        let token = response.customer_token.unwrap();
+
+        // the bare session id is no longer enough
+        let mut tokens = vec![session.id.clone()];
+        // tampered signature
+        tokens.push(format!("{}x", token));
+        // signed by someone else
+        let other = TokenService::new("asdf".to_string(), "other".to_string());
+        tokens.push(other.create_table_session_jwt(
+            Uuid::from_str(&session.id).unwrap(),
+            Uuid::from_str(&session.table_id).unwrap(),
+            Uuid::from_str(&session.order_id).unwrap(),
+        ).unwrap());
+        // a staff token for the same id
+        tokens.push(token_service.create_jwt(session.id.clone()).unwrap());
+        // pointing at another table
+        tokens.push(token_service.create_table_session_jwt(
+            Uuid::from_str(&session.id).unwrap(),
+            Uuid::new_v4(),
+            Uuid::from_str(&session.order_id).unwrap(),
+        ).unwrap());
+
+        for token in tokens {
+            let request = Request::new(proto::TokenRequest { token });
+            let status = table_session_grpc.verify_table_session(request).await.unwrap_err();
+            assert_eq!(status.code(), tonic::Code::Unauthenticated);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_closed_table_session() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
+
+        let response = table_session_grpc.create_table_session(authenticated(&token_service, manager_id, proto::TableIdRequest {
+            table_id: Uuid::new_v4().to_string(),
+            order_id: Uuid::new_v4().to_string(),
+        })).await.unwrap();
+        let response = response.into_inner();
+        let session = response.table_session.unwrap();
+
+        table_session_grpc.set_is_active_to_table_session(authenticated(&token_service, manager_id, proto::IsActiveRequest {
+            id: session.id,
+            value: false,
+        })).await.unwrap();
+
+        let request = Request::new(proto::TokenRequest { token: response.customer_token.unwrap() });
+        let status = table_session_grpc.verify_table_session(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(status.message(), "Table session is closed");
+    }
+
+    #[tokio::test]
+    async fn test_verify_expired_table_session() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
+
+        let response = table_session_grpc.create_table_session(authenticated(&token_service, manager_id, proto::TableIdRequest {
+            table_id: Uuid::new_v4().to_string(),
+            order_id: Uuid::new_v4().to_string(),
+        })).await.unwrap();
+
+        let response = response.into_inner();
+        let session = response.table_session.unwrap();
+        assert!(session.expires_at.unwrap() > session.last_seen_at);
+
+        sqlx::query("UPDATE table_sessions SET expires_at = NOW() - INTERVAL '1 minute'")
+            .execute(&test_db.pool)
+            .await
+            .unwrap();
+
+        let request = Request::new(proto::TokenRequest { token: response.customer_token.unwrap() });
+        let status = table_session_grpc.verify_table_session(request).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+        assert_eq!(status.message(), "Table session has expired");
     }
 
     #[tokio::test]
     async fn test_verify_table_session_fail() {
         let test_db = database::setup_test_db().await;
-        let table_session_repository = TableSessionRepository::new(test_db.pool.clone());
-        let table_session_service = TableSessionService::new(table_session_repository);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
 
-        let session_id = Uuid::new_v4().to_string();
-        let request = Request::new(proto::SessionIdRequest { session_id });
+        let token = token_service
+            .create_table_session_jwt(Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4())
+            .unwrap();
+        let request = Request::new(proto::TokenRequest { token });
         let response = table_session_grpc.verify_table_session(request).await;
 
         assert!(response.is_err());
@@ -90,13 +324,13 @@ mod tests {
     #[tokio::test]
     async fn test_deactivate_session() {
         let test_db = database::setup_test_db().await;
-        let table_session_repository = TableSessionRepository::new(test_db.pool.clone());
-        let table_session_service = TableSessionService::new(table_session_repository);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
 
         // first, create session
         let response = table_session_grpc.create_table_session(
-            Request::new(proto::TableIdRequest {
+            authenticated(&token_service, manager_id, proto::TableIdRequest {
                 table_id: Uuid::new_v4().to_string(),
                 order_id: Uuid::new_v4().to_string(),
             })
@@ -106,9 +340,9 @@ mod tests {
 
         // second, deactivate session
         let response = table_session_grpc.set_is_active_to_table_session(
-            Request::new(proto::IsActiveRequest {
+            authenticated(&token_service, manager_id, proto::IsActiveRequest {
                 id: session_id.clone(),
-                value: true,
+                value: false,
             })
         ).await.unwrap();
 
@@ -123,16 +357,54 @@ mod tests {
         assert_eq!(table_session.is_active, false);
     }
 
+    #[tokio::test]
+    async fn test_reactivate_session() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
+
+        let table_id = Uuid::new_v4().to_string();
+        let create = || authenticated(&token_service, manager_id, proto::TableIdRequest {
+            table_id: table_id.clone(),
+            order_id: Uuid::new_v4().to_string(),
+        });
+        let set_is_active = |id: &str, value: bool| authenticated(&token_service, manager_id, proto::IsActiveRequest {
+            id: id.to_string(),
+            value,
+        });
+
+        let response = table_session_grpc.create_table_session(create()).await.unwrap();
+        let session_id = response.into_inner().table_session.unwrap().id;
+
+        table_session_grpc.set_is_active_to_table_session(set_is_active(&session_id, false)).await.unwrap();
+        let response = table_session_grpc
+            .set_is_active_to_table_session(set_is_active(&session_id, true))
+            .await
+            .unwrap();
+        assert!(response.into_inner().table_session.unwrap().is_active);
+
+        // a newer session on the table blocks reactivation
+        table_session_grpc.set_is_active_to_table_session(set_is_active(&session_id, false)).await.unwrap();
+        table_session_grpc.create_table_session(create()).await.unwrap();
+
+        let status = table_session_grpc
+            .set_is_active_to_table_session(set_is_active(&session_id, true))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::AlreadyExists);
+    }
+
     #[tokio::test]
     async fn test_set_checkout_id() {
         let test_db = database::setup_test_db().await;
-        let table_session_repository = TableSessionRepository::new(test_db.pool.clone());
-        let table_session_service = TableSessionService::new(table_session_repository);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
 
         // first, create session
         let response = table_session_grpc.create_table_session(
-            Request::new(proto::TableIdRequest {
+            authenticated(&token_service, manager_id, proto::TableIdRequest {
                 table_id: Uuid::new_v4().to_string(),
                 order_id: Uuid::new_v4().to_string(),
             })
@@ -144,7 +416,7 @@ mod tests {
         // second, set the checkout id
         let checkout_id = Uuid::new_v4();
         table_session_grpc.set_checkout_id_to_table_session(
-            Request::new(proto::CheckoutIdRequest {
+            authenticated(&token_service, manager_id, proto::CheckoutIdRequest {
                 id: session.id.clone(),
                 checkout_id: Some(checkout_id.to_string()),
             })
@@ -160,16 +432,57 @@ mod tests {
         assert_eq!(db_response.unwrap().checkout_id, Some(checkout_id));
     }
 
+    #[tokio::test]
+    async fn test_mark_paid() {
+        let test_db = database::setup_test_db().await;
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
+
+        let response = table_session_grpc.create_table_session(
+            authenticated(&token_service, manager_id, proto::TableIdRequest {
+                table_id: Uuid::new_v4().to_string(),
+                order_id: Uuid::new_v4().to_string(),
+            })
+        ).await.unwrap();
+
+        let session = response.into_inner().table_session.unwrap();
+        assert_eq!(session.status(), proto::TableSessionStatus::Ordering);
+
+        // nothing to pay before a checkout exists
+        let status = table_session_grpc.mark_table_session_paid(
+            authenticated(&token_service, manager_id, proto::SessionIdRequest { session_id: session.id.clone() })
+        ).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::FailedPrecondition);
+
+        table_session_grpc.set_checkout_id_to_table_session(
+            authenticated(&token_service, manager_id, proto::CheckoutIdRequest {
+                id: session.id.clone(),
+                checkout_id: Some(Uuid::new_v4().to_string()),
+            })
+        ).await.unwrap();
+
+        let response = table_session_grpc.mark_table_session_paid(
+            authenticated(&token_service, manager_id, proto::SessionIdRequest { session_id: session.id.clone() })
+        ).await.unwrap();
+
+        let session = response.into_inner().table_session.unwrap();
+        assert_eq!(session.status(), proto::TableSessionStatus::Paid);
+        assert!(session.checked_out_at.is_some());
+        assert!(session.paid_at.is_some());
+        assert!(session.closed_at.is_none());
+    }
+
     #[tokio::test]
     async fn test_unset_checkout_id() {
         let test_db = database::setup_test_db().await;
-        let table_session_repository = TableSessionRepository::new(test_db.pool.clone());
-        let table_session_service = TableSessionService::new(table_session_repository);
-        let table_session_grpc = TableSessionGrpc::new(table_session_service);
+        let token_service = token_service();
+        let table_session_grpc = setup_grpc(test_db.pool.clone(), token_service.clone());
+        let manager_id = create_staff(&test_db.pool, Role::Manager).await;
 
         // first, create session
         let response = table_session_grpc.create_table_session(
-            Request::new(proto::TableIdRequest {
+            authenticated(&token_service, manager_id, proto::TableIdRequest {
                 table_id: Uuid::new_v4().to_string(),
                 order_id: Uuid::new_v4().to_string(),
             })
@@ -181,7 +494,7 @@ mod tests {
         // second, set the checkout id
         let checkout_id = Uuid::new_v4();
         table_session_grpc.set_checkout_id_to_table_session(
-            Request::new(proto::CheckoutIdRequest {
+            authenticated(&token_service, manager_id, proto::CheckoutIdRequest {
                 id: session.id.clone(),
                 checkout_id: Some(checkout_id.to_string()),
             })
@@ -189,7 +502,7 @@ mod tests {
 
         // third, unset the checkout id
         table_session_grpc.set_checkout_id_to_table_session(
-            Request::new(proto::CheckoutIdRequest {
+            authenticated(&token_service, manager_id, proto::CheckoutIdRequest {
                 id: session.id.clone(),
                 checkout_id: None,
             })
@@ -1,20 +1,66 @@
 use std::str::FromStr;
+use std::sync::Arc;
 
 use tonic::Request;
 use tonic::Response;
 use tonic::Status;
 use uuid::Uuid;
 
-use super::TableSessionService;
+use crate::role::{Permission, RoleService};
+use crate::token::{TABLE_SESSION_SCOPE, TokenService, TokenServiceError, admin_id_from};
+
+use super::{TableSessionModel, TableSessionService, TableSessionServiceError};
 use super::proto;
 
+/// Staff RPCs expect the server to be wrapped in `AuthInterceptor`. Only
+/// `verify_table_session` is open, the guests present their customer token instead.
 pub struct TableSessionGrpc {
     table_session_service: TableSessionService,
+    role_service: RoleService,
+    token_service: Arc<TokenService>,
 }
 
 impl TableSessionGrpc {
-    pub fn new(table_session_service: TableSessionService) -> Self {
-        Self { table_session_service }
+    pub fn new(
+        table_session_service: TableSessionService,
+        role_service: RoleService,
+        token_service: Arc<TokenService>,
+    ) -> Self {
+        Self { table_session_service, role_service, token_service }
+    }
+
+    /// Hands the guests a fresh token along with the session.
+    fn customer_response(
+        &self,
+        table_session: TableSessionModel,
+    ) -> Result<Response<proto::TableSessionResponse>, TokenServiceError> {
+        let customer_token = self
+            .token_service
+            .create_table_session_jwt(table_session.id, table_session.table_id, table_session.order_id)?;
+
+        Ok(Response::new(proto::TableSessionResponse {
+            table_session: Some(proto::TableSession::from(table_session)),
+            customer_token: Some(customer_token),
+        }))
+    }
+}
+
+impl From<TableSessionServiceError> for Status {
+    fn from(value: TableSessionServiceError) -> Self {
+        match value {
+            TableSessionServiceError::TableOccupied { session_id } => {
+                let mut status = Status::already_exists(value.to_string());
+                if let Ok(value) = session_id.to_string().parse() {
+                    status.metadata_mut().insert("session-id", value);
+                }
+                status
+            }
+            TableSessionServiceError::CheckedOut
+            | TableSessionServiceError::Expired
+            | TableSessionServiceError::Closed
+            | TableSessionServiceError::IllegalTransition { .. } => Status::failed_precondition(value.to_string()),
+            TableSessionServiceError::Repository(_) => Status::internal(value.to_string()),
+        }
     }
 }
 
@@ -24,40 +70,43 @@ impl proto::table_session_service_server::TableSessionService for TableSessionGr
         &self,
         request: Request<proto::TableIdRequest>,
     ) -> Result<Response<proto::TableSessionResponse>, Status> {
+        let admin_id = admin_id_from(&request)?;
+        self.role_service.require(admin_id, Permission::ManageTableSessions).await?;
+
         let request = request.into_inner();
         let table_id = Uuid::from_str(&request.table_id)
             .map_err(|_| Status::invalid_argument("table_id not a UUID"))?;
         let order_id = Uuid::from_str(&request.order_id)
             .map_err(|_| Status::invalid_argument("order_id not a UUID"))?;
 
-        // TODO: Check if table is occupied
+        let table_session = self.table_session_service.create_session(table_id, order_id).await?;
 
-        match self.table_session_service.create_session(table_id, order_id).await {
-            Ok(table_session) => {
-                Ok(Response::new(proto::TableSessionResponse {
-                    table_session: Some(proto::TableSession::from(table_session))
-                }))
-            }
-            Err(e) => Err(Status::internal(format!("Failed to create session: {e}")))
-        }
+        self.customer_response(table_session).map_err(|e| Status::internal(e.to_string()))
     }
 
     async fn verify_table_session(
         &self,
-        request: Request<proto::SessionIdRequest>,
+        request: Request<proto::TokenRequest>,
     ) -> Result<Response<proto::TableSessionResponse>, Status> {
-        let request = request.into_inner();
-        let session_id = Uuid::from_str(&request.session_id)
-            .map_err(|_| Status::invalid_argument("session_id not a UUID"))?;
+        let proto::TokenRequest { token } = request.into_inner();
 
-        match self.table_session_service.find_by_id(session_id).await {
-            Ok(Some(table_session)) => {
-                Ok(Response::new(proto::TableSessionResponse {
-                    table_session: Some(proto::TableSession::from(table_session))
-                }))
-            },
+        let claims = self
+            .token_service
+            .decode_scoped(token, TABLE_SESSION_SCOPE)
+            .map_err(|e| Status::unauthenticated(e.to_string()))?;
+        let session_id = Uuid::from_str(&claims.sub)
+            .map_err(|_| Status::unauthenticated("Token subject is not a table session id"))?;
+
+        match self.table_session_service.verify_session(session_id).await {
+            Ok(Some(table_session))
+                if claims.table_id != Some(table_session.table_id)
+                    || claims.order_id != Some(table_session.order_id) =>
+            {
+                Err(Status::unauthenticated("Token does not match the table session"))
+            }
+            Ok(Some(table_session)) => self.customer_response(table_session).map_err(|e| Status::internal(e.to_string())),
             Ok(None) => Err(Status::not_found("Table Session not found")),
-            Err(e) => Err(Status::unauthenticated(format!("Unable to get Table Session: {e}"))),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -65,18 +114,28 @@ impl proto::table_session_service_server::TableSessionService for TableSessionGr
         &self,
         request: Request<proto::IsActiveRequest>,
     ) -> Result<Response<proto::TableSessionResponse>, Status> {
+        let admin_id = admin_id_from(&request)?;
+        self.role_service.require(admin_id, Permission::ManageTableSessions).await?;
+
         let request = request.into_inner();
         let session_id = Uuid::from_str(&request.id)
             .map_err(|_| Status::invalid_argument("id not a UUID"))?;
 
-        match self.table_session_service.deactivate_session(session_id).await {
+        let result = if request.value {
+            self.table_session_service.activate_session(session_id).await
+        } else {
+            self.table_session_service.deactivate_session(session_id).await
+        };
+
+        match result {
             Ok(Some(table_session)) => {
                 Ok(Response::new(proto::TableSessionResponse {
-                    table_session: Some(proto::TableSession::from(table_session))
+                    table_session: Some(proto::TableSession::from(table_session)),
+                    customer_token: None,
                 }))
             },
             Ok(None) => Err(Status::not_found("Table Session not found")),
-            Err(e) => Err(Status::unauthenticated(format!("Unable to get Table Session: {e}"))),
+            Err(e) => Err(e.into()),
         }
     }
 
@@ -84,6 +143,9 @@ impl proto::table_session_service_server::TableSessionService for TableSessionGr
         &self,
         request: Request<proto::CheckoutIdRequest>,
     ) -> Result<Response<proto::TableSessionResponse>, Status> {
+        let admin_id = admin_id_from(&request)?;
+        self.role_service.require(admin_id, Permission::ManageCheckouts).await?;
+
         let request = request.into_inner();
         let id = Uuid::from_str(&request.id)
             .map_err(|_| Status::invalid_argument("id not a UUID"))?;
@@ -99,11 +161,35 @@ impl proto::table_session_service_server::TableSessionService for TableSessionGr
         match self.table_session_service.set_checkout_id(id, checkout_id).await {
             Ok(Some(table_session)) => {
                 Ok(Response::new(proto::TableSessionResponse {
-                    table_session: Some(proto::TableSession::from(table_session))
+                    table_session: Some(proto::TableSession::from(table_session)),
+                    customer_token: None,
+                }))
+            },
+            Ok(None) => Err(Status::not_found("Table Session not found")),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn mark_table_session_paid(
+        &self,
+        request: Request<proto::SessionIdRequest>,
+    ) -> Result<Response<proto::TableSessionResponse>, Status> {
+        let admin_id = admin_id_from(&request)?;
+        self.role_service.require(admin_id, Permission::ManageCheckouts).await?;
+
+        let request = request.into_inner();
+        let session_id = Uuid::from_str(&request.session_id)
+            .map_err(|_| Status::invalid_argument("session_id not a UUID"))?;
+
+        match self.table_session_service.mark_paid(session_id).await {
+            Ok(Some(table_session)) => {
+                Ok(Response::new(proto::TableSessionResponse {
+                    table_session: Some(proto::TableSession::from(table_session)),
+                    customer_token: None,
                 }))
             },
             Ok(None) => Err(Status::not_found("Table Session not found")),
-            Err(e) => Err(Status::unauthenticated(format!("Unable to get Table Session: {e}"))),
+            Err(e) => Err(e.into()),
         }
     }
 }
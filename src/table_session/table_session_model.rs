@@ -1,17 +1,71 @@
+use std::fmt;
+
 use chrono::{DateTime, Utc};
 use serde::Serialize;
 use uuid::Uuid;
 
 use super::proto;
 
+/// Where a session is in its lifecycle. Closed and expired sessions free the
+/// table, the others keep it occupied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "table_session_status", rename_all = "snake_case")]
+pub enum TableSessionStatus {
+    Ordering,
+    AwaitingPayment,
+    Paid,
+    Closed,
+    /// Closed for being idle longer than the configured TTL.
+    Expired,
+}
+
+impl TableSessionStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TableSessionStatus::Ordering => "ordering",
+            TableSessionStatus::AwaitingPayment => "awaiting_payment",
+            TableSessionStatus::Paid => "paid",
+            TableSessionStatus::Closed => "closed",
+            TableSessionStatus::Expired => "expired",
+        }
+    }
+}
+
+impl fmt::Display for TableSessionStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl From<TableSessionStatus> for proto::TableSessionStatus {
+    fn from(value: TableSessionStatus) -> Self {
+        match value {
+            TableSessionStatus::Ordering => proto::TableSessionStatus::Ordering,
+            TableSessionStatus::AwaitingPayment => proto::TableSessionStatus::AwaitingPayment,
+            TableSessionStatus::Paid => proto::TableSessionStatus::Paid,
+            TableSessionStatus::Closed => proto::TableSessionStatus::Closed,
+            TableSessionStatus::Expired => proto::TableSessionStatus::Expired,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
 pub struct TableSessionModel {
     pub id: Uuid,
     pub table_id: Uuid,
     pub order_id: Uuid,
     pub checkout_id: Option<Uuid>,
+    pub status: TableSessionStatus,
+    /// Follows `status`, true until the session is closed or expires.
     pub is_active: bool,
     pub created_at: DateTime<Utc>,
+    pub checked_out_at: Option<DateTime<Utc>>,
+    pub paid_at: Option<DateTime<Utc>>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub last_seen_at: DateTime<Utc>,
+    /// When the session expires unless it is seen again before.
+    pub expires_at: Option<DateTime<Utc>>,
 }
 
 impl From<TableSessionModel> for proto::TableSession {
@@ -22,6 +76,22 @@ impl From<TableSessionModel> for proto::TableSession {
             order_id: value.order_id.to_string(),
             checkout_id: value.checkout_id.map(|u| Uuid::to_string(&u)),
             is_active: value.is_active,
+            status: proto::TableSessionStatus::from(value.status).into(),
+            created_at: value.created_at.timestamp(),
+            checked_out_at: value.checked_out_at.map(|at| at.timestamp()),
+            paid_at: value.paid_at.map(|at| at.timestamp()),
+            closed_at: value.closed_at.map(|at| at.timestamp()),
+            last_seen_at: value.last_seen_at.timestamp(),
+            expires_at: value.expires_at.map(|at| at.timestamp()),
         }
     }
 }
+
+/// One status change of a session.
+#[derive(Debug, Clone, Serialize)]
+pub struct TableSessionHistoryModel {
+    pub id: Uuid,
+    pub table_session_id: Uuid,
+    pub status: TableSessionStatus,
+    pub created_at: DateTime<Utc>,
+}
@@ -1,8 +1,9 @@
-use sqlx::{PgPool, query_as};
+use chrono::Duration;
+use sqlx::{PgConnection, PgPool, query, query_as};
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::TableSessionModel;
+use super::{TableSessionHistoryModel, TableSessionModel, TableSessionStatus};
 
 #[derive(Error, Debug)]
 pub enum TableSessionRepositoryError {
@@ -10,13 +11,30 @@ pub enum TableSessionRepositoryError {
     Database(#[from] sqlx::Error),
 }
 
+impl TableSessionRepositoryError {
+    /// Whether the write clashed with the active session of the same table.
+    pub fn is_unique_violation(&self) -> bool {
+        matches!(self, Self::Database(sqlx::Error::Database(e)) if e.is_unique_violation())
+    }
+}
+
 pub struct TableSessionRepository {
     pool: PgPool,
+    idle_ttl: Duration,
 }
 
 impl TableSessionRepository {
     pub fn new(pool: PgPool) -> Self {
-        Self { pool }
+        Self {
+            pool,
+            idle_ttl: Duration::hours(4),
+        }
+    }
+
+    /// How long an open session may go unseen before it expires.
+    pub fn with_idle_ttl(mut self, idle_ttl: Duration) -> Self {
+        self.idle_ttl = idle_ttl;
+        self
     }
 
     pub async fn create(
@@ -24,18 +42,27 @@ impl TableSessionRepository {
         table_id: Uuid,
         order_id: Uuid,
     ) -> Result<TableSessionModel, TableSessionRepositoryError> {
-        Ok(query_as!(
+        let mut tx = self.pool.begin().await?;
+
+        let session = query_as!(
             TableSessionModel,
             r#"
-            INSERT INTO table_sessions (table_id, order_id)
-            VALUES ($1, $2)
-            RETURNING id, table_id, order_id, checkout_id, is_active, created_at
+            INSERT INTO table_sessions (table_id, order_id, expires_at)
+            VALUES ($1, $2, NOW() + $3)
+            RETURNING id, table_id, order_id, checkout_id, status AS "status: TableSessionStatus",
+                is_active, created_at, checked_out_at, paid_at, closed_at, last_seen_at, expires_at
             "#,
             table_id,
-            order_id
+            order_id,
+            self.idle_ttl as _
         )
-        .fetch_one(&self.pool)
-        .await?)
+        .fetch_one(&mut *tx)
+        .await?;
+
+        record(&mut tx, session.id, session.status).await?;
+        tx.commit().await?;
+
+        Ok(session)
     }
 
     pub async fn find_by_id(
@@ -45,7 +72,8 @@ impl TableSessionRepository {
         Ok(query_as!(
             TableSessionModel,
             r#"
-            SELECT id, table_id, order_id, checkout_id, is_active, created_at
+            SELECT id, table_id, order_id, checkout_id, status AS "status: TableSessionStatus",
+                is_active, created_at, checked_out_at, paid_at, closed_at, last_seen_at, expires_at
             FROM table_sessions
             WHERE id = $1
             "#,
@@ -55,43 +83,168 @@ impl TableSessionRepository {
         .await?)
     }
 
-    pub async fn deactivate(
+    /// At most one session of a table is active, the database enforces it.
+    pub async fn find_active_by_table(
         &self,
-        id: Uuid,
+        table_id: Uuid,
     ) -> Result<Option<TableSessionModel>, TableSessionRepositoryError> {
         Ok(query_as!(
             TableSessionModel,
             r#"
-            UPDATE table_sessions
-            SET is_active = FALSE
-            WHERE id = $1
-            RETURNING id, table_id, order_id, checkout_id, is_active, created_at
+            SELECT id, table_id, order_id, checkout_id, status AS "status: TableSessionStatus",
+                is_active, created_at, checked_out_at, paid_at, closed_at, last_seen_at, expires_at
+            FROM table_sessions
+            WHERE table_id = $1 AND is_active
             "#,
-            id
+            table_id
         )
         .fetch_optional(&self.pool)
         .await?)
     }
 
-    pub async fn set_checkout_id(
+    /// Marks an open session as seen and pushes its expiry back. `None` if it
+    /// is closed or has expired already, even if that was not recorded yet.
+    pub async fn touch(
         &self,
         id: Uuid,
-        checkout_id: Option<Uuid>,
     ) -> Result<Option<TableSessionModel>, TableSessionRepositoryError> {
         Ok(query_as!(
             TableSessionModel,
             r#"
             UPDATE table_sessions
-            SET checkout_id = $2
-            WHERE id = $1
-            RETURNING id, table_id, order_id, checkout_id, is_active, created_at
+            SET last_seen_at = NOW(), expires_at = NOW() + $2
+            WHERE id = $1 AND is_active AND expires_at > NOW()
+            RETURNING id, table_id, order_id, checkout_id, status AS "status: TableSessionStatus",
+                is_active, created_at, checked_out_at, paid_at, closed_at, last_seen_at, expires_at
             "#,
             id,
-            checkout_id
+            self.idle_ttl as _
         )
         .fetch_optional(&self.pool)
         .await?)
     }
+
+    /// Expires up to `limit` open sessions that are past their expiry and
+    /// returns how many it expired. Rows another replica is expiring are skipped.
+    pub async fn expire_idle(&self, limit: i64) -> Result<u64, TableSessionRepositoryError> {
+        let result = query!(
+            r#"
+            WITH stale AS (
+                SELECT id FROM table_sessions
+                WHERE is_active AND expires_at <= NOW()
+                ORDER BY expires_at
+                LIMIT $1
+                FOR UPDATE SKIP LOCKED
+            ), expired AS (
+                UPDATE table_sessions ts
+                SET status = 'expired', closed_at = NOW()
+                FROM stale
+                WHERE ts.id = stale.id
+                RETURNING ts.id
+            )
+            INSERT INTO table_session_history (table_session_id, status)
+            SELECT id, 'expired' FROM expired
+            "#,
+            limit
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(result.rows_affected())
+    }
+
+    /// Moves `current` to `status` with `checkout_id` and stamps the time of the
+    /// move. Whether the move is allowed is up to the caller; `None` means the
+    /// session no longer looks like `current`, so that decision may be stale.
+    pub async fn transition(
+        &self,
+        current: &TableSessionModel,
+        status: TableSessionStatus,
+        checkout_id: Option<Uuid>,
+    ) -> Result<Option<TableSessionModel>, TableSessionRepositoryError> {
+        let mut tx = self.pool.begin().await?;
+
+        let session = query_as!(
+            TableSessionModel,
+            r#"
+            UPDATE table_sessions
+            SET status = $4::table_session_status,
+                checkout_id = $5,
+                checked_out_at = CASE
+                    WHEN $5::UUID IS NULL THEN NULL
+                    WHEN $5 IS DISTINCT FROM checkout_id THEN NOW()
+                    ELSE checked_out_at
+                END,
+                paid_at = CASE WHEN $4::table_session_status = 'paid' THEN NOW() ELSE paid_at END,
+                closed_at = CASE WHEN $4::table_session_status IN ('closed', 'expired') THEN NOW() END,
+                last_seen_at = CASE
+                    WHEN $4::table_session_status IN ('closed', 'expired') THEN last_seen_at
+                    ELSE NOW()
+                END,
+                expires_at = CASE
+                    WHEN $4::table_session_status = 'closed' THEN NULL
+                    WHEN $4::table_session_status = 'expired' THEN expires_at
+                    ELSE NOW() + $6
+                END
+            WHERE id = $1 AND status = $2 AND checkout_id IS NOT DISTINCT FROM $3
+            RETURNING id, table_id, order_id, checkout_id, status AS "status: TableSessionStatus",
+                is_active, created_at, checked_out_at, paid_at, closed_at, last_seen_at, expires_at
+            "#,
+            current.id,
+            current.status as TableSessionStatus,
+            current.checkout_id,
+            status as TableSessionStatus,
+            checkout_id,
+            self.idle_ttl as _
+        )
+        .fetch_optional(&mut *tx)
+        .await?;
+
+        if let Some(session) = &session {
+            record(&mut tx, session.id, session.status).await?;
+        }
+        tx.commit().await?;
+
+        Ok(session)
+    }
+
+    /// Oldest first.
+    pub async fn find_history(
+        &self,
+        id: Uuid,
+    ) -> Result<Vec<TableSessionHistoryModel>, TableSessionRepositoryError> {
+        Ok(query_as!(
+            TableSessionHistoryModel,
+            r#"
+            SELECT id, table_session_id, status AS "status: TableSessionStatus", created_at
+            FROM table_session_history
+            WHERE table_session_id = $1
+            ORDER BY created_at, id
+            "#,
+            id
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+async fn record(
+    conn: &mut PgConnection,
+    table_session_id: Uuid,
+    status: TableSessionStatus,
+) -> Result<(), TableSessionRepositoryError> {
+    query!(
+        r#"
+        INSERT INTO table_session_history (table_session_id, status)
+        VALUES ($1, $2)
+        "#,
+        table_session_id,
+        status as TableSessionStatus
+    )
+    .execute(conn)
+    .await?;
+
+    Ok(())
 }
 
 #[cfg(test)]
@@ -100,6 +253,14 @@ mod tests {
     use crate::database::setup_test_db;
     use uuid::Uuid;
 
+    async fn backdate_expiry(pool: &PgPool, id: Uuid) {
+        sqlx::query("UPDATE table_sessions SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
     #[tokio::test]
     async fn test_create_table_session() {
         let test_db = setup_test_db().await;
@@ -110,6 +271,7 @@ mod tests {
         let session = tsr.create(table_id, order_id).await.unwrap();
 
         assert_eq!(session.table_id, table_id);
+        assert_eq!(session.status, TableSessionStatus::Ordering);
         assert!(session.is_active);
     }
 
@@ -140,9 +302,14 @@ mod tests {
         let created_session = tsr.create(table_id, order_id).await.unwrap();
         assert!(created_session.is_active);
 
-        let deactivated_session = tsr.deactivate(created_session.id).await.unwrap().unwrap();
+        let deactivated_session = tsr
+            .transition(&created_session, TableSessionStatus::Closed, None)
+            .await
+            .unwrap()
+            .unwrap();
         assert_eq!(deactivated_session.id, created_session.id);
         assert!(!deactivated_session.is_active);
+        assert!(deactivated_session.closed_at.is_some());
 
         // Verify the session is indeed deactivated in the database
         let Some(found_session) = tsr.find_by_id(created_session.id).await.unwrap() else {
@@ -152,22 +319,141 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_find_nonexistent_session() {
+    async fn test_transition_history() {
         let test_db = setup_test_db().await;
         let tsr = TableSessionRepository::new(test_db.pool);
-        let random_id = Uuid::new_v4();
+        let checkout_id = Uuid::new_v4();
 
-        let result = tsr.find_by_id(random_id).await.unwrap();
+        let session = tsr.create(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        let session = tsr
+            .transition(&session, TableSessionStatus::AwaitingPayment, Some(checkout_id))
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(session.checked_out_at.is_some());
+
+        let session = tsr.transition(&session, TableSessionStatus::Paid, Some(checkout_id)).await.unwrap().unwrap();
+        assert!(session.paid_at.is_some());
+
+        let history: Vec<TableSessionStatus> = tsr
+            .find_history(session.id)
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.status)
+            .collect();
+        assert_eq!(history, vec![
+            TableSessionStatus::Ordering,
+            TableSessionStatus::AwaitingPayment,
+            TableSessionStatus::Paid,
+        ]);
+    }
+
+    #[tokio::test]
+    async fn test_transition_stale_session() {
+        let test_db = setup_test_db().await;
+        let tsr = TableSessionRepository::new(test_db.pool);
+
+        let session = tsr.create(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        tsr.transition(&session, TableSessionStatus::AwaitingPayment, Some(Uuid::new_v4())).await.unwrap();
+
+        // `session` still says ordering, which the database no longer agrees with
+        let result = tsr.transition(&session, TableSessionStatus::Closed, None).await.unwrap();
         assert!(result.is_none());
+        assert_eq!(tsr.find_history(session.id).await.unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_activate_on_occupied_table() {
+        let test_db = setup_test_db().await;
+        let tsr = TableSessionRepository::new(test_db.pool);
+        let table_id = Uuid::new_v4();
+
+        let closed = tsr.create(table_id, Uuid::new_v4()).await.unwrap();
+        let closed = tsr.transition(&closed, TableSessionStatus::Closed, None).await.unwrap().unwrap();
+        tsr.create(table_id, Uuid::new_v4()).await.unwrap();
+
+        let result = tsr.transition(&closed, TableSessionStatus::Ordering, None).await;
+        assert!(result.unwrap_err().is_unique_violation());
+        assert_eq!(tsr.find_history(closed.id).await.unwrap().len(), 2);
     }
 
     #[tokio::test]
-    async fn test_deactivate_nonexistent_session() {
+    async fn test_create_on_occupied_table() {
+        let test_db = setup_test_db().await;
+        let tsr = TableSessionRepository::new(test_db.pool);
+        let table_id = Uuid::new_v4();
+
+        let session = tsr.create(table_id, Uuid::new_v4()).await.unwrap();
+
+        let result = tsr.create(table_id, Uuid::new_v4()).await;
+        assert!(result.unwrap_err().is_unique_violation());
+
+        let active = tsr.find_active_by_table(table_id).await.unwrap().unwrap();
+        assert_eq!(active.id, session.id);
+
+        // a closed session frees the table
+        tsr.transition(&session, TableSessionStatus::Closed, None).await.unwrap();
+        assert!(tsr.find_active_by_table(table_id).await.unwrap().is_none());
+        assert!(tsr.create(table_id, Uuid::new_v4()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_touch() {
+        let test_db = setup_test_db().await;
+        let tsr = TableSessionRepository::new(test_db.pool.clone()).with_idle_ttl(Duration::minutes(30));
+
+        let session = tsr.create(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        assert!(session.expires_at.unwrap() > session.created_at + Duration::minutes(29));
+
+        let touched = tsr.touch(session.id).await.unwrap().unwrap();
+        assert!(touched.last_seen_at > session.last_seen_at);
+        assert!(touched.expires_at > session.expires_at);
+
+        // past its expiry it is not revived, even before the sweep
+        backdate_expiry(&test_db.pool, session.id).await;
+        assert!(tsr.touch(session.id).await.unwrap().is_none());
+
+        let closed = tsr.create(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        let closed = tsr.transition(&closed, TableSessionStatus::Closed, None).await.unwrap().unwrap();
+        assert!(closed.expires_at.is_none());
+        assert!(tsr.touch(closed.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_expire_idle() {
+        let test_db = setup_test_db().await;
+        let tsr = TableSessionRepository::new(test_db.pool.clone());
+
+        let fresh = tsr.create(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        let mut stale = Vec::new();
+        for _ in 0..3 {
+            let session = tsr.create(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+            backdate_expiry(&test_db.pool, session.id).await;
+            stale.push(session.id);
+        }
+
+        assert_eq!(tsr.expire_idle(2).await.unwrap(), 2);
+        assert_eq!(tsr.expire_idle(2).await.unwrap(), 1);
+        assert_eq!(tsr.expire_idle(2).await.unwrap(), 0);
+
+        for id in stale {
+            let session = tsr.find_by_id(id).await.unwrap().unwrap();
+            assert_eq!(session.status, TableSessionStatus::Expired);
+            assert!(!session.is_active);
+            assert!(session.closed_at.is_some());
+            assert_eq!(tsr.find_history(id).await.unwrap().last().unwrap().status, TableSessionStatus::Expired);
+        }
+        assert!(tsr.find_by_id(fresh.id).await.unwrap().unwrap().is_active);
+    }
+
+    #[tokio::test]
+    async fn test_find_nonexistent_session() {
         let test_db = setup_test_db().await;
         let tsr = TableSessionRepository::new(test_db.pool);
         let random_id = Uuid::new_v4();
 
-        let result = tsr.deactivate(random_id).await.unwrap();
+        let result = tsr.find_by_id(random_id).await.unwrap();
         assert!(result.is_none());
     }
 
@@ -185,7 +471,10 @@ mod tests {
 
         // second set the checkout_id
         let checkout_id = Uuid::new_v4();
-        let session = tsr.set_checkout_id(session.id, Some(checkout_id)).await.unwrap();
+        let session = tsr
+            .transition(&session, TableSessionStatus::AwaitingPayment, Some(checkout_id))
+            .await
+            .unwrap();
 
         // third assert
         assert_eq!(session.unwrap().checkout_id.unwrap(), checkout_id);
@@ -205,12 +494,18 @@ mod tests {
 
         // second set the checkout_id
         let checkout_id = Uuid::new_v4();
-        tsr.set_checkout_id(session.id, Some(checkout_id)).await.unwrap();
+        let session = tsr
+            .transition(&session, TableSessionStatus::AwaitingPayment, Some(checkout_id))
+            .await
+            .unwrap()
+            .unwrap();
 
         // third unset the checkout_id
-        let session = tsr.set_checkout_id(session.id, None).await.unwrap();
+        let session = tsr.transition(&session, TableSessionStatus::Ordering, None).await.unwrap();
 
         // third assert
-        assert!(session.unwrap().checkout_id.is_none());
+        let session = session.unwrap();
+        assert!(session.checkout_id.is_none());
+        assert!(session.checked_out_at.is_none());
     }
 }
@@ -1,14 +1,99 @@
+use std::fmt;
+
+use chrono::Utc;
 use thiserror::Error;
 use uuid::Uuid;
 
-use super::{TableSessionModel, TableSessionRepository, TableSessionRepositoryError};
+use super::{
+    TableSessionHistoryModel, TableSessionModel, TableSessionRepository, TableSessionRepositoryError,
+    TableSessionStatus,
+};
 
 #[derive(Error, Debug)]
 pub enum TableSessionServiceError {
+    #[error("Table already has an active session {session_id}")]
+    TableOccupied { session_id: Uuid },
+
+    #[error("Table session was checked out and cannot be reactivated")]
+    CheckedOut,
+
+    #[error("Table session has expired")]
+    Expired,
+
+    #[error("Table session is closed")]
+    Closed,
+
+    #[error("Cannot {transition} a table session that is {status}")]
+    IllegalTransition {
+        transition: Transition,
+        status: TableSessionStatus,
+    },
+
     #[error("{0}")]
     Repository(#[from] TableSessionRepositoryError),
 }
 
+/// The moves a session can make, see `Transition::apply` for where each may
+/// start from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Transition {
+    Reopen,
+    Close,
+    Checkout(Uuid),
+    CancelCheckout,
+    Pay,
+}
+
+impl fmt::Display for Transition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Transition::Reopen => "reopen",
+            Transition::Close => "close",
+            Transition::Checkout(_) => "set a checkout on",
+            Transition::CancelCheckout => "remove the checkout from",
+            Transition::Pay => "mark as paid",
+        })
+    }
+}
+
+impl Transition {
+    /// The status and checkout `session` moves to, `None` if it is there already.
+    fn apply(
+        self,
+        session: &TableSessionModel,
+    ) -> Result<Option<(TableSessionStatus, Option<Uuid>)>, TableSessionServiceError> {
+        use TableSessionStatus::*;
+
+        let next = match (self, session.status) {
+            (Transition::Reopen, Ordering | AwaitingPayment | Paid) => return Ok(None),
+            (Transition::Reopen, Closed | Expired) if session.checkout_id.is_some() => {
+                return Err(TableSessionServiceError::CheckedOut);
+            }
+            (Transition::Reopen, Closed | Expired) => (Ordering, None),
+
+            (Transition::Close, Closed | Expired) => return Ok(None),
+            (Transition::Close, _) => (Closed, session.checkout_id),
+
+            (Transition::Checkout(checkout_id), AwaitingPayment) if session.checkout_id == Some(checkout_id) => {
+                return Ok(None);
+            }
+            (Transition::Checkout(checkout_id), Ordering | AwaitingPayment) => (AwaitingPayment, Some(checkout_id)),
+
+            (Transition::CancelCheckout, Ordering) => return Ok(None),
+            (Transition::CancelCheckout, AwaitingPayment) => (Ordering, None),
+
+            (Transition::Pay, Paid) => return Ok(None),
+            (Transition::Pay, AwaitingPayment) => (Paid, session.checkout_id),
+
+            (transition, status) => {
+                return Err(TableSessionServiceError::IllegalTransition { transition, status });
+            }
+        };
+
+        Ok(Some(next))
+    }
+}
+
 pub struct TableSessionService {
     repo: TableSessionRepository,
 }
@@ -23,14 +108,71 @@ impl TableSessionService {
         table_id: Uuid,
         order_id: Uuid,
     ) -> Result<TableSessionModel, TableSessionServiceError> {
-        Ok(self.repo.create(table_id, order_id).await?)
+        loop {
+            match self.repo.create(table_id, order_id).await {
+                Err(e) if e.is_unique_violation() => {
+                    let Some(active) = self.repo.find_active_by_table(table_id).await? else {
+                        // the active session was closed in the meantime, so the table is free again
+                        continue;
+                    };
+
+                    // no need to wait for the next sweep to free the table
+                    if active.expires_at.is_some_and(|expires_at| expires_at <= Utc::now()) {
+                        self.repo
+                            .transition(&active, TableSessionStatus::Expired, active.checkout_id)
+                            .await?;
+                        continue;
+                    }
+
+                    return Err(TableSessionServiceError::TableOccupied { session_id: active.id });
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    /// Applies `transition` to the session, `None` if there is no such session.
+    pub async fn transition(
+        &self,
+        id: Uuid,
+        transition: Transition,
+    ) -> Result<Option<TableSessionModel>, TableSessionServiceError> {
+        loop {
+            let Some(session) = self.repo.find_by_id(id).await? else {
+                return Ok(None);
+            };
+
+            let Some((status, checkout_id)) = transition.apply(&session)? else {
+                return Ok(Some(session));
+            };
+
+            match self.repo.transition(&session, status, checkout_id).await {
+                Ok(Some(session)) => return Ok(Some(session)),
+                // it changed since it was read, decide again on the new state
+                Ok(None) => {}
+                Err(e) if e.is_unique_violation() => {
+                    if let Some(active) = self.repo.find_active_by_table(session.table_id).await? {
+                        return Err(TableSessionServiceError::TableOccupied { session_id: active.id });
+                    }
+                }
+                Err(e) => return Err(e.into()),
+            }
+        }
+    }
+
+    /// Reopens a closed session, e.g. one that was closed by mistake.
+    pub async fn activate_session(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<TableSessionModel>, TableSessionServiceError> {
+        self.transition(id, Transition::Reopen).await
     }
 
     pub async fn deactivate_session(
         &self,
-        table_id: Uuid,
+        id: Uuid,
     ) -> Result<Option<TableSessionModel>, TableSessionServiceError> {
-        Ok(self.repo.deactivate(table_id).await?)
+        self.transition(id, Transition::Close).await
     }
 
     pub async fn find_by_id(
@@ -40,18 +182,73 @@ impl TableSessionService {
         Ok(self.repo.find_by_id(id).await?)
     }
 
+    /// Looks the session up on behalf of its guests, which keeps it from
+    /// expiring. Only open sessions pass.
+    pub async fn verify_session(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<TableSessionModel>, TableSessionServiceError> {
+        if let Some(session) = self.repo.touch(id).await? {
+            return Ok(Some(session));
+        }
+
+        match self.repo.find_by_id(id).await? {
+            // still active here means past its expiry but not swept yet
+            Some(session) if session.status == TableSessionStatus::Expired || session.is_active => {
+                Err(TableSessionServiceError::Expired)
+            }
+            Some(_) => Err(TableSessionServiceError::Closed),
+            None => Ok(None),
+        }
+    }
+
+    /// Expires idle sessions `batch_size` at a time until none are left and
+    /// returns how many it expired.
+    pub async fn expire_idle(&self, batch_size: i64) -> Result<u64, TableSessionServiceError> {
+        let mut total = 0;
+
+        loop {
+            let expired = self.repo.expire_idle(batch_size).await?;
+            total += expired;
+
+            if expired < batch_size as u64 {
+                return Ok(total);
+            }
+        }
+    }
+
+    pub async fn history(
+        &self,
+        id: Uuid,
+    ) -> Result<Vec<TableSessionHistoryModel>, TableSessionServiceError> {
+        Ok(self.repo.find_history(id).await?)
+    }
+
+    /// Setting a checkout waits for payment, removing it goes back to ordering.
     pub async fn set_checkout_id(
         &self,
         id: Uuid,
         checkout_id: Option<Uuid>,
     ) -> Result<Option<TableSessionModel>, TableSessionServiceError> {
-        Ok(self.repo.set_checkout_id(id, checkout_id).await?)
+        let transition = match checkout_id {
+            Some(checkout_id) => Transition::Checkout(checkout_id),
+            None => Transition::CancelCheckout,
+        };
+
+        self.transition(id, transition).await
+    }
+
+    pub async fn mark_paid(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<TableSessionModel>, TableSessionServiceError> {
+        self.transition(id, Transition::Pay).await
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{TableSessionRepository, TableSessionService};
+    use super::{TableSessionRepository, TableSessionService, TableSessionServiceError, TableSessionStatus, Transition};
     use crate::database::setup_test_db;
     use uuid::Uuid;
 
@@ -70,6 +267,50 @@ mod tests {
         assert!(result.is_active);
     }
 
+    #[tokio::test]
+    async fn test_create_session_on_occupied_table() {
+        let test_db = setup_test_db().await;
+        let table_id = Uuid::new_v4();
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool));
+        let session = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+
+        let result = service.create_session(table_id, Uuid::new_v4()).await;
+        let Err(TableSessionServiceError::TableOccupied { session_id }) = result else { panic!() };
+        assert_eq!(session_id, session.id);
+
+        // another table is not affected
+        assert!(service.create_session(Uuid::new_v4(), Uuid::new_v4()).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_create_session_concurrently() {
+        let test_db = setup_test_db().await;
+        let table_id = Uuid::new_v4();
+
+        let service = std::sync::Arc::new(TableSessionService::new(TableSessionRepository::new(test_db.pool)));
+
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let service = service.clone();
+                tokio::spawn(async move { service.create_session(table_id, Uuid::new_v4()).await })
+            })
+            .collect();
+
+        let mut created = Vec::new();
+        let mut occupied = Vec::new();
+        for handle in handles {
+            match handle.await.unwrap() {
+                Ok(session) => created.push(session.id),
+                Err(TableSessionServiceError::TableOccupied { session_id }) => occupied.push(session_id),
+                Err(e) => panic!("{e}"),
+            }
+        }
+
+        assert_eq!(created.len(), 1);
+        assert!(occupied.iter().all(|session_id| *session_id == created[0]));
+    }
+
     #[tokio::test]
     async fn test_find_by_id() {
         let test_db = setup_test_db().await;
@@ -111,6 +352,146 @@ mod tests {
         assert!(!deactivated_session.is_active);
     }
 
+    #[tokio::test]
+    async fn test_activate_session() {
+        let test_db = setup_test_db().await;
+        let table_id = Uuid::new_v4();
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool));
+        let session = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+        service.deactivate_session(session.id).await.unwrap();
+
+        let activated = service.activate_session(session.id).await.unwrap().unwrap();
+        assert!(activated.is_active);
+
+        // activating twice is harmless
+        assert!(service.activate_session(session.id).await.unwrap().unwrap().is_active);
+        assert_eq!(service.history(session.id).await.unwrap().len(), 3);
+
+        assert!(service.activate_session(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_activate_refused() {
+        let test_db = setup_test_db().await;
+        let table_id = Uuid::new_v4();
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool));
+
+        let checked_out = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+        service.set_checkout_id(checked_out.id, Some(Uuid::new_v4())).await.unwrap();
+        service.deactivate_session(checked_out.id).await.unwrap();
+
+        let result = service.activate_session(checked_out.id).await;
+        assert!(matches!(result, Err(TableSessionServiceError::CheckedOut)));
+
+        let closed = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+        service.deactivate_session(closed.id).await.unwrap();
+        let active = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+
+        let result = service.activate_session(closed.id).await;
+        let Err(TableSessionServiceError::TableOccupied { session_id }) = result else { panic!() };
+        assert_eq!(session_id, active.id);
+    }
+
+    #[tokio::test]
+    async fn test_lifecycle() {
+        let test_db = setup_test_db().await;
+        let checkout_id = Uuid::new_v4();
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool));
+        let session = service.create_session(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        assert_eq!(session.status, TableSessionStatus::Ordering);
+
+        let session = service.set_checkout_id(session.id, Some(checkout_id)).await.unwrap().unwrap();
+        assert_eq!(session.status, TableSessionStatus::AwaitingPayment);
+
+        let session = service.mark_paid(session.id).await.unwrap().unwrap();
+        assert_eq!(session.status, TableSessionStatus::Paid);
+        assert!(session.is_active);
+
+        let session = service.deactivate_session(session.id).await.unwrap().unwrap();
+        assert_eq!(session.status, TableSessionStatus::Closed);
+        assert_eq!(session.checkout_id, Some(checkout_id));
+        assert!(session.checked_out_at.is_some() && session.paid_at.is_some() && session.closed_at.is_some());
+
+        assert_eq!(service.history(session.id).await.unwrap().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_illegal_transitions() {
+        let test_db = setup_test_db().await;
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool));
+        let session = service.create_session(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+
+        let result = service.mark_paid(session.id).await;
+        assert!(matches!(
+            result,
+            Err(TableSessionServiceError::IllegalTransition { transition: Transition::Pay, status: TableSessionStatus::Ordering })
+        ));
+
+        service.deactivate_session(session.id).await.unwrap();
+
+        let result = service.set_checkout_id(session.id, Some(Uuid::new_v4())).await;
+        let Err(e @ TableSessionServiceError::IllegalTransition { .. }) = result else { panic!() };
+        assert_eq!(e.to_string(), "Cannot set a checkout on a table session that is closed");
+
+        // nothing was recorded for the refused moves
+        assert_eq!(service.history(session.id).await.unwrap().len(), 2);
+    }
+
+    async fn backdate_expiry(pool: &sqlx::PgPool, id: Uuid) {
+        sqlx::query("UPDATE table_sessions SET expires_at = NOW() - INTERVAL '1 minute' WHERE id = $1")
+            .bind(id)
+            .execute(pool)
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_verify_session() {
+        let test_db = setup_test_db().await;
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool.clone()));
+        let session = service.create_session(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+
+        let verified = service.verify_session(session.id).await.unwrap().unwrap();
+        assert!(verified.last_seen_at > session.last_seen_at);
+
+        backdate_expiry(&test_db.pool, session.id).await;
+        let result = service.verify_session(session.id).await;
+        assert!(matches!(result, Err(TableSessionServiceError::Expired)));
+
+        assert_eq!(service.expire_idle(100).await.unwrap(), 1);
+        let result = service.verify_session(session.id).await;
+        assert!(matches!(result, Err(TableSessionServiceError::Expired)));
+
+        // closed by staff is not the same as expired
+        let closed = service.create_session(Uuid::new_v4(), Uuid::new_v4()).await.unwrap();
+        service.deactivate_session(closed.id).await.unwrap();
+        let result = service.verify_session(closed.id).await;
+        assert!(matches!(result, Err(TableSessionServiceError::Closed)));
+
+        assert!(service.verify_session(Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_create_session_replaces_expired() {
+        let test_db = setup_test_db().await;
+        let table_id = Uuid::new_v4();
+
+        let service = TableSessionService::new(TableSessionRepository::new(test_db.pool.clone()));
+        let stale = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+        backdate_expiry(&test_db.pool, stale.id).await;
+
+        let session = service.create_session(table_id, Uuid::new_v4()).await.unwrap();
+        assert_ne!(session.id, stale.id);
+
+        let stale = service.find_by_id(stale.id).await.unwrap().unwrap();
+        assert_eq!(stale.status, TableSessionStatus::Expired);
+    }
+
     #[tokio::test]
     async fn test_deactivate_nonexistent_session() {
         let test_db = setup_test_db().await;
@@ -0,0 +1,141 @@
+use std::sync::Arc;
+
+use tonic::service::Interceptor;
+use tonic::{Request, Status};
+use uuid::Uuid;
+
+use super::{Claims, TokenService};
+
+/// Verifies the bearer token in the `authorization` metadata and stores its
+/// `Claims` in the request extensions. Requests without the metadata pass
+/// through, so each RPC decides whether it needs a caller via `claims_from`.
+#[derive(Clone)]
+pub struct AuthInterceptor {
+    token_service: Arc<TokenService>,
+}
+
+impl AuthInterceptor {
+    pub fn new(token_service: Arc<TokenService>) -> Self {
+        Self { token_service }
+    }
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, Status> {
+        let Some(value) = request.metadata().get("authorization") else {
+            return Ok(request);
+        };
+
+        let token = value
+            .to_str()
+            .ok()
+            .and_then(|value| value.strip_prefix("Bearer "))
+            .ok_or_else(|| Status::unauthenticated("Malformed authorization metadata"))?;
+
+        let claims = self
+            .token_service
+            .decode_jwt(token.to_string())
+            .map_err(|_| Status::unauthenticated("Unauthenticated"))?;
+
+        request.extensions_mut().insert(claims);
+
+        Ok(request)
+    }
+}
+
+/// Claims of the caller authenticated by `AuthInterceptor`.
+#[allow(clippy::result_large_err)] // `Status` is what every RPC returns anyway
+pub fn claims_from<T>(request: &Request<T>) -> Result<Claims, Status> {
+    request
+        .extensions()
+        .get::<Claims>()
+        .cloned()
+        .ok_or_else(|| Status::unauthenticated("Unauthenticated"))
+}
+
+/// Id of the admin authenticated by `AuthInterceptor`.
+#[allow(clippy::result_large_err)]
+pub fn admin_id_from<T>(request: &Request<T>) -> Result<Uuid, Status> {
+    claims_from(request)?
+        .admin_id()
+        .map_err(|_| Status::unauthenticated("Unauthenticated"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_interceptor() -> (Arc<TokenService>, AuthInterceptor) {
+        let token_service = Arc::new(TokenService::new("sigma".to_string(), "secret".to_string()));
+        (token_service.clone(), AuthInterceptor::new(token_service))
+    }
+
+    fn request_with(authorization: &str) -> Request<()> {
+        let mut request = Request::new(());
+        request
+            .metadata_mut()
+            .insert("authorization", authorization.parse().unwrap());
+        request
+    }
+
+    #[test]
+    fn test_valid_token() {
+        let (token_service, mut interceptor) = setup_interceptor();
+        let token = token_service.create_jwt("alice".to_string()).unwrap();
+
+        let request = interceptor.call(request_with(&format!("Bearer {token}"))).unwrap();
+
+        assert_eq!(claims_from(&request).unwrap().sub, "alice");
+    }
+
+    #[test]
+    fn test_admin_id() {
+        let (token_service, mut interceptor) = setup_interceptor();
+        let admin_id = Uuid::new_v4();
+        let token = token_service.create_jwt(admin_id.to_string()).unwrap();
+
+        let request = interceptor.call(request_with(&format!("Bearer {token}"))).unwrap();
+        assert_eq!(admin_id_from(&request).unwrap(), admin_id);
+
+        let token = token_service.create_jwt("alice@gmail.com".to_string()).unwrap();
+        let request = interceptor.call(request_with(&format!("Bearer {token}"))).unwrap();
+        assert_eq!(admin_id_from(&request).unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_no_metadata() {
+        let (_, mut interceptor) = setup_interceptor();
+
+        let request = interceptor.call(Request::new(())).unwrap();
+
+        assert_eq!(claims_from(&request).unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_invalid_token() {
+        let (_, mut interceptor) = setup_interceptor();
+
+        let result = interceptor.call(request_with("Bearer not.a.token"));
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_not_bearer() {
+        let (_, mut interceptor) = setup_interceptor();
+
+        let result = interceptor.call(request_with("Basic YWJjOmRlZg=="));
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+
+    #[test]
+    fn test_mfa_token() {
+        let (token_service, mut interceptor) = setup_interceptor();
+        let token = token_service.create_mfa_jwt("alice".to_string()).unwrap();
+
+        let result = interceptor.call(request_with(&format!("Bearer {token}")));
+
+        assert_eq!(result.unwrap_err().code(), tonic::Code::Unauthenticated);
+    }
+}
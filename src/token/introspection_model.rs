@@ -0,0 +1,56 @@
+use serde::{Deserialize, Serialize};
+
+use super::Claims;
+
+/// RFC 7662 section 2.1
+#[derive(Debug, Clone, Deserialize)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    #[allow(dead_code)]
+    pub token_type_hint: Option<String>,
+}
+
+/// RFC 7662 section 2.2. Only `active` is set for tokens that are not valid.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
+}
+
+impl IntrospectionResponse {
+    pub fn inactive() -> Self {
+        Self::default()
+    }
+}
+
+impl From<Claims> for IntrospectionResponse {
+    fn from(value: Claims) -> Self {
+        Self {
+            active: true,
+            scope: Some(value.scope),
+            token_type: Some("Bearer".to_string()),
+            sub: Some(value.sub),
+            exp: Some(value.exp),
+            iat: Some(value.iat),
+            iss: Some(value.iss),
+            jti: Some(value.jti),
+            roles: Some(value.roles),
+        }
+    }
+}
+
@@ -0,0 +1,236 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+
+use super::SigningKey;
+
+struct RetiredKey {
+    key: Arc<SigningKey>,
+    verify_until: Option<DateTime<Utc>>,
+    /// Listed in the config, as opposed to kept for a grace period after rotating.
+    configured: bool,
+}
+
+impl RetiredKey {
+    fn is_live(&self, now: DateTime<Utc>) -> bool {
+        self.verify_until.is_none_or(|until| now < until)
+    }
+}
+
+/// The current signing key plus retired keys that still verify tokens.
+/// Retired keys with no `verify_until` come from config and stay until removed there.
+pub struct KeyRing {
+    current: Arc<SigningKey>,
+    retired: Vec<RetiredKey>,
+}
+
+impl KeyRing {
+    pub fn new(current: SigningKey, previous: Vec<(SigningKey, Option<DateTime<Utc>>)>) -> Self {
+        let mut ring = Self {
+            current: Arc::new(current),
+            retired: Vec::new(),
+        };
+        ring.set_previous(previous);
+        ring
+    }
+
+    pub fn current(&self) -> Arc<SigningKey> {
+        self.current.clone()
+    }
+
+    pub fn find(&self, kid: &str) -> Option<Arc<SigningKey>> {
+        if self.current.kid() == kid {
+            return Some(self.current.clone());
+        }
+
+        let now = Utc::now();
+        self.retired
+            .iter()
+            .find(|r| r.key.kid() == kid && r.is_live(now))
+            .map(|r| r.key.clone())
+    }
+
+    pub fn verification_keys(&self) -> Vec<Arc<SigningKey>> {
+        let now = Utc::now();
+        std::iter::once(self.current.clone())
+            .chain(
+                self.retired
+                    .iter()
+                    .filter(|r| r.is_live(now))
+                    .map(|r| r.key.clone()),
+            )
+            .collect()
+    }
+
+    /// Makes `current` the signing key and replaces the configured retired keys
+    /// with `previous`, so a key dropped from the config stops verifying right away.
+    /// Outgoing keys keep verifying until their grace period ends.
+    pub fn rotate(
+        &mut self,
+        current: SigningKey,
+        previous: Vec<(SigningKey, Option<DateTime<Utc>>)>,
+        grace_until: DateTime<Utc>,
+    ) {
+        self.retired.retain(|r| !r.configured);
+        self.rotate_current(current, grace_until);
+        self.set_previous(previous);
+    }
+
+    /// Makes `current` the signing key and keeps the configured retired keys. If
+    /// the `kid` changed, the outgoing key keeps verifying until `grace_until`.
+    pub fn rotate_current(&mut self, current: SigningKey, grace_until: DateTime<Utc>) {
+        let outgoing = std::mem::replace(&mut self.current, Arc::new(current));
+
+        let now = Utc::now();
+        self.retired
+            .retain(|r| r.is_live(now) && (r.configured || r.key.kid() != outgoing.kid()));
+
+        let listed = self.retired.iter().any(|r| r.key.kid() == outgoing.kid());
+        if outgoing.kid() != self.current.kid() && !listed {
+            self.retired.push(RetiredKey {
+                key: outgoing,
+                verify_until: Some(grace_until),
+                configured: false,
+            });
+        }
+
+        let current_kid = self.current.kid().to_string();
+        self.retired.retain(|r| r.key.kid() != current_kid);
+    }
+
+    /// Adds the configured retired keys. A key listed there wins over a grace entry
+    /// for the same `kid`.
+    fn set_previous(&mut self, previous: Vec<(SigningKey, Option<DateTime<Utc>>)>) {
+        for (key, verify_until) in previous {
+            self.retired.retain(|r| r.key.kid() != key.kid());
+            self.retired.push(RetiredKey {
+                key: Arc::new(key),
+                verify_until,
+                configured: true,
+            });
+        }
+
+        let current_kid = self.current.kid().to_string();
+        self.retired.retain(|r| r.key.kid() != current_kid);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    fn key(kid: &str) -> SigningKey {
+        SigningKey::hmac(kid.to_string(), kid.as_bytes())
+    }
+
+    #[test]
+    fn test_find_current() {
+        let ring = KeyRing::new(key("a"), vec![]);
+
+        assert_eq!(ring.current().kid(), "a");
+        assert!(ring.find("a").is_some());
+        assert!(ring.find("b").is_none());
+    }
+
+    #[test]
+    fn test_rotate_keeps_outgoing_key_during_grace_period() {
+        let mut ring = KeyRing::new(key("a"), vec![]);
+
+        ring.rotate(key("b"), vec![], Utc::now() + Duration::hours(1));
+
+        assert_eq!(ring.current().kid(), "b");
+        assert!(ring.find("a").is_some());
+        assert_eq!(ring.verification_keys().len(), 2);
+    }
+
+    #[test]
+    fn test_rotate_drops_outgoing_key_after_grace_period() {
+        let mut ring = KeyRing::new(key("a"), vec![]);
+
+        ring.rotate(key("b"), vec![], Utc::now() - Duration::seconds(1));
+
+        assert!(ring.find("a").is_none());
+        assert_eq!(ring.verification_keys().len(), 1);
+    }
+
+    #[test]
+    fn test_rotate_to_same_kid_does_not_retire() {
+        let mut ring = KeyRing::new(key("a"), vec![]);
+
+        ring.rotate(key("a"), vec![], Utc::now() + Duration::hours(1));
+
+        assert_eq!(ring.verification_keys().len(), 1);
+    }
+
+    #[test]
+    fn test_rotate_replaces_configured_previous_keys() {
+        let mut ring = KeyRing::new(key("b"), vec![(key("a"), None)]);
+        assert!(ring.find("a").is_some());
+
+        ring.rotate(key("c"), vec![], Utc::now() + Duration::hours(1));
+
+        assert!(ring.find("a").is_none());
+        assert!(ring.find("b").is_some());
+        assert!(ring.find("c").is_some());
+    }
+
+    #[test]
+    fn test_rotate_drops_previous_key_removed_from_config() {
+        let later = Some(Utc::now() + Duration::days(1));
+        let mut ring = KeyRing::new(key("b"), vec![(key("a"), later), (key("x"), None)]);
+        assert!(ring.find("a").is_some());
+
+        // same signing key, "a" no longer listed
+        ring.rotate(key("b"), vec![(key("x"), None)], Utc::now() + Duration::hours(1));
+
+        assert!(ring.find("a").is_none());
+        assert!(ring.find("x").is_some());
+        assert_eq!(ring.verification_keys().len(), 2);
+    }
+
+    #[test]
+    fn test_rotate_keeps_earlier_outgoing_keys_during_grace_period() {
+        let mut ring = KeyRing::new(key("a"), vec![]);
+
+        ring.rotate(key("b"), vec![], Utc::now() + Duration::hours(1));
+        ring.rotate(key("c"), vec![], Utc::now() + Duration::hours(1));
+
+        assert!(ring.find("a").is_some());
+        assert!(ring.find("b").is_some());
+        assert_eq!(ring.verification_keys().len(), 3);
+    }
+
+    #[test]
+    fn test_rotate_prunes_earlier_outgoing_keys_after_grace_period() {
+        let mut ring = KeyRing::new(key("a"), vec![]);
+
+        ring.rotate(key("b"), vec![], Utc::now() - Duration::seconds(1));
+        ring.rotate(key("c"), vec![], Utc::now() + Duration::hours(1));
+
+        assert!(ring.find("a").is_none());
+        assert!(ring.find("b").is_some());
+    }
+
+    #[test]
+    fn test_rotate_current_keeps_configured_previous_keys() {
+        let mut ring = KeyRing::new(key("b"), vec![(key("a"), None)]);
+
+        ring.rotate_current(key("c"), Utc::now() + Duration::hours(1));
+
+        assert!(ring.find("a").is_some());
+        assert!(ring.find("b").is_some());
+        assert!(ring.find("c").is_some());
+    }
+
+    #[test]
+    fn test_expired_previous_key() {
+        let ring = KeyRing::new(
+            key("b"),
+            vec![(key("a"), Some(Utc::now() - Duration::seconds(1)))],
+        );
+
+        assert!(ring.find("a").is_none());
+    }
+}
@@ -1,3 +1,35 @@
+mod auth_interceptor;
+mod introspection_model;
+mod key_ring;
+mod oauth_client;
+mod one_time_token_model;
+mod one_time_token_repository;
+mod one_time_token_service;
+mod refresh_token_model;
+mod refresh_token_repository;
+mod refresh_token_service;
+mod revocation_list;
+mod revocation_model;
+mod revocation_repository;
+mod revocation_service;
+mod signing_key;
+mod token_rest;
 mod token_service;
 
+pub use auth_interceptor::*;
+pub use introspection_model::*;
+pub use key_ring::*;
+pub use oauth_client::*;
+pub use one_time_token_model::*;
+pub use one_time_token_repository::*;
+pub use one_time_token_service::*;
+pub use refresh_token_model::*;
+pub use refresh_token_repository::*;
+pub use refresh_token_service::*;
+pub use revocation_list::*;
+pub use revocation_model::*;
+pub use revocation_repository::*;
+pub use revocation_service::*;
+pub use signing_key::*;
+pub use token_rest::*;
 pub use token_service::*;
@@ -0,0 +1,59 @@
+use sha2::{Digest, Sha256};
+
+use crate::config::OAuthClientConfig;
+
+/// Resource servers allowed to call the introspection endpoint.
+#[derive(Default)]
+pub struct OAuthClients {
+    clients: Vec<OAuthClientConfig>,
+}
+
+impl OAuthClients {
+    pub fn new(clients: Vec<OAuthClientConfig>) -> Self {
+        Self { clients }
+    }
+
+    pub fn authenticate(&self, client_id: &str, client_secret: &str) -> bool {
+        // compare digests so the comparison time does not depend on the secret
+        let presented = Sha256::digest(client_secret.as_bytes());
+
+        self.clients.iter().any(|client| {
+            client.client_id == client_id
+                && Sha256::digest(client.client_secret.as_bytes()) == presented
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn setup_clients() -> OAuthClients {
+        OAuthClients::new(vec![OAuthClientConfig {
+            client_id: "orders".to_string(),
+            client_secret: "orders-secret".to_string(),
+        }])
+    }
+
+    #[test]
+    fn test_authenticate() {
+        let clients = setup_clients();
+
+        assert!(clients.authenticate("orders", "orders-secret"));
+    }
+
+    #[test]
+    fn test_wrong_secret() {
+        let clients = setup_clients();
+
+        assert!(!clients.authenticate("orders", "wrong"));
+        assert!(!clients.authenticate("payments", "orders-secret"));
+    }
+
+    #[test]
+    fn test_no_clients() {
+        let clients = OAuthClients::default();
+
+        assert!(!clients.authenticate("", ""));
+    }
+}
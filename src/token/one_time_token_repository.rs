@@ -0,0 +1,192 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, query, query_as};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::OneTimeTokenModel;
+
+#[derive(Error, Debug)]
+pub enum OneTimeTokenRepositoryError {
+    #[error("An error occurred with the database: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct OneTimeTokenRepository {
+    pool: PgPool,
+}
+
+impl OneTimeTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        admin_id: Uuid,
+        email: String,
+        purpose: String,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<OneTimeTokenModel, OneTimeTokenRepositoryError> {
+        Ok(query_as!(
+            OneTimeTokenModel,
+            r#"
+            INSERT INTO one_time_tokens (admin_id, email, purpose, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4, $5)
+            RETURNING id, admin_id, email, purpose, token_hash, expires_at, used_at, created_at
+            "#,
+            admin_id,
+            email,
+            purpose,
+            token_hash,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    /// Finds a token that could still be consumed, without using it up.
+    pub async fn find_live(
+        &self,
+        purpose: String,
+        token_hash: String,
+    ) -> Result<Option<OneTimeTokenModel>, OneTimeTokenRepositoryError> {
+        Ok(query_as!(
+            OneTimeTokenModel,
+            r#"
+            SELECT id, admin_id, email, purpose, token_hash, expires_at, used_at, created_at
+            FROM one_time_tokens
+            WHERE purpose = $1 AND token_hash = $2 AND used_at IS NULL AND expires_at > NOW()
+            "#,
+            purpose,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Marks a live token as used. Returns `None` if it does not exist, has
+    /// expired or was already used, so a token can only be consumed once.
+    pub async fn consume(
+        &self,
+        purpose: String,
+        token_hash: String,
+    ) -> Result<Option<OneTimeTokenModel>, OneTimeTokenRepositoryError> {
+        Ok(query_as!(
+            OneTimeTokenModel,
+            r#"
+            UPDATE one_time_tokens
+            SET used_at = NOW()
+            WHERE purpose = $1 AND token_hash = $2 AND used_at IS NULL AND expires_at > NOW()
+            RETURNING id, admin_id, email, purpose, token_hash, expires_at, used_at, created_at
+            "#,
+            purpose,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Invalidates the admin's outstanding tokens for `purpose`.
+    pub async fn invalidate(
+        &self,
+        admin_id: Uuid,
+        purpose: String,
+    ) -> Result<(), OneTimeTokenRepositoryError> {
+        query!(
+            r#"
+            UPDATE one_time_tokens
+            SET used_at = NOW()
+            WHERE admin_id = $1 AND purpose = $2 AND used_at IS NULL
+            "#,
+            admin_id,
+            purpose
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+
+    async fn setup_repository() -> (crate::database::TestDb, OneTimeTokenRepository, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let repo = OneTimeTokenRepository::new(test_db.pool.clone());
+        (test_db, repo, admin.id)
+    }
+
+    #[tokio::test]
+    async fn test_consume_once() {
+        let (_test_db, repo, id) = setup_repository().await;
+        let expires_at = Utc::now() + Duration::hours(1);
+
+        repo.create(id, EMAIL.to_string(), "reset".to_string(), "hash".to_string(), expires_at)
+            .await
+            .unwrap();
+
+        let first = repo.consume("reset".to_string(), "hash".to_string()).await.unwrap();
+        let first = first.unwrap();
+        assert_eq!(first.admin_id, id);
+        assert_eq!(first.email, EMAIL);
+
+        let second = repo.consume("reset".to_string(), "hash".to_string()).await.unwrap();
+        assert!(second.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_consume_wrong_purpose() {
+        let (_test_db, repo, id) = setup_repository().await;
+        let expires_at = Utc::now() + Duration::hours(1);
+
+        repo.create(id, EMAIL.to_string(), "reset".to_string(), "hash".to_string(), expires_at)
+            .await
+            .unwrap();
+
+        let result = repo.consume("verify".to_string(), "hash".to_string()).await.unwrap();
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_consume_expired() {
+        let (_test_db, repo, id) = setup_repository().await;
+        let expires_at = Utc::now() - Duration::seconds(1);
+
+        repo.create(id, EMAIL.to_string(), "reset".to_string(), "hash".to_string(), expires_at)
+            .await
+            .unwrap();
+
+        let result = repo.consume("reset".to_string(), "hash".to_string()).await.unwrap();
+        assert!(result.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_invalidate() {
+        let (_test_db, repo, id) = setup_repository().await;
+        let expires_at = Utc::now() + Duration::hours(1);
+
+        repo.create(id, EMAIL.to_string(), "reset".to_string(), "hash".to_string(), expires_at)
+            .await
+            .unwrap();
+        repo.invalidate(id, "reset".to_string()).await.unwrap();
+
+        let result = repo.consume("reset".to_string(), "hash".to_string()).await.unwrap();
+        assert!(result.is_none());
+    }
+}
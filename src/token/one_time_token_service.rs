@@ -0,0 +1,135 @@
+use chrono::{Duration, Utc};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::utils::{generate_token, hash_token};
+
+use super::{OneTimeTokenModel, OneTimeTokenRepository, OneTimeTokenRepositoryError, TokenPurpose};
+
+#[derive(Error, Debug)]
+pub enum OneTimeTokenServiceError {
+    #[error("{0}")]
+    Repository(#[from] OneTimeTokenRepositoryError),
+
+    #[error("The token is invalid or expired")]
+    InvalidToken,
+}
+
+/// Single-use tokens delivered out of band. Only their hash is stored.
+pub struct OneTimeTokenService {
+    repo: OneTimeTokenRepository,
+}
+
+impl OneTimeTokenService {
+    pub fn new(repo: OneTimeTokenRepository) -> Self {
+        Self { repo }
+    }
+
+    /// Issues a new token sent to `email`, invalidating any earlier one of the
+    /// admin for the same purpose.
+    pub async fn issue(
+        &self,
+        admin_id: Uuid,
+        email: String,
+        purpose: TokenPurpose,
+        ttl: Duration,
+    ) -> Result<String, OneTimeTokenServiceError> {
+        let token = generate_token();
+
+        self.repo
+            .invalidate(admin_id, purpose.as_str().to_string())
+            .await?;
+        self.repo
+            .create(admin_id, email, purpose.as_str().to_string(), hash_token(&token), Utc::now() + ttl)
+            .await?;
+
+        Ok(token)
+    }
+
+    /// Invalidates the admin's outstanding tokens for `purpose`.
+    pub async fn invalidate(&self, admin_id: Uuid, purpose: TokenPurpose) -> Result<(), OneTimeTokenServiceError> {
+        Ok(self.repo.invalidate(admin_id, purpose.as_str().to_string()).await?)
+    }
+
+    /// Like `consume`, but leaves the token usable, for checks that should
+    /// not cost the admin their link when they fail.
+    pub async fn peek(
+        &self,
+        token: String,
+        purpose: TokenPurpose,
+    ) -> Result<OneTimeTokenModel, OneTimeTokenServiceError> {
+        self.repo
+            .find_live(purpose.as_str().to_string(), hash_token(&token))
+            .await?
+            .ok_or(OneTimeTokenServiceError::InvalidToken)
+    }
+
+    /// Returns the used token, which names the admin and the address it was
+    /// sent to.
+    pub async fn consume(
+        &self,
+        token: String,
+        purpose: TokenPurpose,
+    ) -> Result<OneTimeTokenModel, OneTimeTokenServiceError> {
+        self.repo
+            .consume(purpose.as_str().to_string(), hash_token(&token))
+            .await?
+            .ok_or(OneTimeTokenServiceError::InvalidToken)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+
+    async fn setup_service() -> (crate::database::TestDb, OneTimeTokenService, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let service = OneTimeTokenService::new(OneTimeTokenRepository::new(test_db.pool.clone()));
+        (test_db, service, admin.id)
+    }
+
+    #[tokio::test]
+    async fn test_issue_and_consume() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let token = service
+            .issue(id, EMAIL.to_string(), TokenPurpose::PasswordReset, Duration::hours(1))
+            .await
+            .unwrap();
+
+        let consumed = service.consume(token.clone(), TokenPurpose::PasswordReset).await.unwrap();
+        assert_eq!(consumed.admin_id, id);
+        assert_eq!(consumed.email, EMAIL);
+
+        let result = service.consume(token, TokenPurpose::PasswordReset).await;
+        assert!(matches!(result, Err(OneTimeTokenServiceError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_new_token_invalidates_previous() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let first = service
+            .issue(id, EMAIL.to_string(), TokenPurpose::PasswordReset, Duration::hours(1))
+            .await
+            .unwrap();
+        let second = service
+            .issue(id, EMAIL.to_string(), TokenPurpose::PasswordReset, Duration::hours(1))
+            .await
+            .unwrap();
+
+        assert!(service.consume(first, TokenPurpose::PasswordReset).await.is_err());
+        assert!(service.consume(second, TokenPurpose::PasswordReset).await.is_ok());
+    }
+}
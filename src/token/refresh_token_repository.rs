@@ -0,0 +1,188 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, query, query_as};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::RefreshTokenModel;
+
+#[derive(Error, Debug)]
+pub enum RefreshTokenRepositoryError {
+    #[error("An error occurred with the database: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct RefreshTokenRepository {
+    pool: PgPool,
+}
+
+impl RefreshTokenRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn create(
+        &self,
+        family_id: Uuid,
+        admin_id: Uuid,
+        token_hash: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<RefreshTokenModel, RefreshTokenRepositoryError> {
+        Ok(query_as!(
+            RefreshTokenModel,
+            r#"
+            INSERT INTO refresh_tokens (family_id, admin_id, token_hash, expires_at)
+            VALUES ($1, $2, $3, $4)
+            RETURNING id, family_id, admin_id, token_hash, expires_at, rotated_at, revoked_at, created_at
+            "#,
+            family_id,
+            admin_id,
+            token_hash,
+            expires_at
+        )
+        .fetch_one(&self.pool)
+        .await?)
+    }
+
+    pub async fn find_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<Option<RefreshTokenModel>, RefreshTokenRepositoryError> {
+        Ok(query_as!(
+            RefreshTokenModel,
+            r#"
+            SELECT id, family_id, admin_id, token_hash, expires_at, rotated_at, revoked_at, created_at
+            FROM refresh_tokens
+            WHERE token_hash = $1
+            "#,
+            token_hash
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    /// Marks the token as used. Returns `None` if it was already rotated or revoked,
+    /// so two concurrent refreshes with the same token cannot both succeed.
+    pub async fn mark_rotated(
+        &self,
+        id: Uuid,
+    ) -> Result<Option<RefreshTokenModel>, RefreshTokenRepositoryError> {
+        Ok(query_as!(
+            RefreshTokenModel,
+            r#"
+            UPDATE refresh_tokens
+            SET rotated_at = NOW()
+            WHERE id = $1 AND rotated_at IS NULL AND revoked_at IS NULL
+            RETURNING id, family_id, admin_id, token_hash, expires_at, rotated_at, revoked_at, created_at
+            "#,
+            id
+        )
+        .fetch_optional(&self.pool)
+        .await?)
+    }
+
+    pub async fn revoke_family(&self, family_id: Uuid) -> Result<(), RefreshTokenRepositoryError> {
+        query!(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE family_id = $1 AND revoked_at IS NULL
+            "#,
+            family_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_all_for_admin(&self, admin_id: Uuid) -> Result<(), RefreshTokenRepositoryError> {
+        query!(
+            r#"
+            UPDATE refresh_tokens
+            SET revoked_at = NOW()
+            WHERE admin_id = $1 AND revoked_at IS NULL
+            "#,
+            admin_id
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    async fn setup_repository() -> (crate::database::TestDb, RefreshTokenRepository, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create("asdf@gmail.com".to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let repo = RefreshTokenRepository::new(test_db.pool.clone());
+        (test_db, repo, admin.id)
+    }
+
+    #[tokio::test]
+    async fn test_create_and_find_by_hash() {
+        let (_test_db, repo, id) = setup_repository().await;
+        let family_id = Uuid::new_v4();
+
+        let created = repo
+            .create(family_id, id, "hash".to_string(), Utc::now() + Duration::days(1))
+            .await
+            .unwrap();
+
+        let Some(found) = repo.find_by_hash("hash".to_string()).await.unwrap() else {
+            panic!()
+        };
+        assert_eq!(found.id, created.id);
+        assert_eq!(found.family_id, family_id);
+        assert!(found.rotated_at.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_mark_rotated_only_once() {
+        let (_test_db, repo, id) = setup_repository().await;
+
+        let created = repo
+            .create(Uuid::new_v4(), id, "hash".to_string(), Utc::now() + Duration::days(1))
+            .await
+            .unwrap();
+
+        let first = repo.mark_rotated(created.id).await.unwrap();
+        assert!(first.unwrap().rotated_at.is_some());
+
+        let second = repo.mark_rotated(created.id).await.unwrap();
+        assert!(second.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_family() {
+        let (_test_db, repo, id) = setup_repository().await;
+        let family_id = Uuid::new_v4();
+        let expires_at = Utc::now() + Duration::days(1);
+
+        repo.create(family_id, id, "first".to_string(), expires_at).await.unwrap();
+        repo.create(family_id, id, "second".to_string(), expires_at).await.unwrap();
+        repo.create(Uuid::new_v4(), id, "other".to_string(), expires_at).await.unwrap();
+
+        repo.revoke_family(family_id).await.unwrap();
+
+        let first = repo.find_by_hash("first".to_string()).await.unwrap().unwrap();
+        let second = repo.find_by_hash("second".to_string()).await.unwrap().unwrap();
+        let other = repo.find_by_hash("other".to_string()).await.unwrap().unwrap();
+        assert!(first.revoked_at.is_some());
+        assert!(second.revoked_at.is_some());
+        assert!(other.revoked_at.is_none());
+    }
+}
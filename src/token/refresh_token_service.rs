@@ -0,0 +1,207 @@
+use chrono::{Duration, Utc};
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::utils::{generate_token, hash_token};
+
+use super::{RefreshTokenRepository, RefreshTokenRepositoryError};
+
+#[derive(Error, Debug)]
+pub enum RefreshTokenServiceError {
+    #[error("{0}")]
+    Repository(#[from] RefreshTokenRepositoryError),
+
+    #[error("The refresh token is invalid or expired")]
+    InvalidToken,
+
+    #[error("The refresh token was already used, all sessions from this login were revoked")]
+    TokenReused,
+}
+
+/// A freshly issued refresh token. Only its hash is stored.
+pub struct IssuedRefreshToken {
+    pub token: String,
+    pub admin_id: Uuid,
+}
+
+pub struct RefreshTokenService {
+    repo: RefreshTokenRepository,
+    ttl: Duration,
+}
+
+impl RefreshTokenService {
+    pub fn new(repo: RefreshTokenRepository) -> Self {
+        Self {
+            repo,
+            ttl: Duration::days(30),
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Starts a new token family, typically on login.
+    pub async fn issue(&self, admin_id: Uuid) -> Result<IssuedRefreshToken, RefreshTokenServiceError> {
+        self.issue_in_family(Uuid::new_v4(), admin_id).await
+    }
+
+    /// Exchanges a refresh token for a new one in the same family. Presenting a token
+    /// that was already rotated revokes the whole family, since it means the token leaked.
+    pub async fn rotate(&self, token: String) -> Result<IssuedRefreshToken, RefreshTokenServiceError> {
+        let existing = self
+            .repo
+            .find_by_hash(hash_token(&token))
+            .await?
+            .ok_or(RefreshTokenServiceError::InvalidToken)?;
+
+        if existing.revoked_at.is_some() {
+            return Err(RefreshTokenServiceError::InvalidToken);
+        }
+
+        if existing.rotated_at.is_some() {
+            self.repo.revoke_family(existing.family_id).await?;
+            return Err(RefreshTokenServiceError::TokenReused);
+        }
+
+        if existing.expires_at <= Utc::now() {
+            return Err(RefreshTokenServiceError::InvalidToken);
+        }
+
+        // lost a race with a concurrent refresh using the same token
+        if self.repo.mark_rotated(existing.id).await?.is_none() {
+            self.repo.revoke_family(existing.family_id).await?;
+            return Err(RefreshTokenServiceError::TokenReused);
+        }
+
+        self.issue_in_family(existing.family_id, existing.admin_id).await
+    }
+
+    /// Revokes the token's whole family, e.g. on logout. Tokens belonging to a
+    /// different admin are ignored.
+    pub async fn revoke(&self, token: String, admin_id: Uuid) -> Result<(), RefreshTokenServiceError> {
+        let existing = self.repo.find_by_hash(hash_token(&token)).await?;
+
+        if let Some(existing) = existing.filter(|t| t.admin_id == admin_id) {
+            self.repo.revoke_family(existing.family_id).await?;
+        }
+
+        Ok(())
+    }
+
+    async fn issue_in_family(
+        &self,
+        family_id: Uuid,
+        admin_id: Uuid,
+    ) -> Result<IssuedRefreshToken, RefreshTokenServiceError> {
+        let token = generate_token();
+
+        self.repo
+            .create(family_id, admin_id, hash_token(&token), Utc::now() + self.ttl)
+            .await?;
+
+        Ok(IssuedRefreshToken { token, admin_id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    async fn setup_service() -> (crate::database::TestDb, RefreshTokenService, Uuid) {
+        let test_db = setup_test_db().await;
+
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create("asdf@gmail.com".to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let repo = RefreshTokenRepository::new(test_db.pool.clone());
+        (test_db, RefreshTokenService::new(repo), admin.id)
+    }
+
+    #[tokio::test]
+    async fn test_issue_and_rotate() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let issued = service.issue(id).await.unwrap();
+        let rotated = service.rotate(issued.token.clone()).await.unwrap();
+
+        assert_ne!(issued.token, rotated.token);
+        assert_eq!(rotated.admin_id, id);
+    }
+
+    #[tokio::test]
+    async fn test_rotate_unknown_token() {
+        let (_test_db, service, _) = setup_service().await;
+
+        let result = service.rotate("not-a-token".to_string()).await;
+
+        assert!(matches!(result, Err(RefreshTokenServiceError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_reuse_revokes_family() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let issued = service.issue(id).await.unwrap();
+        let rotated = service.rotate(issued.token.clone()).await.unwrap();
+
+        // replaying the first token is detected
+        let result = service.rotate(issued.token).await;
+        assert!(matches!(result, Err(RefreshTokenServiceError::TokenReused)));
+
+        // and the legitimate successor is revoked along with it
+        let result = service.rotate(rotated.token).await;
+        assert!(matches!(result, Err(RefreshTokenServiceError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_reuse_does_not_affect_other_families() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let first = service.issue(id).await.unwrap();
+        let second = service.issue(id).await.unwrap();
+
+        service.rotate(first.token.clone()).await.unwrap();
+        let _ = service.rotate(first.token).await;
+
+        assert!(service.rotate(second.token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_revoke() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let issued = service.issue(id).await.unwrap();
+        service.revoke(issued.token.clone(), id).await.unwrap();
+
+        let result = service.rotate(issued.token).await;
+        assert!(matches!(result, Err(RefreshTokenServiceError::InvalidToken)));
+    }
+
+    #[tokio::test]
+    async fn test_revoke_other_admins_token() {
+        let (_test_db, service, id) = setup_service().await;
+
+        let issued = service.issue(id).await.unwrap();
+        service.revoke(issued.token.clone(), Uuid::new_v4()).await.unwrap();
+
+        assert!(service.rotate(issued.token).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_expired_token() {
+        let (_test_db, service, id) = setup_service().await;
+        let service = service.with_ttl(Duration::seconds(-1));
+
+        let issued = service.issue(id).await.unwrap();
+        let result = service.rotate(issued.token).await;
+
+        assert!(matches!(result, Err(RefreshTokenServiceError::InvalidToken)));
+    }
+}
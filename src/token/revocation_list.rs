@@ -0,0 +1,110 @@
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use super::Claims;
+
+#[derive(Default)]
+struct Entries {
+    /// jti -> exp of the revoked token
+    tokens: HashMap<String, usize>,
+    /// sub -> tokens issued before this timestamp are revoked
+    admins: HashMap<String, usize>,
+}
+
+/// In-memory view of the revocation store, checked on every token decode.
+/// Entries are only ever added, and dropped once the tokens they cover expire.
+#[derive(Default)]
+pub struct RevocationList {
+    entries: RwLock<Entries>,
+}
+
+impl RevocationList {
+    pub fn revoke_token(&self, jti: String, exp: usize) {
+        self.entries.write().unwrap().tokens.insert(jti, exp);
+    }
+
+    pub fn revoke_admin(&self, sub: String, revoked_before: usize) {
+        let mut entries = self.entries.write().unwrap();
+        let cutoff = entries.admins.entry(sub).or_default();
+        *cutoff = (*cutoff).max(revoked_before);
+    }
+
+    /// A token issued in the same second as a revoke-all is still accepted,
+    /// since `iat` only has second precision. Revoke a token that must not be
+    /// used again by its `jti` as well.
+    pub fn is_revoked(&self, claims: &Claims) -> bool {
+        let entries = self.entries.read().unwrap();
+
+        entries.tokens.contains_key(&claims.jti)
+            || entries
+                .admins
+                .get(&claims.sub)
+                .is_some_and(|cutoff| claims.iat < *cutoff)
+    }
+
+    pub fn purge_expired(&self, now: usize) {
+        self.entries.write().unwrap().tokens.retain(|_, exp| *exp > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(sub: &str, jti: &str, iat: usize) -> Claims {
+        Claims {
+            sub: sub.to_string(),
+            exp: iat + 60,
+            iat,
+            iss: "sigma".to_string(),
+            jti: jti.to_string(),
+            scope: "admin".to_string(),
+            roles: Vec::new(),
+            table_id: None,
+            order_id: None,
+        }
+    }
+
+    #[test]
+    fn test_revoke_token() {
+        let list = RevocationList::default();
+
+        list.revoke_token("a".to_string(), 200);
+
+        assert!(list.is_revoked(&claims("alice", "a", 100)));
+        assert!(!list.is_revoked(&claims("alice", "b", 100)));
+    }
+
+    #[test]
+    fn test_revoke_admin() {
+        let list = RevocationList::default();
+
+        list.revoke_admin("alice".to_string(), 100);
+
+        assert!(list.is_revoked(&claims("alice", "a", 99)));
+        assert!(!list.is_revoked(&claims("alice", "b", 100)));
+        assert!(!list.is_revoked(&claims("bob", "c", 99)));
+    }
+
+    #[test]
+    fn test_revoke_admin_keeps_latest_cutoff() {
+        let list = RevocationList::default();
+
+        list.revoke_admin("alice".to_string(), 100);
+        list.revoke_admin("alice".to_string(), 50);
+
+        assert!(list.is_revoked(&claims("alice", "a", 99)));
+    }
+
+    #[test]
+    fn test_purge_expired() {
+        let list = RevocationList::default();
+
+        list.revoke_token("a".to_string(), 100);
+        list.revoke_token("b".to_string(), 300);
+        list.purge_expired(200);
+
+        assert!(!list.is_revoked(&claims("alice", "a", 50)));
+        assert!(list.is_revoked(&claims("alice", "b", 50)));
+    }
+}
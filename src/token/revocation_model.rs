@@ -0,0 +1,17 @@
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+#[derive(Debug, Clone)]
+pub struct RevokedTokenModel {
+    pub jti: String,
+    /// `sub` of the revoked token.
+    pub subject: String,
+    pub expires_at: DateTime<Utc>,
+    pub revoked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone)]
+pub struct AdminRevocationModel {
+    pub admin_id: Uuid,
+    pub revoked_before: DateTime<Utc>,
+}
@@ -0,0 +1,160 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, query, query_as};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{AdminRevocationModel, RevokedTokenModel};
+
+#[derive(Error, Debug)]
+pub enum RevocationRepositoryError {
+    #[error("An error occurred with the database: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+pub struct RevocationRepository {
+    pool: PgPool,
+}
+
+impl RevocationRepository {
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    pub async fn revoke_token(
+        &self,
+        jti: String,
+        subject: String,
+        expires_at: DateTime<Utc>,
+    ) -> Result<(), RevocationRepositoryError> {
+        query!(
+            r#"
+            INSERT INTO revoked_tokens (jti, subject, expires_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT (jti) DO NOTHING
+            "#,
+            jti,
+            subject,
+            expires_at
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn revoke_admin(
+        &self,
+        admin_id: Uuid,
+        revoked_before: DateTime<Utc>,
+    ) -> Result<(), RevocationRepositoryError> {
+        query!(
+            r#"
+            INSERT INTO admin_token_revocations (admin_id, revoked_before)
+            VALUES ($1, $2)
+            ON CONFLICT (admin_id)
+            DO UPDATE SET revoked_before = GREATEST(admin_token_revocations.revoked_before, EXCLUDED.revoked_before)
+            "#,
+            admin_id,
+            revoked_before
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_active_tokens(&self) -> Result<Vec<RevokedTokenModel>, RevocationRepositoryError> {
+        Ok(query_as!(
+            RevokedTokenModel,
+            r#"
+            SELECT jti, subject, expires_at, revoked_at
+            FROM revoked_tokens
+            WHERE expires_at > NOW()
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub async fn find_admin_revocations(&self) -> Result<Vec<AdminRevocationModel>, RevocationRepositoryError> {
+        Ok(query_as!(
+            AdminRevocationModel,
+            r#"
+            SELECT admin_id, revoked_before
+            FROM admin_token_revocations
+            "#
+        )
+        .fetch_all(&self.pool)
+        .await?)
+    }
+
+    pub async fn delete_expired(&self) -> Result<(), RevocationRepositoryError> {
+        query!(
+            r#"
+            DELETE FROM revoked_tokens
+            WHERE expires_at <= NOW()
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use crate::database::setup_test_db;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_revoke_token_and_find_active() {
+        let test_db = setup_test_db().await;
+        let repo = RevocationRepository::new(test_db.pool);
+
+        repo.revoke_token("live".to_string(), "a@b.com".to_string(), Utc::now() + Duration::hours(1))
+            .await
+            .unwrap();
+        repo.revoke_token("dead".to_string(), "a@b.com".to_string(), Utc::now() - Duration::hours(1))
+            .await
+            .unwrap();
+
+        let active = repo.find_active_tokens().await.unwrap();
+        assert_eq!(active.len(), 1);
+        assert_eq!(active[0].jti, "live");
+
+        repo.delete_expired().await.unwrap();
+        assert_eq!(repo.find_active_tokens().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_token_twice() {
+        let test_db = setup_test_db().await;
+        let repo = RevocationRepository::new(test_db.pool);
+        let expires_at = Utc::now() + Duration::hours(1);
+
+        repo.revoke_token("jti".to_string(), "a@b.com".to_string(), expires_at).await.unwrap();
+        repo.revoke_token("jti".to_string(), "a@b.com".to_string(), expires_at).await.unwrap();
+
+        assert_eq!(repo.find_active_tokens().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_revoke_admin_keeps_latest_cutoff() {
+        let test_db = setup_test_db().await;
+        let repo = RevocationRepository::new(test_db.pool);
+        let admin_id = Uuid::new_v4();
+        let later = Utc::now();
+        let earlier = later - Duration::hours(1);
+
+        repo.revoke_admin(admin_id, later).await.unwrap();
+        repo.revoke_admin(admin_id, earlier).await.unwrap();
+
+        let revocations = repo.find_admin_revocations().await.unwrap();
+        assert_eq!(revocations.len(), 1);
+        assert_eq!(revocations[0].revoked_before.timestamp(), later.timestamp());
+    }
+}
@@ -0,0 +1,170 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+use uuid::Uuid;
+
+use super::{
+    Claims, RefreshTokenRepository, RefreshTokenRepositoryError, RevocationList,
+    RevocationRepository, RevocationRepositoryError,
+};
+
+#[derive(Error, Debug)]
+pub enum RevocationServiceError {
+    #[error("{0}")]
+    Repository(#[from] RevocationRepositoryError),
+
+    #[error("{0}")]
+    RefreshTokenRepository(#[from] RefreshTokenRepositoryError),
+}
+
+/// Persists revocations in Postgres and mirrors them into the in-memory
+/// `RevocationList` that `TokenService` checks.
+pub struct RevocationService {
+    repo: RevocationRepository,
+    refresh_token_repo: RefreshTokenRepository,
+    list: Arc<RevocationList>,
+}
+
+impl RevocationService {
+    pub fn new(
+        repo: RevocationRepository,
+        refresh_token_repo: RefreshTokenRepository,
+        list: Arc<RevocationList>,
+    ) -> Self {
+        Self { repo, refresh_token_repo, list }
+    }
+
+    pub async fn revoke_token(&self, claims: &Claims) -> Result<(), RevocationServiceError> {
+        let expires_at = DateTime::from_timestamp(claims.exp as i64, 0).unwrap_or_else(Utc::now);
+
+        self.repo
+            .revoke_token(claims.jti.clone(), claims.sub.clone(), expires_at)
+            .await?;
+        self.list.revoke_token(claims.jti.clone(), claims.exp);
+
+        Ok(())
+    }
+
+    /// Rejects every access token issued to the admin so far and revokes all of
+    /// their refresh tokens.
+    pub async fn revoke_all_for_admin(&self, admin_id: Uuid) -> Result<(), RevocationServiceError> {
+        let now = Utc::now();
+
+        self.repo.revoke_admin(admin_id, now).await?;
+        self.refresh_token_repo.revoke_all_for_admin(admin_id).await?;
+        self.list.revoke_admin(admin_id.to_string(), now.timestamp() as usize);
+
+        Ok(())
+    }
+
+    /// Pulls revocations made by other replicas into the local list.
+    pub async fn sync(&self) -> Result<(), RevocationServiceError> {
+        self.repo.delete_expired().await?;
+
+        for token in self.repo.find_active_tokens().await? {
+            self.list.revoke_token(token.jti, token.expires_at.timestamp() as usize);
+        }
+
+        for revocation in self.repo.find_admin_revocations().await? {
+            self.list
+                .revoke_admin(revocation.admin_id.to_string(), revocation.revoked_before.timestamp() as usize);
+        }
+
+        self.list.purge_expired(Utc::now().timestamp() as usize);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::admin::AdminRepository;
+    use crate::database::setup_test_db;
+    use crate::token::{RefreshTokenService, TokenService, TokenServiceError};
+
+    use super::*;
+
+    const EMAIL: &str = "asdf@gmail.com";
+
+    fn setup_service(pool: sqlx::PgPool, token_service: &TokenService) -> RevocationService {
+        RevocationService::new(
+            RevocationRepository::new(pool.clone()),
+            RefreshTokenRepository::new(pool),
+            token_service.revocations(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_revoke_token() {
+        let test_db = setup_test_db().await;
+        let token_service = TokenService::new("sigma".to_string(), "secret".to_string());
+        let service = setup_service(test_db.pool, &token_service);
+
+        let token = token_service.create_jwt(EMAIL.to_string()).unwrap();
+        let other = token_service.create_jwt(EMAIL.to_string()).unwrap();
+        let claims = token_service.decode_jwt(token.clone()).unwrap();
+
+        service.revoke_token(&claims).await.unwrap();
+
+        assert!(matches!(
+            token_service.decode_jwt(token),
+            Err(TokenServiceError::Revoked)
+        ));
+        assert!(token_service.decode_jwt(other).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_revoke_all_for_admin() {
+        let test_db = setup_test_db().await;
+        let admin = AdminRepository::new(test_db.pool.clone())
+            .create(EMAIL.to_string(), "asdf".to_string(), "HelloWorld123".to_string())
+            .await
+            .unwrap();
+
+        let token_service = TokenService::new("sigma".to_string(), "secret".to_string());
+        let service = setup_service(test_db.pool.clone(), &token_service);
+        let refresh_token_service =
+            RefreshTokenService::new(RefreshTokenRepository::new(test_db.pool.clone()));
+
+        let token = token_service.create_jwt(admin.id.to_string()).unwrap();
+        let other_admin = token_service.create_jwt(Uuid::new_v4().to_string()).unwrap();
+        let refresh_token = refresh_token_service.issue(admin.id).await.unwrap();
+
+        // make sure the token was issued strictly before the cutoff
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+        service.revoke_all_for_admin(admin.id).await.unwrap();
+
+        assert!(matches!(
+            token_service.decode_jwt(token),
+            Err(TokenServiceError::Revoked)
+        ));
+        assert!(token_service.decode_jwt(other_admin).is_ok());
+        assert!(refresh_token_service.rotate(refresh_token.token).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_sync_from_other_replica() {
+        let test_db = setup_test_db().await;
+
+        let token_service = TokenService::new("sigma".to_string(), "secret".to_string());
+        let service = setup_service(test_db.pool.clone(), &token_service);
+
+        // a second replica with its own in-memory list
+        let replica_token_service = TokenService::new("sigma".to_string(), "secret".to_string());
+        let replica_service = setup_service(test_db.pool.clone(), &replica_token_service);
+
+        let token = token_service.create_jwt(EMAIL.to_string()).unwrap();
+        let claims = token_service.decode_jwt(token.clone()).unwrap();
+        service.revoke_token(&claims).await.unwrap();
+
+        assert!(replica_token_service.decode_jwt(token.clone()).is_ok());
+
+        replica_service.sync().await.unwrap();
+
+        assert!(matches!(
+            replica_token_service.decode_jwt(token),
+            Err(TokenServiceError::Revoked)
+        ));
+    }
+}
@@ -0,0 +1,169 @@
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters, Jwk,
+    KeyAlgorithm, OctetKeyPairParameters, PublicKeyUse, RSAKeyParameters,
+};
+use jsonwebtoken::{Algorithm, DecodingKey, EncodingKey};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum SigningKeyError {
+    #[error("Unable to read key file {path}: {source}")]
+    ReadFile {
+        path: String,
+        source: std::io::Error,
+    },
+
+    #[error("Unable to parse {0:?} private key")]
+    InvalidPem(Algorithm),
+
+    #[error("Signing algorithm {0:?} is not supported")]
+    UnsupportedAlgorithm(Algorithm),
+
+    #[error("JWT Error: {0}")]
+    JwtError(#[from] jsonwebtoken::errors::Error),
+}
+
+/// A key used to sign and verify JWTs. Asymmetric keys also carry the
+/// public half as a JWK so it can be published for offline verification.
+pub struct SigningKey {
+    kid: String,
+    algorithm: Algorithm,
+    encoding_key: EncodingKey,
+    decoding_key: DecodingKey,
+    jwk: Option<Jwk>,
+}
+
+impl SigningKey {
+    pub fn hmac(kid: String, secret: &[u8]) -> Self {
+        Self {
+            kid,
+            algorithm: Algorithm::HS256,
+            encoding_key: EncodingKey::from_secret(secret),
+            decoding_key: DecodingKey::from_secret(secret),
+            jwk: None,
+        }
+    }
+
+    pub fn from_pem_file(
+        kid: String,
+        algorithm: Algorithm,
+        path: &str,
+    ) -> Result<Self, SigningKeyError> {
+        let pem = std::fs::read_to_string(path).map_err(|source| SigningKeyError::ReadFile {
+            path: path.to_string(),
+            source,
+        })?;
+
+        Self::from_pem(kid, algorithm, &pem)
+    }
+
+    /// Builds a key from a PKCS#8 private key (PKCS#1 is also accepted for RSA).
+    /// The public key is derived from it, so no separate public key file is needed.
+    pub fn from_pem(kid: String, algorithm: Algorithm, pem: &str) -> Result<Self, SigningKeyError> {
+        let (encoding_key, params) = match algorithm {
+            Algorithm::RS256 => (EncodingKey::from_rsa_pem(pem.as_bytes())?, rsa_params(pem)?),
+            Algorithm::ES256 => (EncodingKey::from_ec_pem(pem.as_bytes())?, ec_params(pem)?),
+            Algorithm::EdDSA => (EncodingKey::from_ed_pem(pem.as_bytes())?, ed_params(pem)?),
+            other => return Err(SigningKeyError::UnsupportedAlgorithm(other)),
+        };
+
+        let jwk = Jwk {
+            common: CommonParameters {
+                public_key_use: Some(PublicKeyUse::Signature),
+                key_algorithm: Some(key_algorithm(algorithm)),
+                key_id: Some(kid.clone()),
+                ..Default::default()
+            },
+            algorithm: params,
+        };
+
+        Ok(Self {
+            kid,
+            algorithm,
+            encoding_key,
+            decoding_key: DecodingKey::from_jwk(&jwk)?,
+            jwk: Some(jwk),
+        })
+    }
+
+    pub fn kid(&self) -> &str {
+        &self.kid
+    }
+
+    pub fn algorithm(&self) -> Algorithm {
+        self.algorithm
+    }
+
+    pub fn encoding_key(&self) -> &EncodingKey {
+        &self.encoding_key
+    }
+
+    pub fn decoding_key(&self) -> &DecodingKey {
+        &self.decoding_key
+    }
+
+    /// The public JWK, or `None` for shared secrets which must never be published.
+    pub fn jwk(&self) -> Option<&Jwk> {
+        self.jwk.as_ref()
+    }
+}
+
+fn key_algorithm(algorithm: Algorithm) -> KeyAlgorithm {
+    match algorithm {
+        Algorithm::RS256 => KeyAlgorithm::RS256,
+        Algorithm::ES256 => KeyAlgorithm::ES256,
+        Algorithm::EdDSA => KeyAlgorithm::EdDSA,
+        _ => KeyAlgorithm::HS256,
+    }
+}
+
+fn rsa_params(pem: &str) -> Result<AlgorithmParameters, SigningKeyError> {
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+    use rsa::pkcs8::DecodePrivateKey;
+    use rsa::traits::PublicKeyParts;
+
+    let key = rsa::RsaPrivateKey::from_pkcs8_pem(pem)
+        .or_else(|_| rsa::RsaPrivateKey::from_pkcs1_pem(pem))
+        .map_err(|_| SigningKeyError::InvalidPem(Algorithm::RS256))?;
+
+    Ok(AlgorithmParameters::RSA(RSAKeyParameters {
+        key_type: Default::default(),
+        n: URL_SAFE_NO_PAD.encode(key.n().to_bytes_be()),
+        e: URL_SAFE_NO_PAD.encode(key.e().to_bytes_be()),
+    }))
+}
+
+fn ec_params(pem: &str) -> Result<AlgorithmParameters, SigningKeyError> {
+    use p256::elliptic_curve::sec1::ToEncodedPoint;
+    use p256::pkcs8::DecodePrivateKey;
+
+    let key = p256::SecretKey::from_pkcs8_pem(pem)
+        .map_err(|_| SigningKeyError::InvalidPem(Algorithm::ES256))?;
+    let point = key.public_key().to_encoded_point(false);
+
+    let (Some(x), Some(y)) = (point.x(), point.y()) else {
+        return Err(SigningKeyError::InvalidPem(Algorithm::ES256));
+    };
+
+    Ok(AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+        key_type: Default::default(),
+        curve: EllipticCurve::P256,
+        x: URL_SAFE_NO_PAD.encode(x),
+        y: URL_SAFE_NO_PAD.encode(y),
+    }))
+}
+
+fn ed_params(pem: &str) -> Result<AlgorithmParameters, SigningKeyError> {
+    use ed25519_dalek::pkcs8::DecodePrivateKey;
+
+    let key = ed25519_dalek::SigningKey::from_pkcs8_pem(pem)
+        .map_err(|_| SigningKeyError::InvalidPem(Algorithm::EdDSA))?;
+
+    Ok(AlgorithmParameters::OctetKeyPair(OctetKeyPairParameters {
+        key_type: Default::default(),
+        curve: EllipticCurve::Ed25519,
+        x: URL_SAFE_NO_PAD.encode(key.verifying_key().to_bytes()),
+    }))
+}
@@ -0,0 +1,89 @@
+use axum::Router;
+use axum::extract::{Form, Json, State};
+use axum::response::{IntoResponse, Response};
+use axum::routing;
+use axum_extra::TypedHeader;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::{Basic, Bearer};
+use hyper::header::WWW_AUTHENTICATE;
+use hyper::StatusCode;
+use jsonwebtoken::jwk::JwkSet;
+use serde_json::json;
+
+use crate::app::RestState;
+use crate::config::Config;
+use crate::role::Permission;
+
+use super::{IntrospectionRequest, IntrospectionResponse};
+
+pub fn router() -> Router<RestState> {
+    Router::new()
+        .route("/.well-known/jwks.json", routing::get(jwks_handler))
+        .route("/admin/keys/rotate", routing::post(rotate_keys_handler))
+        .route("/oauth/introspect", routing::post(introspect_handler))
+}
+
+pub async fn jwks_handler(
+    State(RestState { token_service, .. }): State<RestState>,
+) -> Json<JwkSet> {
+    Json(token_service.jwks())
+}
+
+/// Re-reads the signing keys from configuration so a new key can be rolled out
+/// without restarting. Every replica has to be rotated separately.
+pub async fn rotate_keys_handler(
+    State(RestState { token_service, role_service, .. }): State<RestState>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Result<Response, Response> {
+    let admin_id = token_service
+        .decode_jwt(bearer.token().to_string())
+        .and_then(|claims| claims.admin_id())
+        .map_err(|_| (StatusCode::UNAUTHORIZED, "Unauthenticated".to_string()).into_response())?;
+
+    role_service
+        .require(admin_id, Permission::RotateKeys)
+        .await
+        .map_err(IntoResponse::into_response)?;
+
+    let config = Config::load().map_err(|e| {
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "message": e.to_string() }))).into_response()
+    })?;
+
+    let kid = token_service.reload(&config.token).map_err(|e| {
+        (StatusCode::INTERNAL_SERVER_ERROR, Json(json!({ "message": e.to_string() }))).into_response()
+    })?;
+
+    tracing::info!("Rotated signing keys, current kid is {}", kid);
+
+    Ok((StatusCode::OK, Json(json!({ "kid": kid }))).into_response())
+}
+
+/// RFC 7662 token introspection. Callers authenticate with HTTP Basic using a
+/// client registered under `oauth.clients`.
+pub async fn introspect_handler(
+    State(RestState { token_service, oauth_clients, .. }): State<RestState>,
+    client: Option<TypedHeader<Authorization<Basic>>>,
+    Form(IntrospectionRequest { token, .. }): Form<IntrospectionRequest>,
+) -> Response {
+    let authenticated = client
+        .is_some_and(|TypedHeader(Authorization(basic))| {
+            oauth_clients.authenticate(basic.username(), basic.password())
+        });
+
+    if !authenticated {
+        return (
+            StatusCode::UNAUTHORIZED,
+            [(WWW_AUTHENTICATE, "Basic realm=\"introspect\"")],
+            Json(json!({ "error": "invalid_client" })),
+        )
+            .into_response();
+    }
+
+    // expired, revoked and malformed tokens are all simply inactive
+    let response = match token_service.decode_jwt(token) {
+        Ok(claims) => IntrospectionResponse::from(claims),
+        Err(_) => IntrospectionResponse::inactive(),
+    };
+
+    (StatusCode::OK, Json(response)).into_response()
+}
@@ -1,14 +1,53 @@
+use std::sync::{Arc, RwLock};
+
 use chrono::{Duration, Utc};
-use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
+use jsonwebtoken::jwk::JwkSet;
+use jsonwebtoken::{Algorithm, Header, Validation, decode, decode_header, encode};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::{KeyConfig, TokenConfig};
+
+use super::{KeyRing, RevocationList, SigningKey, SigningKeyError};
+
+const DEFAULT_KID: &str = "default";
+
+/// Scope of access tokens issued to admins after login.
+pub const ADMIN_SCOPE: &str = "admin";
+
+/// Scope of the short-lived token returned by a login that still needs a TOTP code.
+pub const MFA_SCOPE: &str = "mfa";
 
-#[derive(Debug, Serialize, Deserialize)]
+/// Scope of the token returned by a login whose password has expired.
+pub const PASSWORD_CHANGE_SCOPE: &str = "password_change";
+
+/// Scope of the token that lets a customer act within their table session.
+pub const TABLE_SESSION_SCOPE: &str = "table_session";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
     pub sub: String,
     pub exp: usize,
     pub iat: usize,
     pub iss: String,
+    pub jti: String,
+    pub scope: String,
+    #[serde(default)]
+    pub roles: Vec<String>,
+    /// Set on table session tokens, whose subject is the session id.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub table_id: Option<Uuid>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub order_id: Option<Uuid>,
+}
+
+impl Claims {
+    /// The admin the token was issued to. Tokens from before admins had ids
+    /// carry an email here and are rejected.
+    pub fn admin_id(&self) -> Result<Uuid, TokenServiceError> {
+        self.sub.parse().map_err(|_| TokenServiceError::InvalidSubject)
+    }
 }
 
 #[derive(Error, Debug)]
@@ -16,58 +55,265 @@ pub enum TokenServiceError {
     #[error("JWT Error: {0}")]
     JwtError(#[from] jsonwebtoken::errors::Error),
 
+    #[error("{0}")]
+    SigningKey(#[from] SigningKeyError),
+
+    #[error("Token was signed with an unknown key")]
+    UnknownKey,
+
+    #[error("Token has been revoked")]
+    Revoked,
+
+    #[error("Token is not valid for this use")]
+    WrongScope,
+
+    #[error("Token subject is not an admin id")]
+    InvalidSubject,
+
     #[error("An error occurred")]
     OtherError,
 }
 
 pub struct TokenService {
     service_name: String,
-    decoding_key: DecodingKey,
-    encoding_key: EncodingKey,
+    ttl: Duration,
+    mfa_ttl: Duration,
+    password_change_ttl: Duration,
+    table_session_ttl: Duration,
+    grace_period: Duration,
+    keys: RwLock<KeyRing>,
+    revocations: Arc<RevocationList>,
 }
 
 impl TokenService {
     pub fn new(service_name: String, secret: String) -> Self {
-        let secret = secret.as_bytes();
+        Self::with_key(
+            service_name,
+            SigningKey::hmac(DEFAULT_KID.to_string(), secret.as_bytes()),
+        )
+    }
 
+    pub fn with_key(service_name: String, key: SigningKey) -> Self {
         Self {
             service_name,
-            decoding_key: DecodingKey::from_secret(secret),
-            encoding_key: EncodingKey::from_secret(secret),
+            ttl: Duration::hours(24),
+            mfa_ttl: Duration::minutes(5),
+            password_change_ttl: Duration::minutes(10),
+            table_session_ttl: Duration::hours(1),
+            grace_period: Duration::hours(24),
+            keys: RwLock::new(KeyRing::new(key, Vec::new())),
+            revocations: Arc::default(),
         }
     }
 
+    pub fn from_config(config: &TokenConfig) -> Result<Self, TokenServiceError> {
+        let (current, previous) = load_keys(config)?;
+
+        Ok(Self {
+            service_name: config.issuer.clone(),
+            ttl: Duration::seconds(config.ttl_seconds),
+            mfa_ttl: Duration::seconds(config.mfa_ttl_seconds),
+            password_change_ttl: Duration::seconds(config.password_change_ttl_seconds),
+            table_session_ttl: Duration::seconds(config.table_session_ttl_seconds),
+            grace_period: Duration::seconds(config.rotation_grace_seconds),
+            keys: RwLock::new(KeyRing::new(current, previous)),
+            revocations: Arc::default(),
+        })
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    pub fn revocations(&self) -> Arc<RevocationList> {
+        self.revocations.clone()
+    }
+
+    /// Swaps in the keys from `config` without a restart. The outgoing signing key
+    /// keeps verifying for the grace period, retired keys no longer listed in `config`
+    /// stop at once. Returns the `kid` now used for signing.
+    pub fn reload(&self, config: &TokenConfig) -> Result<String, TokenServiceError> {
+        let (current, previous) = load_keys(config)?;
+        let kid = current.kid().to_string();
+
+        self.keys
+            .write()
+            .unwrap()
+            .rotate(current, previous, Utc::now() + self.grace_period);
+
+        Ok(kid)
+    }
+
+    /// Signs with `key` from now on. The configured previous keys keep verifying.
+    pub fn rotate(&self, key: SigningKey) {
+        let mut keys = self.keys.write().unwrap();
+        let grace_until = Utc::now() + self.grace_period;
+        keys.rotate_current(key, grace_until);
+    }
+
     pub fn create_jwt(&self, admin_id: String) -> Result<String, TokenServiceError> {
-        let iat = Utc::now().timestamp() as usize;
+        self.create_jwt_with_roles(admin_id, Vec::new())
+    }
+
+    /// Roles are informational for resource servers, permission checks in this
+    /// service always go to the database.
+    pub fn create_jwt_with_roles(
+        &self,
+        admin_id: String,
+        roles: Vec<String>,
+    ) -> Result<String, TokenServiceError> {
+        self.encode_claims(admin_id, ADMIN_SCOPE, roles, self.ttl)
+    }
 
-        let exp = Utc::now()
-            .checked_add_signed(Duration::hours(24))
+    /// Proves the password was correct, and is only good for finishing the login
+    /// with a TOTP code.
+    pub fn create_mfa_jwt(&self, admin_id: String) -> Result<String, TokenServiceError> {
+        self.encode_claims(admin_id, MFA_SCOPE, Vec::new(), self.mfa_ttl)
+    }
+
+    /// Proves the login succeeded, and is only good for replacing the expired
+    /// password.
+    pub fn create_password_change_jwt(&self, admin_id: String) -> Result<String, TokenServiceError> {
+        self.encode_claims(admin_id, PASSWORD_CHANGE_SCOPE, Vec::new(), self.password_change_ttl)
+    }
+
+    /// Issued to the guests of a table session in place of its bare id, which
+    /// anyone could reuse indefinitely once they learned it.
+    pub fn create_table_session_jwt(
+        &self,
+        session_id: Uuid,
+        table_id: Uuid,
+        order_id: Uuid,
+    ) -> Result<String, TokenServiceError> {
+        let claims = Claims {
+            table_id: Some(table_id),
+            order_id: Some(order_id),
+            ..self.claims(session_id.to_string(), TABLE_SESSION_SCOPE, Vec::new(), self.table_session_ttl)?
+        };
+
+        self.sign(&claims)
+    }
+
+    fn encode_claims(
+        &self,
+        sub: String,
+        scope: &str,
+        roles: Vec<String>,
+        ttl: Duration,
+    ) -> Result<String, TokenServiceError> {
+        self.sign(&self.claims(sub, scope, roles, ttl)?)
+    }
+
+    fn claims(
+        &self,
+        sub: String,
+        scope: &str,
+        roles: Vec<String>,
+        ttl: Duration,
+    ) -> Result<Claims, TokenServiceError> {
+        let now = Utc::now();
+        let iat = now.timestamp() as usize;
+
+        let exp = now
+            .checked_add_signed(ttl)
             .ok_or(TokenServiceError::OtherError)?
             .timestamp() as usize;
 
-        let claims = Claims {
+        Ok(Claims {
             iss: self.service_name.clone(),
-            sub: admin_id,
+            sub,
             iat,
             exp,
-        };
+            jti: Uuid::new_v4().to_string(),
+            scope: scope.to_string(),
+            roles,
+            table_id: None,
+            order_id: None,
+        })
+    }
 
-        Ok(encode(&Header::default(), &claims, &self.encoding_key)?)
+    fn sign(&self, claims: &Claims) -> Result<String, TokenServiceError> {
+        let key = self.keys.read().unwrap().current();
+
+        let mut header = Header::new(key.algorithm());
+        header.kid = Some(key.kid().to_string());
+
+        Ok(encode(&header, claims, key.encoding_key())?)
     }
 
+    /// Decodes an admin access token.
     pub fn decode_jwt(&self, token: String) -> Result<Claims, TokenServiceError> {
-        let mut validation = Validation::default();
+        self.decode_scoped(token, ADMIN_SCOPE)
+    }
+
+    pub fn decode_scoped(&self, token: String, scope: &str) -> Result<Claims, TokenServiceError> {
+        let header = decode_header(&token)?;
+
+        // tokens issued before `kid` was added are checked against the current key
+        let key = {
+            let keys = self.keys.read().unwrap();
+            match header.kid {
+                Some(kid) => keys.find(&kid).ok_or(TokenServiceError::UnknownKey)?,
+                None => keys.current(),
+            }
+        };
+
+        let mut validation = Validation::new(key.algorithm());
         validation.validate_exp = true;
+        validation.set_issuer(&[&self.service_name]);
+
+        let token = decode::<Claims>(&token, key.decoding_key(), &validation)?;
+
+        if token.claims.scope != scope {
+            return Err(TokenServiceError::WrongScope);
+        }
 
-        let token = decode::<Claims>(&token, &self.decoding_key, &validation)?;
+        if self.revocations.is_revoked(&token.claims) {
+            return Err(TokenServiceError::Revoked);
+        }
 
         Ok(token.claims)
     }
+
+    /// Public keys for offline verification. Shared secrets are never published.
+    pub fn jwks(&self) -> JwkSet {
+        let keys = self.keys.read().unwrap().verification_keys();
+
+        JwkSet {
+            keys: keys.iter().filter_map(|key| key.jwk().cloned()).collect(),
+        }
+    }
+}
+
+fn load_key(config: &KeyConfig) -> Result<SigningKey, TokenServiceError> {
+    let kid = config.kid.clone();
+
+    Ok(match (config.algorithm, &config.private_key_path) {
+        (Algorithm::HS256, _) => SigningKey::hmac(kid, config.secret.as_bytes()),
+        (algorithm, Some(path)) => SigningKey::from_pem_file(kid, algorithm, path)?,
+        (algorithm, None) => return Err(SigningKeyError::UnsupportedAlgorithm(algorithm).into()),
+    })
+}
+
+type LoadedKeys = (SigningKey, Vec<(SigningKey, Option<chrono::DateTime<Utc>>)>);
+
+fn load_keys(config: &TokenConfig) -> Result<LoadedKeys, TokenServiceError> {
+    let current = load_key(&config.current_key())?;
+
+    let previous = config
+        .previous_keys
+        .iter()
+        .map(|key| Ok((load_key(key)?, key.verify_until)))
+        .collect::<Result<Vec<_>, TokenServiceError>>()?;
+
+    Ok((current, previous))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use password_hash::rand_core::OsRng;
     use std::thread;
     use std::time::Duration as StdDuration;
 
@@ -108,6 +354,80 @@ mod tests {
         assert!(claims.exp > now);
     }
 
+    #[test]
+    fn test_admin_id() {
+        let service = setup_service();
+        let admin_id = Uuid::new_v4();
+
+        let claims = service.decode_jwt(service.create_jwt(admin_id.to_string()).unwrap()).unwrap();
+        assert_eq!(claims.admin_id().unwrap(), admin_id);
+
+        // tokens issued before admins had ids
+        let claims = service.decode_jwt(service.create_jwt("alice@gmail.com".to_string()).unwrap()).unwrap();
+        assert!(matches!(claims.admin_id(), Err(TokenServiceError::InvalidSubject)));
+    }
+
+    #[test]
+    fn test_roles_in_claims() {
+        let service = setup_service();
+
+        let token = service
+            .create_jwt_with_roles("alice".to_string(), vec!["waiter".to_string()])
+            .unwrap();
+        let claims = service.decode_jwt(token).unwrap();
+
+        assert_eq!(claims.scope, ADMIN_SCOPE);
+        assert_eq!(claims.roles, vec!["waiter".to_string()]);
+    }
+
+    #[test]
+    fn test_mfa_token_scope() {
+        let service = setup_service();
+
+        let token = service.create_mfa_jwt("alice".to_string()).unwrap();
+
+        assert!(matches!(
+            service.decode_jwt(token.clone()),
+            Err(TokenServiceError::WrongScope)
+        ));
+
+        let claims = service.decode_scoped(token, MFA_SCOPE).unwrap();
+        assert_eq!(claims.sub, "alice");
+        assert_eq!(claims.exp - claims.iat, 5 * 60);
+    }
+
+    #[test]
+    fn test_password_change_token_scope() {
+        let service = setup_service();
+
+        let token = service.create_password_change_jwt("alice".to_string()).unwrap();
+
+        assert!(service.decode_jwt(token.clone()).is_err());
+        assert!(service.decode_scoped(token.clone(), MFA_SCOPE).is_err());
+
+        let claims = service.decode_scoped(token, PASSWORD_CHANGE_SCOPE).unwrap();
+        assert_eq!(claims.sub, "alice");
+    }
+
+    #[test]
+    fn test_table_session_token() {
+        let service = setup_service();
+        let (session_id, table_id, order_id) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+
+        let token = service.create_table_session_jwt(session_id, table_id, order_id).unwrap();
+
+        assert!(service.decode_jwt(token.clone()).is_err());
+
+        let claims = service.decode_scoped(token, TABLE_SESSION_SCOPE).unwrap();
+        assert_eq!(claims.sub, session_id.to_string());
+        assert_eq!(claims.table_id, Some(table_id));
+        assert_eq!(claims.order_id, Some(order_id));
+
+        // admin tokens do not carry the table claims at all
+        let token = service.create_jwt("alice".to_string()).unwrap();
+        assert!(service.decode_jwt(token).unwrap().table_id.is_none());
+    }
+
     #[test]
     fn test_invalid_token() {
         let service = setup_service();
@@ -164,4 +484,168 @@ mod tests {
 
         assert!(claims1.iat < claims2.iat);
     }
+
+    #[test]
+    fn test_configured_ttl() {
+        let service = setup_service().with_ttl(Duration::minutes(5));
+
+        let token = service.create_jwt("test".to_string()).unwrap();
+        let claims = service.decode_jwt(token).unwrap();
+
+        assert_eq!(claims.exp - claims.iat, 5 * 60);
+    }
+
+    #[test]
+    fn test_rs256_roundtrip() {
+        use rsa::pkcs8::{EncodePrivateKey, LineEnding};
+
+        let pem = rsa::RsaPrivateKey::new(&mut OsRng, 2048)
+            .unwrap()
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+
+        assert_asymmetric_roundtrip(Algorithm::RS256, &pem);
+    }
+
+    #[test]
+    fn test_es256_roundtrip() {
+        use p256::pkcs8::{EncodePrivateKey, LineEnding};
+
+        let pem = p256::SecretKey::random(&mut OsRng)
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+
+        assert_asymmetric_roundtrip(Algorithm::ES256, &pem);
+    }
+
+    #[test]
+    fn test_eddsa_roundtrip() {
+        use ed25519_dalek::pkcs8::{EncodePrivateKey, spki::der::pem::LineEnding};
+
+        let pem = ed25519_dalek::SigningKey::generate(&mut OsRng)
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+
+        assert_asymmetric_roundtrip(Algorithm::EdDSA, &pem);
+    }
+
+    #[test]
+    fn test_hmac_jwks_is_empty() {
+        let service = setup_service();
+
+        assert!(service.jwks().keys.is_empty());
+    }
+
+    #[test]
+    fn test_unique_jti() {
+        let service = setup_service();
+
+        let claims1 = service.decode_jwt(service.create_jwt("test".to_string()).unwrap()).unwrap();
+        let claims2 = service.decode_jwt(service.create_jwt("test".to_string()).unwrap()).unwrap();
+
+        assert_ne!(claims1.jti, claims2.jti);
+    }
+
+    #[test]
+    fn test_revoked_token() {
+        let service = setup_service();
+        let token = service.create_jwt("test".to_string()).unwrap();
+        let claims = service.decode_jwt(token.clone()).unwrap();
+
+        service.revocations().revoke_token(claims.jti, claims.exp);
+
+        assert!(matches!(
+            service.decode_jwt(token),
+            Err(TokenServiceError::Revoked)
+        ));
+    }
+
+    #[test]
+    fn test_kid_header() {
+        let service = setup_service();
+
+        let token = service.create_jwt("test".to_string()).unwrap();
+
+        assert_eq!(
+            jsonwebtoken::decode_header(&token).unwrap().kid.as_deref(),
+            Some(DEFAULT_KID)
+        );
+    }
+
+    #[test]
+    fn test_rotated_key_still_verifies() {
+        let service = setup_service();
+        let old_token = service.create_jwt("test".to_string()).unwrap();
+
+        service.rotate(SigningKey::hmac("next".to_string(), b"next-secret"));
+        let new_token = service.create_jwt("test".to_string()).unwrap();
+
+        assert_eq!(jsonwebtoken::decode_header(&new_token).unwrap().kid.as_deref(), Some("next"));
+        assert!(service.decode_jwt(old_token).is_ok());
+        assert!(service.decode_jwt(new_token).is_ok());
+    }
+
+    #[test]
+    fn test_rotated_key_expires_after_grace_period() {
+        let mut service = setup_service();
+        service.grace_period = Duration::zero();
+        let old_token = service.create_jwt("test".to_string()).unwrap();
+
+        service.rotate(SigningKey::hmac("next".to_string(), b"next-secret"));
+
+        assert!(matches!(
+            service.decode_jwt(old_token),
+            Err(TokenServiceError::UnknownKey)
+        ));
+    }
+
+    #[test]
+    fn test_unknown_kid() {
+        let service = setup_service();
+        let other_service = TokenService::with_key(
+            SERVICE_NAME.to_string(),
+            SigningKey::hmac("other".to_string(), b"test-secret"),
+        );
+
+        let token = other_service.create_jwt("test".to_string()).unwrap();
+
+        assert!(matches!(
+            service.decode_jwt(token),
+            Err(TokenServiceError::UnknownKey)
+        ));
+    }
+
+    fn assert_asymmetric_roundtrip(algorithm: Algorithm, pem: &str) {
+        let key = SigningKey::from_pem("test".to_string(), algorithm, pem).unwrap();
+        let service = TokenService::with_key(SERVICE_NAME.to_string(), key);
+
+        let token = service.create_jwt("carol".to_string()).unwrap();
+        assert_eq!(jsonwebtoken::decode_header(&token).unwrap().alg, algorithm);
+
+        // a verifier holding only the published JWK accepts the token
+        let jwks = service.jwks();
+        assert_eq!(jwks.keys.len(), 1);
+        let decoding_key = jsonwebtoken::DecodingKey::from_jwk(&jwks.keys[0]).unwrap();
+        let mut validation = Validation::new(algorithm);
+        validation.set_issuer(&[SERVICE_NAME]);
+        let claims = decode::<Claims>(&token, &decoding_key, &validation).unwrap().claims;
+        assert_eq!(claims.sub, "carol");
+
+        // and a token signed with a shared secret is rejected
+        let hmac_token = setup_service().create_jwt("carol".to_string()).unwrap();
+        assert!(service.decode_jwt(hmac_token).is_err());
+    }
+
+    #[test]
+    fn test_different_issuer() {
+        let service = setup_service();
+        let other_service = TokenService::new("other".to_string(), "test-secret".to_string());
+
+        let token = other_service.create_jwt("test".to_string()).unwrap();
+
+        assert!(matches!(
+            service.decode_jwt(token),
+            Err(TokenServiceError::JwtError(..))
+        ));
+    }
 }
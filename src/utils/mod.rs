@@ -1,3 +1,7 @@
+mod secret_token;
+
+pub use secret_token::*;
+
 use axum::extract::rejection::JsonRejection;
 use axum::extract::{FromRequest, Json, Request};
 use axum::response::{IntoResponse, Response};